@@ -0,0 +1,87 @@
+use proc_macro_error::{abort, abort_call_site};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Field, GenericArgument, Index, PathArguments, Type,
+};
+
+pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => abort_call_site!("ActionSet can only be derived for structs"),
+    };
+    if fields.is_empty() {
+        abort_call_site!("ActionSet can only be derived for structs with at least one field");
+    }
+    let types = fields.iter().map(action_type).collect::<Vec<_>>();
+    let members = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => quote! { #ident },
+            None => {
+                let index = Index::from(i);
+                quote! { #index }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Paths are absolute so that only the derive macro needs to be in scope.
+    let expanded = quote! {
+        #[allow(unused_qualifications)]
+        impl #impl_generics ::entity_gym_rs::agent::ActionSet for #name #ty_generics #where_clause {
+            type Output = Self;
+            type Keyed = (#(<#types as ::entity_gym_rs::agent::ActionSet>::Keyed,)*);
+
+            fn requests() -> Vec<::entity_gym_rs::agent::ActionRequest<'static>> {
+                let mut requests = vec![];
+                #(requests.extend(<#types as ::entity_gym_rs::agent::ActionSet>::requests());)*
+                requests
+            }
+
+            fn from_raw(raw: Vec<Vec<u64>>) -> Self {
+                let mut raw = raw.into_iter();
+                #name {
+                    #(#members: <#types as ::entity_gym_rs::agent::ActionSet>::from_raw(vec![raw.next().expect("Missing actions")]),)*
+                }
+            }
+
+            fn key_by_id(output: Self, obs: &::entity_gym_rs::agent::Obs) -> Self::Keyed {
+                (#(<#types as ::entity_gym_rs::agent::ActionSet>::key_by_id(output.#members, obs),)*)
+            }
+        }
+    };
+
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// Returns the action type of a field, given by an `#[action(Type)]` attribute or inferred from a `Vec<Type>` field.
+fn action_type(field: &Field) -> Type {
+    for attr in &field.attrs {
+        if attr.path.is_ident("action") {
+            return match attr.parse_args::<Type>() {
+                Ok(ty) => ty,
+                Err(err) => abort!(err.span(), "{}", err),
+            };
+        }
+    }
+    if let Type::Path(path) = &field.ty {
+        let segment = path.path.segments.last().unwrap();
+        if segment.ident == "Vec" {
+            if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let Some(GenericArgument::Type(ty)) = args.args.first() {
+                    return ty.clone();
+                }
+            }
+        }
+    }
+    abort!(
+        field.ty.span(),
+        "Expected a `Vec` of actions, use `#[action(Type)]` to specify the action type of other fields, e.g. `#[action(Select<Attack>)]`"
+    )
+}
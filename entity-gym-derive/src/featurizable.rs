@@ -1,8 +1,14 @@
 use proc_macro2::TokenStream;
 use proc_macro_error::abort;
-use quote::quote;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, Data, DeriveInput, Expr, ExprLit, Field, Ident, Lit, Type, TypePath};
+use syn::{
+    parenthesized, parse_macro_input, token, Data, DeriveInput, Expr, ExprLit, ExprParen,
+    ExprUnary, Field, Fields, GenericArgument, Ident, Index, Lit, PathArguments, Token, Type,
+    TypePath, UnOp,
+};
 
 pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Parse the input tokens into a syntax tree
@@ -46,79 +52,411 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
 fn field_names(data: &Data, name: &Ident) -> (TokenStream, TokenStream, TokenStream) {
     match data {
-        Data::Struct(data) => match &data.fields {
-            syn::Fields::Named(fields) => {
-                let mut names = vec![];
-                let mut counts = vec![];
-                let mut features = vec![];
-                for field in fields.named.iter() {
-                    let (name, count) = feature_name_field(field);
-                    names.push(name);
-                    counts.push(count);
-                    features.push(featurize_field(field))
-                }
-
-                (
-                    quote! { #(#counts)+* },
-                    quote! { #(#names)* },
-                    quote! { #(#features);* },
-                )
-            }
-            syn::Fields::Unnamed(_fields) => todo!(),
-            // syn::Fields::Unnamed(fields) => {
-            //     let mut names = vec![];
-            //     for i in 0..fields.unnamed.len() {
-            //         let name = format!("_{}", i);
-            //         names.push(quote! { #name });
-            //     }
-            //     let count = fields.unnamed.len();
-            //     (quote! { #count }, quote! { &[#(#names),*] })
-            // }
-            syn::Fields::Unit => (quote! { 0 }, quote! { (); }, quote! { (); }),
-        },
+        Data::Struct(data) => {
+            let (counts, names, features) =
+                fields(&data.fields, "", |i, field| match &field.ident {
+                    Some(ident) => quote! { self.#ident },
+                    None => {
+                        let index = Index::from(i);
+                        quote! { self.#index }
+                    }
+                });
+            (
+                quote! { 0usize #(+ #counts)* },
+                quote! { #(#names)* },
+                quote! { #(#features;)* },
+            )
+        }
         Data::Enum(data) => {
+            // Enums are featurized as a one-hot encoding of the variant, followed by the
+            // concatenated payloads of all variants. Payloads of inactive variants are zero.
             let mut names = vec![];
-            let count = data.variants.len();
-            let mut features = vec![];
+            let mut counts = vec![];
+            let mut payloads = vec![];
             for variant in &data.variants {
-                if !variant.fields.is_empty() {
-                    abort!(
-                        variant.span(),
-                        "Enum variants with fields are not supported",
-                    );
-                }
                 let field_name = format!("is_{}", variant.ident);
                 names.push(quote! { names.push(#field_name.to_string()); });
+            }
+            for variant in &data.variants {
+                let (variant_counts, variant_names, variant_features) =
+                    fields(&variant.fields, &format!("{}.", variant.ident), |i, _| {
+                        let binding = binding(i);
+                        quote! { (*#binding) }
+                    });
+                names.extend(variant_names);
+                counts.push(quote! { (0usize #(+ #variant_counts)*) });
+                payloads.push(variant_features);
+            }
+            let num_variants = data.variants.len();
+            let mut arms = vec![];
+            for (index, variant) in data.variants.iter().enumerate() {
                 let variant_ident = &variant.ident;
-                features.push(quote! {
-                    buffer.push(if let #name::#variant_ident = self {
-                        1.0
-                    } else {
-                        0.0
-                    })
-                })
+                let bindings = (0..variant.fields.len()).map(binding).collect::<Vec<_>>();
+                let pattern = match &variant.fields {
+                    Fields::Named(fields) => {
+                        let idents = fields.named.iter().map(|f| f.ident.as_ref().unwrap());
+                        quote! { #name::#variant_ident { #(#idents: #bindings),* } }
+                    }
+                    Fields::Unnamed(_) => quote! { #name::#variant_ident(#(#bindings),*) },
+                    Fields::Unit => quote! { #name::#variant_ident },
+                };
+                let one_hot = (0..num_variants).map(|i| if i == index { 1.0f32 } else { 0.0f32 });
+                let payload =
+                    payloads
+                        .iter()
+                        .zip(counts.iter())
+                        .enumerate()
+                        .map(|(i, (features, count))| {
+                            if i == index {
+                                quote! { #(#features;)* }
+                            } else {
+                                quote! { buffer.extend(std::iter::repeat(0.0).take(#count)); }
+                            }
+                        });
+                arms.push(quote! {
+                    #pattern => {
+                        #(buffer.push(#one_hot);)*
+                        #(#payload)*
+                    }
+                });
             }
 
             (
-                quote! { #count },
+                quote! { #num_variants #(+ #counts)* },
                 quote! { #(#names)* },
-                quote! { #(#features);* },
+                quote! {
+                    match self {
+                        #(#arms)*
+                    }
+                },
             )
         }
         Data::Union(_) => unimplemented!("Union not supported, must be struct"),
     }
 }
 
-fn featurize_field(field: &Field) -> TokenStream {
-    let ident = field.ident.as_ref().unwrap();
-    featurize_type(&field.ty, quote! { self.#ident })
+/// Returns the feature counts, feature names and featurization code for each field.
+/// Unnamed fields are named by their position.
+fn fields(
+    fields: &Fields,
+    prefix: &str,
+    accessor: impl Fn(usize, &Field) -> TokenStream,
+) -> (Vec<TokenStream>, Vec<TokenStream>, Vec<TokenStream>) {
+    let mut counts = vec![];
+    let mut names = vec![];
+    let mut features = vec![];
+    for (i, field) in fields.iter().enumerate() {
+        let attrs = FieldAttrs::parse(field);
+        if attrs.skip {
+            continue;
+        }
+        let field_name = match (&attrs.rename, &field.ident) {
+            (Some(rename), _) => format!("{}{}", prefix, rename),
+            (None, Some(ident)) => format!("{}{}", prefix, ident),
+            (None, None) => format!("{}{}", prefix, i),
+        };
+        let (name, count, featurize) = if let Some(encoding) = attrs.categorical(field) {
+            encoding.encode(&field_name, accessor(i, field))
+        } else {
+            let (name, count) = feature_name_type(&field.ty, &field_name);
+            let featurize = featurize_type(&field.ty, accessor(i, field), &attrs);
+            (name, count, featurize)
+        };
+        names.push(name);
+        counts.push(count);
+        features.push(featurize);
+    }
+    (counts, names, features)
+}
+
+/// Encodes a numeric field as a one-hot vector.
+enum Categorical<'a> {
+    /// One feature for each of the integer values `0..n`.
+    OneHot(&'a Expr),
+    /// One feature for each of the intervals delimited by the ascending boundaries.
+    Buckets(&'a [Expr]),
+}
+
+impl Categorical<'_> {
+    /// Returns the feature names, feature count and featurization code for the field.
+    fn encode(
+        &self,
+        prefix: &str,
+        accessor: TokenStream,
+    ) -> (TokenStream, TokenStream, TokenStream) {
+        match self {
+            Categorical::OneHot(n) => (
+                quote! {
+                    for i in 0..(#n) as usize {
+                        names.push(format!("{}.is_{}", #prefix, i));
+                    }
+                },
+                quote! { ((#n) as usize) },
+                quote! {
+                    {
+                        let value = (#accessor) as i64;
+                        for i in 0..(#n) as i64 {
+                            buffer.push(if value == i { 1.0 } else { 0.0 });
+                        }
+                    }
+                },
+            ),
+            Categorical::Buckets(boundaries) => {
+                let len = boundaries.len();
+                (
+                    quote! {
+                        {
+                            let boundaries: [f32; #len] = [#((#boundaries) as f32),*];
+                            names.push(format!("{}.lt_{}", #prefix, boundaries[0]));
+                            for w in boundaries.windows(2) {
+                                names.push(format!("{}.{}_to_{}", #prefix, w[0], w[1]));
+                            }
+                            names.push(format!("{}.ge_{}", #prefix, boundaries[#len - 1]));
+                        }
+                    },
+                    quote! { (#len + 1) },
+                    quote! {
+                        {
+                            let boundaries: [f32; #len] = [#((#boundaries) as f32),*];
+                            let value = (#accessor) as f32;
+                            let bucket = boundaries.iter().filter(|&&b| value >= b).count();
+                            for i in 0..=#len {
+                                buffer.push(if i == bucket { 1.0 } else { 0.0 });
+                            }
+                        }
+                    },
+                )
+            }
+        }
+    }
+}
+
+/// Options set with `#[featurize(...)]` attributes on a field.
+#[derive(Default)]
+struct FieldAttrs {
+    skip: bool,
+    rename: Option<String>,
+    scale: Option<Expr>,
+    offset: Option<Expr>,
+    clamp: Option<(Expr, Expr)>,
+    one_hot: Option<Expr>,
+    buckets: Option<Vec<Expr>>,
+}
+
+enum AttrValue {
+    None,
+    Assign(Box<Expr>),
+    List(Vec<Expr>),
+}
+
+struct AttrArg {
+    name: Ident,
+    value: AttrValue,
+}
+
+impl Parse for AttrArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            AttrValue::Assign(Box::new(input.parse()?))
+        } else if input.peek(token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            let args = content.parse_terminated::<Expr, Token![,]>(Expr::parse)?;
+            AttrValue::List(args.into_iter().collect())
+        } else {
+            AttrValue::None
+        };
+        Ok(AttrArg { name, value })
+    }
+}
+
+impl FieldAttrs {
+    fn parse(field: &Field) -> FieldAttrs {
+        let mut attrs = FieldAttrs::default();
+        for attr in field.attrs.iter().filter(|a| a.path.is_ident("featurize")) {
+            let args =
+                match attr.parse_args_with(Punctuated::<AttrArg, Token![,]>::parse_terminated) {
+                    Ok(args) => args,
+                    Err(err) => abort!(err.span(), "{}", err),
+                };
+            for arg in args {
+                match (arg.name.to_string().as_str(), arg.value) {
+                    ("skip", AttrValue::None) => attrs.skip = true,
+                    ("rename", AttrValue::Assign(expr)) => match *expr {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(name),
+                            ..
+                        }) => attrs.rename = Some(name.value()),
+                        _ => abort!(arg.name.span(), "Expected `rename = \"name\"`"),
+                    },
+                    ("scale", AttrValue::Assign(expr)) => attrs.scale = Some(*expr),
+                    ("offset", AttrValue::Assign(expr)) => attrs.offset = Some(*expr),
+                    ("clamp", AttrValue::List(bounds)) if bounds.len() == 2 => {
+                        let mut bounds = bounds.into_iter();
+                        attrs.clamp = Some((bounds.next().unwrap(), bounds.next().unwrap()));
+                    }
+                    ("one_hot", AttrValue::Assign(expr)) => attrs.one_hot = Some(*expr),
+                    ("buckets", AttrValue::List(boundaries)) if !boundaries.is_empty() => {
+                        attrs.buckets = Some(boundaries)
+                    }
+                    ("skip", _) => abort!(arg.name.span(), "Expected `skip`"),
+                    ("rename", _) => abort!(arg.name.span(), "Expected `rename = \"name\"`"),
+                    ("scale", _) => abort!(arg.name.span(), "Expected `scale = <factor>`"),
+                    ("offset", _) => abort!(arg.name.span(), "Expected `offset = <offset>`"),
+                    ("clamp", _) => abort!(arg.name.span(), "Expected `clamp(<min>, <max>)`"),
+                    ("one_hot", _) => abort!(arg.name.span(), "Expected `one_hot = <n>`"),
+                    ("buckets", _) => {
+                        abort!(arg.name.span(), "Expected `buckets(<boundary>, ...)`")
+                    }
+                    (name, _) => abort!(
+                        arg.name.span(),
+                        "Unknown featurize attribute `{}`, expected one of `skip`, `rename`, `scale`, `offset`, `clamp`, `one_hot`, `buckets`",
+                        name
+                    ),
+                }
+            }
+        }
+        attrs
+    }
+
+    /// Returns the categorical encoding of the field, if any.
+    fn categorical(&self, field: &Field) -> Option<Categorical<'_>> {
+        let encoding = match (&self.one_hot, &self.buckets) {
+            (None, None) => return None,
+            (Some(n), None) => Categorical::OneHot(n),
+            (None, Some(boundaries)) => Categorical::Buckets(boundaries),
+            (Some(_), Some(_)) => abort!(
+                field.span(),
+                "`one_hot` and `buckets` can't be used on the same field"
+            ),
+        };
+        if self.scale.is_some() || self.offset.is_some() || self.clamp.is_some() {
+            abort!(
+                field.span(),
+                "`scale`, `offset` and `clamp` can't be combined with `one_hot` or `buckets`"
+            );
+        }
+        match &field.ty {
+            Type::Path(ty) if is_primitive_type(ty) => {
+                // `bool` can't be cast to `f32`.
+                if let Categorical::Buckets(_) = encoding {
+                    if ty.path.segments.last().unwrap().ident == "bool" {
+                        abort!(ty.span(), "`buckets` is only supported for numeric fields");
+                    }
+                }
+            }
+            ty => abort!(
+                ty.span(),
+                "`one_hot` and `buckets` are only supported for numeric fields"
+            ),
+        }
+        if let Categorical::Buckets(boundaries) = encoding {
+            // Boundaries that aren't literals can only be checked at runtime.
+            let values = boundaries.iter().map(literal_value).collect::<Vec<_>>();
+            for (i, w) in values.windows(2).enumerate() {
+                if let [Some(lower), Some(upper)] = w {
+                    if lower >= upper {
+                        abort!(
+                            boundaries[i + 1].span(),
+                            "`buckets` boundaries must be in ascending order"
+                        );
+                    }
+                }
+            }
+        }
+        Some(encoding)
+    }
+
+    /// Wraps the featurization code of a field to apply `scale`, `offset` and `clamp` (in that order) to each of its features.
+    fn transform(&self, featurize: TokenStream) -> TokenStream {
+        if self.scale.is_none() && self.offset.is_none() && self.clamp.is_none() {
+            return featurize;
+        }
+        let scale = self
+            .scale
+            .iter()
+            .map(|scale| quote! { *x *= (#scale) as f32; });
+        let offset = self
+            .offset
+            .iter()
+            .map(|offset| quote! { *x += (#offset) as f32; });
+        let clamp = self
+            .clamp
+            .iter()
+            .map(|(min, max)| quote! { *x = x.clamp((#min) as f32, (#max) as f32); });
+        quote! {
+            {
+                let start = buffer.len();
+                #featurize;
+                for x in &mut buffer[start..] {
+                    #(#scale)*
+                    #(#offset)*
+                    #(#clamp)*
+                }
+            }
+        }
+    }
+}
+
+/// Returns the value of a numeric literal, which may be negated.
+fn literal_value(expr: &Expr) -> Option<f64> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => lit.base10_parse().ok(),
+        Expr::Lit(ExprLit {
+            lit: Lit::Float(lit),
+            ..
+        }) => lit.base10_parse().ok(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => literal_value(expr).map(|value| -value),
+        Expr::Paren(ExprParen { expr, .. }) => literal_value(expr),
+        _ => None,
+    }
+}
+
+fn binding(i: usize) -> Ident {
+    format_ident!("__field{}", i)
 }
 
-fn featurize_type(ty: &Type, accessor: TokenStream) -> TokenStream {
+/// Featurizes a value of type `ty`, applying the field's transforms to every feature except the presence flags of `Option`s.
+fn featurize_type(ty: &Type, accessor: TokenStream, attrs: &FieldAttrs) -> TokenStream {
     match ty {
+        Type::Path(path) if option_inner(path).is_some() => {
+            // Options are featurized as a presence flag followed by the zero-filled payload.
+            let inner = option_inner(path).unwrap();
+            let featurize = featurize_type(inner, quote! { (*__value) }, attrs);
+            let (_, count) = feature_name_type(inner, "");
+            quote! {
+                match &#accessor {
+                    Some(__value) => {
+                        buffer.push(1.0);
+                        #featurize;
+                    }
+                    None => {
+                        buffer.push(0.0);
+                        buffer.extend(std::iter::repeat(0.0).take(#count));
+                    }
+                }
+            }
+        }
+        Type::Tuple(ty) => {
+            let elems = ty.elems.iter().enumerate().map(|(i, elem)| {
+                let index = Index::from(i);
+                featurize_type(elem, quote! { #accessor.#index }, attrs)
+            });
+            quote! {
+                #(#elems;)*
+            }
+        }
         Type::Path(ty) => {
             let ty = ty.path.segments.last().unwrap();
-            match ty.ident.to_string().as_str() {
+            let featurize = match ty.ident.to_string().as_str() {
                 "f32" => quote! { buffer.push(#accessor) },
                 "bool" => quote! { buffer.push(if #accessor { 1.0 } else { 0.0 }) },
                 "f64" | "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64" | "usize"
@@ -126,7 +464,8 @@ fn featurize_type(ty: &Type, accessor: TokenStream) -> TokenStream {
                     quote! { buffer.push(#accessor as f32) }
                 }
                 _ => quote!(buffer.extend(#accessor.featurize())),
-            }
+            };
+            attrs.transform(featurize)
         }
         Type::Array(ty) => {
             let mut elems = vec![];
@@ -140,7 +479,7 @@ fn featurize_type(ty: &Type, accessor: TokenStream) -> TokenStream {
                 x => abort!(x.span(), "Array length must be an integer literal"),
             };
             for i in 0..len {
-                elems.push(featurize_type(&ty.elem, quote! { #accessor[#i] }));
+                elems.push(featurize_type(&ty.elem, quote! { #accessor[#i] }, attrs));
             }
             quote! {
                 #(#elems);*;
@@ -154,6 +493,21 @@ fn featurize_type(ty: &Type, accessor: TokenStream) -> TokenStream {
     }
 }
 
+/// Returns `T` if the type is an `Option<T>`.
+fn option_inner(ty: &TypePath) -> Option<&Type> {
+    let segment = ty.path.segments.last().unwrap();
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 fn is_primitive_type(ty: &TypePath) -> bool {
     let ty = ty.path.segments.last().unwrap();
     matches!(
@@ -174,28 +528,42 @@ fn is_primitive_type(ty: &TypePath) -> bool {
     )
 }
 
-fn feature_name_field(field: &Field) -> (TokenStream, TokenStream) {
-    let ident = field.ident.as_ref().unwrap().to_string();
-    feature_name_type(&field.ty, &ident)
-}
-
 fn feature_name_type(ty: &Type, prefix: &str) -> (TokenStream, TokenStream) {
     match ty {
         Type::Path(ty) if is_primitive_type(ty) => (
             quote! { names.push(#prefix.to_string()); },
             quote! { 1usize },
         ),
-        Type::Path(ty) => {
-            let type_ident = &ty.path.segments.last().unwrap().ident;
+        Type::Path(path) if option_inner(path).is_some() => {
+            let inner = option_inner(path).unwrap();
+            let is_some = format!("{}.is_some", prefix);
+            let (names, count) = feature_name_type(inner, prefix);
             (
                 quote! {
-                    for name in #type_ident::feature_names() {
-                        names.push(format!("{}.{}", #prefix, name));
-                    }
+                    names.push(#is_some.to_string());
+                    #names
                 },
-                quote! { #type_ident::num_feats() },
+                quote! { (1usize + #count) },
             )
         }
+        Type::Tuple(ty) => {
+            let mut names = vec![];
+            let mut counts = vec![];
+            for (i, elem) in ty.elems.iter().enumerate() {
+                let (name, count) = feature_name_type(elem, &format!("{}.{}", prefix, i));
+                names.push(name);
+                counts.push(count);
+            }
+            (quote! { #(#names)* }, quote! { (0usize #(+ #counts)*) })
+        }
+        Type::Path(ty) => (
+            quote! {
+                for name in <#ty as Featurizable>::feature_names() {
+                    names.push(format!("{}.{}", #prefix, name));
+                }
+            },
+            quote! { <#ty as Featurizable>::num_feats() },
+        ),
         Type::Array(ty) => {
             let mut names = vec![];
             let mut counts = vec![];
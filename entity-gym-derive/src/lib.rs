@@ -1,10 +1,11 @@
 use proc_macro_error::proc_macro_error;
 
 mod action;
+mod action_set;
 mod featurizable;
 
 #[proc_macro_error]
-#[proc_macro_derive(Featurizable)]
+#[proc_macro_derive(Featurizable, attributes(featurize))]
 pub fn derive_featurizable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     featurizable::derive(input)
 }
@@ -14,3 +15,9 @@ pub fn derive_featurizable(input: proc_macro::TokenStream) -> proc_macro::TokenS
 pub fn derive_action(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     action::derive(input)
 }
+
+#[proc_macro_error]
+#[proc_macro_derive(ActionSet, attributes(action))]
+pub fn derive_action_set(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    action_set::derive(input)
+}
@@ -0,0 +1,44 @@
+use ndarray::prelude::*;
+
+use crate::checkpoint::Tensors;
+use crate::fun::softmax;
+use crate::linear::Linear;
+use crate::msgpack::TensorDict;
+#[derive(Debug, Clone)]
+pub struct CategoricalActionHead {
+    proj: Linear,
+}
+
+impl From<&TensorDict> for CategoricalActionHead {
+    fn from(state_dict: &TensorDict) -> Self {
+        let dict = state_dict.as_dict();
+        CategoricalActionHead {
+            proj: Linear::from(&dict["proj"]),
+        }
+    }
+}
+
+impl CategoricalActionHead {
+    pub fn tensors(&self, prefix: &str, tensors: &mut Tensors) {
+        self.proj.tensors(&format!("{}.proj", prefix), tensors);
+    }
+
+    /// Returns the probabilities of each choice for every actor without sampling.
+    pub fn probs(
+        &self,
+        x: ArrayView2<f32>,
+        actors: Vec<usize>,
+        mask: Option<&Array2<bool>>,
+    ) -> Array2<f32> {
+        let actor_x = x.select(Axis(0), &actors);
+        let mut logits = self.proj.forward(actor_x.view());
+        if let Some(mask) = mask {
+            logits.zip_mut_with(mask, |logit, &allowed| {
+                if !allowed {
+                    *logit = f32::NEG_INFINITY;
+                }
+            });
+        }
+        softmax(&logits)
+    }
+}
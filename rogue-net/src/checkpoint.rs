@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use indexmap::IndexMap;
+use ndarray::{ArrayView, Dimension};
+
+use crate::config::{
+    EnvConfig, OptimizerConfig, PPOConfig, RogueNetConfig, RolloutConfig, TrainConfig,
+};
+use crate::msgpack::encode_state_dict;
+use crate::state::{ActionSpace, ObsSpace, State};
+
+/// Parameters of a RogueNet neural network together with the metadata required to write a checkpoint directory.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// Network architecture.
+    pub config: RogueNetConfig,
+    /// Entity types and their features.
+    pub obs_space: ObsSpace,
+    /// Action types, in the order of the action heads.
+    pub action_space: IndexMap<String, ActionSpace>,
+    /// Number of environment steps the network was trained for.
+    pub step: u32,
+    /// Maps parameter names such as `backbone.blocks.0.ln1.weight` to their shape and values, in PyTorch layout.
+    pub tensors: Tensors,
+}
+
+/// Maps parameter names to their shape and values.
+pub(crate) type Tensors = IndexMap<String, (Vec<usize>, Vec<f32>)>;
+
+pub(crate) fn insert_tensor<D: Dimension>(
+    tensors: &mut Tensors,
+    key: String,
+    value: ArrayView<f32, D>,
+) {
+    tensors.insert(
+        key,
+        (value.shape().to_vec(), value.iter().copied().collect()),
+    );
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to a directory that can be loaded with [`RogueNet::load`](crate::RogueNet::load).
+    ///
+    /// # Arguments
+    /// * `path` - Path to the checkpoint directory, which is created if it does not exist.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path)?;
+        for (name, contents) in self.files()? {
+            std::fs::write(path.join(name), contents)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the checkpoint as a tar archive that can be loaded with [`RogueNet::load_archive`](crate::RogueNet::load_archive).
+    pub fn save_archive<W: Write>(&self, w: W) -> Result<(), std::io::Error> {
+        self.save_archive_with_extra_files(w, &HashMap::new())
+    }
+
+    /// Writes the checkpoint as a tar archive that additionally contains the given files, keyed by their path in the archive.
+    /// The extra files are returned by [`RogueNet::load_archive_with_extra_files`](crate::RogueNet::load_archive_with_extra_files).
+    pub fn save_archive_with_extra_files<W: Write>(
+        &self,
+        w: W,
+        extra_files: &HashMap<String, Vec<u8>>,
+    ) -> Result<(), std::io::Error> {
+        let mut builder = tar::Builder::new(w);
+        for (name, contents) in self.files()? {
+            append_file(&mut builder, name, &contents)?;
+        }
+        for (name, contents) in extra_files {
+            append_file(&mut builder, name, contents)?;
+        }
+        builder.into_inner()?.flush()
+    }
+
+    pub(crate) fn state(&self) -> State {
+        State {
+            step: self.step,
+            restart: 0,
+            next_eval_step: None,
+            agent: "<blob:msgpack>".to_string(),
+            value_function: None,
+            optimizer: "<blob:msgpack>".to_string(),
+            vf_optimizer: None,
+            obs_space: self.obs_space.clone(),
+            action_space: self.action_space.clone(),
+        }
+    }
+
+    fn files(&self) -> Result<Vec<(&'static str, Vec<u8>)>, std::io::Error> {
+        let pretty = ron::ser::PrettyConfig::default();
+        let config = TrainConfig {
+            version: 3,
+            env: EnvConfig {
+                kwargs: "{}".to_string(),
+                id: String::new(),
+                validate: false,
+            },
+            net: self.config.clone(),
+            optim: OptimizerConfig {
+                lr: 0.0,
+                bs: 0,
+                weight_decay: 0.0,
+                micro_bs: None,
+                anneal_lr: false,
+                update_epochs: 0,
+                max_grad_norm: 0.0,
+            },
+            ppo: PPOConfig {
+                gae: true,
+                gamma: 0.0,
+                gae_lambda: 0.0,
+                norm_adv: false,
+                clip_coef: 0.0,
+                clip_vloss: false,
+                ent_coef: 0.0,
+                vf_coef: 0.0,
+                target_kl: None,
+                anneal_entropy: false,
+            },
+            rollout: RolloutConfig {
+                steps: 0,
+                num_envs: 0,
+                processes: 0,
+            },
+            eval: None,
+            vf_net: None,
+            name: String::new(),
+            seed: 0,
+            total_timesteps: self.step as u64,
+            max_train_time: None,
+            torch_deterministic: false,
+            cuda: false,
+            track: false,
+            wandb_project_name: String::new(),
+            wandb_entity: String::new(),
+            capture_samples: None,
+            capture_logits: false,
+            capture_samples_subsample: 1,
+            trial: None,
+            data_dir: ".".to_string(),
+            cuda_empty_cache: false,
+        };
+        let to_io_error = |e: ron::Error| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let config = ron::ser::to_string_pretty(&config, pretty.clone()).map_err(to_io_error)?;
+        let state = ron::ser::to_string_pretty(&self.state(), pretty).map_err(to_io_error)?;
+        let mut agent = Vec::new();
+        encode_state_dict(&mut agent, &self.tensors)?;
+        Ok(vec![
+            ("config.ron", config.into_bytes()),
+            ("state.ron", state.into_bytes()),
+            ("state.agent.msgpack", agent),
+        ])
+    }
+}
+
+/// Appends a regular file with the given contents to a tar archive.
+pub(crate) fn append_file<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+) -> Result<(), std::io::Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, contents)
+}
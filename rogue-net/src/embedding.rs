@@ -0,0 +1,125 @@
+use ndarray::prelude::*;
+
+use crate::checkpoint::{insert_tensor, Tensors};
+use crate::fun::{clip, relu};
+use crate::layer_norm::LayerNorm;
+use crate::linear::Linear;
+use crate::msgpack::TensorDict;
+
+#[derive(Debug, Clone)]
+pub struct Embedding {
+    mean: Array<f32, Ix2>,
+    std: Array<f32, Ix2>,
+    proj: Linear,
+    ln: LayerNorm,
+    obs_filter: Option<ObsFilter>,
+}
+
+#[derive(Debug, Clone)]
+struct ObsFilter {
+    feature_selector: Vec<usize>,
+    received_features: Vec<String>,
+}
+
+impl From<&TensorDict> for Embedding {
+    fn from(state_dict: &TensorDict) -> Self {
+        let dict = state_dict.as_dict();
+        let norm = dict["0"].as_dict();
+        let mean = norm["mean"]
+            .as_tensor()
+            .to_ndarray_f32()
+            .insert_axis(Axis(0));
+        let count = norm["count"].as_tensor().to_ndarray_f32();
+        let squares_sum = norm["squares_sum"]
+            .as_tensor()
+            .to_ndarray_f32()
+            .insert_axis(Axis(0));
+        Embedding {
+            std: (squares_sum / (count - 1.0))
+                .mapv(|x| if x == 0.0 { 1.0 } else { x.sqrt() })
+                .into_dimensionality()
+                .unwrap(),
+            mean: mean.into_dimensionality().unwrap(),
+            proj: Linear::from(&dict["1"]),
+            ln: LayerNorm::from(&dict["3"]),
+            obs_filter: None,
+        }
+    }
+}
+
+impl Embedding {
+    pub fn forward(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        let x = match &self.obs_filter {
+            Some(filter) => x.select(Axis(1), &filter.feature_selector),
+            None => x.to_owned(),
+        };
+        let x = (&x - &self.mean) / &self.std;
+        let x = clip(x.view(), -5.0, 5.0);
+        let x = self.proj.forward(x.view());
+        let x = relu(x.view());
+        self.ln.forward(x.view())
+    }
+
+    pub fn set_obs_filter(&mut self, expected_features: &[String], received_features: &[String]) {
+        let feature_selector = expected_features
+            .iter()
+            .map(|f| {
+                received_features
+                    .iter()
+                    .position(|ff| ff == f)
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "expected feature with name \"{}\" in {:?}",
+                            f, received_features
+                        )
+                    })
+            })
+            .collect();
+        self.obs_filter = Some(ObsFilter {
+            feature_selector,
+            received_features: received_features.to_vec(),
+        });
+    }
+
+    /// Returns the features consumed by the embedding if it was adapted with [`Embedding::set_obs_filter`].
+    pub fn received_features(&self) -> Option<&[String]> {
+        self.obs_filter
+            .as_ref()
+            .map(|filter| filter.received_features.as_slice())
+    }
+
+    /// Writes the parameters of the embedding.
+    /// An observation filter is folded into the parameters, so the written embedding directly consumes the received features.
+    pub fn tensors(&self, prefix: &str, tensors: &mut Tensors) {
+        let (mean, std, proj) = match &self.obs_filter {
+            Some(filter) => {
+                let num_features = filter.received_features.len();
+                let mut mean = Array1::zeros(num_features);
+                let mut std = Array1::ones(num_features);
+                for (i, &f) in filter.feature_selector.iter().enumerate() {
+                    mean[f] = self.mean[[0, i]];
+                    std[f] = self.std[[0, i]];
+                }
+                let proj = self
+                    .proj
+                    .scatter_inputs(&filter.feature_selector, num_features);
+                (mean, std, proj)
+            }
+            None => (
+                self.mean.row(0).to_owned(),
+                self.std.row(0).to_owned(),
+                self.proj.clone(),
+            ),
+        };
+        // With a count of 2, the loaded standard deviation equals the square root of `squares_sum`.
+        insert_tensor(tensors, format!("{}.0.count", prefix), arr0(2.0f32).view());
+        insert_tensor(tensors, format!("{}.0.mean", prefix), mean.view());
+        insert_tensor(
+            tensors,
+            format!("{}.0.squares_sum", prefix),
+            std.mapv(|x| x * x).view(),
+        );
+        proj.tensors(&format!("{}.1", prefix), tensors);
+        self.ln.tensors(&format!("{}.3", prefix), tensors);
+    }
+}
@@ -0,0 +1,20 @@
+mod categorical_action_head;
+mod checkpoint;
+mod config;
+mod embedding;
+mod fun;
+mod layer_norm;
+mod linear;
+mod msgpack;
+mod relpos_encoding;
+mod rogue_net;
+mod select_entity_action_head;
+mod state;
+#[cfg(test)]
+mod tests;
+mod transformer;
+
+pub use crate::checkpoint::Checkpoint;
+pub use crate::config::RogueNetConfig;
+pub use crate::rogue_net::{BatchOutput, BatchProbs, FwdArgs, RogueNet};
+pub use crate::state::{ActionSpace, Entity, ObsSpace};
@@ -0,0 +1,48 @@
+use ndarray::prelude::*;
+
+use crate::checkpoint::{insert_tensor, Tensors};
+use crate::msgpack::TensorDict;
+#[derive(Debug, Clone)]
+pub struct Linear {
+    weight: Array<f32, Ix2>,
+    bias: Array<f32, Ix2>,
+}
+
+impl From<&TensorDict> for Linear {
+    fn from(state_dict: &TensorDict) -> Self {
+        let dict = state_dict.as_dict();
+        let weight = dict["weight"].as_tensor().to_ndarray_f32();
+        let bias = dict["bias"].as_tensor().to_ndarray_f32();
+        Linear {
+            weight: weight.reversed_axes().into_dimensionality().unwrap(),
+            bias: bias.insert_axis(Axis(0)).into_dimensionality().unwrap(),
+        }
+    }
+}
+
+impl Linear {
+    pub fn forward(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        x.dot(&self.weight) + &self.bias
+    }
+
+    /// Returns an equivalent layer that takes `num_inputs` inputs of which only the ones at `selector` are used.
+    pub fn scatter_inputs(&self, selector: &[usize], num_inputs: usize) -> Linear {
+        let mut weight = Array2::zeros((num_inputs, self.weight.dim().1));
+        for (row, &i) in selector.iter().enumerate() {
+            weight.row_mut(i).assign(&self.weight.row(row));
+        }
+        Linear {
+            weight,
+            bias: self.bias.clone(),
+        }
+    }
+
+    pub fn tensors(&self, prefix: &str, tensors: &mut Tensors) {
+        insert_tensor(tensors, format!("{}.weight", prefix), self.weight.t());
+        insert_tensor(
+            tensors,
+            format!("{}.bias", prefix),
+            self.bias.index_axis(Axis(0), 0),
+        );
+    }
+}
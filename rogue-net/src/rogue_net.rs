@@ -0,0 +1,620 @@
+use indexmap::IndexMap;
+use ndarray::{concatenate, s, Array1, Array2, Axis};
+use ron::extensions::Extensions;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::categorical_action_head::CategoricalActionHead;
+use crate::checkpoint::{Checkpoint, Tensors};
+use crate::config::RogueNetConfig;
+use crate::config::TrainConfig;
+use crate::embedding::Embedding;
+use crate::fun::sample;
+use crate::linear::Linear;
+use crate::msgpack::decode_state_dict;
+use crate::msgpack::{Tensor, TensorDict};
+use crate::select_entity_action_head::SelectEntityActionHead;
+use crate::state::{ActionSpace, ObsSpace, State};
+use crate::transformer::Transformer;
+
+#[derive(Debug, Clone)]
+/// Implements the [RogueNet](https://github.com/entity-neural-network/rogue-net) entity neural network.
+pub struct RogueNet {
+    pub config: RogueNetConfig,
+    pub obs_space: ObsSpace,
+    translation: Option<Translate>,
+    embeddings: Vec<(String, Embedding)>,
+    backbone: Transformer,
+    action_heads: IndexMap<String, ActionHead>,
+    value_head: Option<Linear>,
+    action_space: IndexMap<String, ActionSpace>,
+    step: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Arguments for RogueNet forward pass.
+pub struct FwdArgs {
+    pub features: HashMap<String, Array2<f32>>,
+    pub actors: Vec<String>,
+    /// Maps actions to the entity types that act for them, overriding `actors` for that action.
+    pub action_actors: HashMap<String, Vec<String>>,
+    /// Maps select entity actions to the entity types that can be selected.
+    pub actees: HashMap<String, Vec<String>>,
+    /// Maps categorical actions to a mask of shape `(actors, choices)` that is `false` for disallowed choices.
+    /// Every actor must be allowed at least one choice.
+    pub action_masks: HashMap<String, Array2<bool>>,
+}
+
+#[derive(Debug, Clone)]
+/// Result of [`RogueNet::forward_batch`] for a single observation.
+pub struct BatchOutput {
+    /// Probabilities and sampled choices for each requested action, see [`RogueNet::forward_action`].
+    pub actions: Vec<(Array2<f32>, Vec<u64>)>,
+    /// Estimate of the expected return computed by the value head, if the checkpoint contains one.
+    pub value: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+/// Result of [`RogueNet::forward_batch_probs`] for a single observation.
+pub struct BatchProbs {
+    /// Probabilities for each requested action, see [`RogueNet::forward_probs`].
+    pub probs: Vec<Array2<f32>>,
+    /// Estimate of the expected return computed by the value head, if the checkpoint contains one.
+    pub value: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+enum ActionHead {
+    Categorical(CategoricalActionHead),
+    SelectEntity(SelectEntityActionHead),
+}
+
+#[derive(Debug, Clone)]
+struct Translate {
+    reference_entity: String,
+    rotation_vec_indices: Option<[usize; 2]>,
+    position_feature_indices: HashMap<String, Vec<usize>>,
+}
+
+impl RogueNet {
+    /// Loads the parameters for a trained RogueNet neural network from a checkpoint directory produced by [enn-trainer](https://github.com/entity-neural-network/enn-trainer).
+    ///
+    /// # Arguments
+    /// * `path` - Path to the checkpoint directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> RogueNet {
+        let config_path = path.as_ref().join("config.ron");
+        let ron = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+
+        let config: TrainConfig = ron
+            .from_reader(
+                File::open(&config_path)
+                    .unwrap_or_else(|_| panic!("Failed to open {}", config_path.display())),
+            )
+            .unwrap();
+
+        let state_path = path.as_ref().join("state.ron");
+        let state: State = ron
+            .from_reader(
+                File::open(&state_path)
+                    .unwrap_or_else(|_| panic!("Failed to open {}", state_path.display())),
+            )
+            .unwrap();
+
+        let agent_path = path.as_ref().join("state.agent.msgpack");
+        let state_dict = decode_state_dict(File::open(&agent_path).unwrap()).unwrap();
+        RogueNet::new(&state_dict, config.net, &state)
+    }
+
+    /// Loads the parameters for a trained RogueNet neural network from a tar archive of a checkpoint directory.
+    ///
+    /// # Arguments
+    /// * `r` - A reader for the tar archive.
+    ///
+    /// # Example
+    /// ```
+    /// use std::fs::File;
+    /// use rogue_net::RogueNet;
+    ///
+    /// let rogue_net = RogueNet::load_archive(File::open("test-data/simple.roguenet").unwrap());
+    /// ```
+    pub fn load_archive<R: Read>(r: R) -> Result<RogueNet, std::io::Error> {
+        let (rogue_net, extra_files) = RogueNet::load_archive_with_extra_files(r)?;
+        match extra_files.keys().next() {
+            Some(path) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Unexpected file: {}", path),
+            )),
+            None => Ok(rogue_net),
+        }
+    }
+
+    /// Loads the parameters for a trained RogueNet neural network from a tar archive of a checkpoint directory
+    /// that may contain additional files.
+    ///
+    /// Returns the network and the contents of all files that are not part of the checkpoint, keyed by their path in the archive.
+    ///
+    /// # Arguments
+    /// * `r` - A reader for the tar archive.
+    pub fn load_archive_with_extra_files<R: Read>(
+        r: R,
+    ) -> Result<(RogueNet, HashMap<String, Vec<u8>>), std::io::Error> {
+        let mut a = tar::Archive::new(r);
+        let mut config: Option<TrainConfig> = None;
+        let mut state = None;
+        let mut state_dict = None;
+        let mut extra_files = HashMap::new();
+        let ron = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+        for file in a.entries()? {
+            let mut file = file?;
+            match file
+                .path()?
+                .components()
+                .next_back()
+                .unwrap()
+                .as_os_str()
+                .to_str()
+                .unwrap()
+            {
+                "config.ron" => config = Some(ron.from_reader(file).unwrap()),
+                "state.ron" => state = Some(ron.from_reader(file).unwrap()),
+                "state.agent.msgpack" => state_dict = Some(decode_state_dict(file).unwrap()),
+                _ => {
+                    let path = file.path()?.display().to_string();
+                    let mut contents = vec![];
+                    file.read_to_end(&mut contents)?;
+                    extra_files.insert(path, contents);
+                }
+            }
+        }
+        let rogue_net = RogueNet::new(
+            &state_dict.ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::Other, "Missing state.agent.msgpack")
+            })?,
+            config
+                .ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::Other, "Missing config.ron")
+                })?
+                .net,
+            &state.ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::Other, "Missing state.ron")
+            })?,
+        );
+        Ok((rogue_net, extra_files))
+    }
+
+    /// Runs a forward pass of the RogueNet neural network.
+    ///
+    /// # Arguments
+    /// * `entities` - Maps each entity type to an `Array2<f32>` containing the entities' features.
+    ///
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use ndarray::prelude::*;
+    /// use rogue_net::{RogueNet, FwdArgs};
+    ///
+    /// let rogue_net = RogueNet::load("test-data/simple");
+    /// let mut features = HashMap::new();
+    /// features.insert("Head".to_string(), array![[3.0, 4.0]]);
+    /// features.insert("SnakeSegment".to_string(), array![[3.0, 4.0], [4.0, 4.0]]);
+    /// features.insert("Food".to_string(), array![[3.0, 5.0], [8.0, 4.0]]);
+    /// let (action_probs, actions) = rogue_net.forward(FwdArgs { features, ..Default::default() });
+    /// ```
+    pub fn forward(&self, args: FwdArgs) -> (Array2<f32>, Vec<u64>) {
+        let action = self.action_heads.keys().next().unwrap().clone();
+        self.forward_action(args, &action)
+    }
+
+    /// Runs a forward pass of the RogueNet neural network and samples from the action head with the given name.
+    ///
+    /// For categorical actions, returns the action probabilities and the sampled choice for each actor.
+    /// For select entity actions, returns the selection probabilities and the index of the selected entity
+    /// for each actor, where indices refer to the concatenation of all entities with a type in `args.actees[action]`.
+    /// Actors and actees are ordered by the position of their type in `args.actors` (or `args.action_actors[action]`) and `args.actees[action]`.
+    ///
+    /// # Arguments
+    /// * `args` - Entity features, actor types and actee types.
+    /// * `action` - Name of the action.
+    pub fn forward_action(&self, args: FwdArgs, action: &str) -> (Array2<f32>, Vec<u64>) {
+        self.forward_actions(args, &[action]).pop().unwrap()
+    }
+
+    /// Runs a single forward pass of the RogueNet neural network and samples from each of the given action heads.
+    ///
+    /// Returns the probabilities and sampled actions for each action in the same order as `actions`.
+    /// See [`RogueNet::forward_action`] for details.
+    pub fn forward_actions(&self, args: FwdArgs, actions: &[&str]) -> Vec<(Array2<f32>, Vec<u64>)> {
+        self.forward_batch(vec![(args, actions.to_vec())])
+            .pop()
+            .unwrap()
+            .actions
+    }
+
+    /// Runs a single forward pass of the RogueNet neural network and returns the probabilities of each of the given
+    /// action heads without sampling from them.
+    ///
+    /// Use this instead of [`RogueNet::forward_actions`] to choose actions with your own random number generator or sampling strategy.
+    pub fn forward_probs(&self, args: FwdArgs, actions: &[&str]) -> Vec<Array2<f32>> {
+        self.forward_batch_probs(vec![(args, actions.to_vec())])
+            .pop()
+            .unwrap()
+            .probs
+    }
+
+    /// Runs a single forward pass of the RogueNet neural network on a batch of independent observations.
+    ///
+    /// Each element of `batch` holds the arguments for one observation and the names of the action heads to sample from.
+    /// Entities only attend to entities of the same observation, so the results are the same as calling
+    /// [`RogueNet::forward_actions`] on each observation, but all observations share the same matrix multiplications.
+    ///
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use ndarray::prelude::*;
+    /// use rogue_net::{RogueNet, FwdArgs};
+    ///
+    /// let rogue_net = RogueNet::load("test-data/simple");
+    /// let batch = (0..3)
+    ///     .map(|i| {
+    ///         let mut features = HashMap::new();
+    ///         features.insert("Head".to_string(), array![[i as f32, 4.0]]);
+    ///         features.insert("SnakeSegment".to_string(), array![[3.0, 4.0]]);
+    ///         features.insert("Food".to_string(), array![[3.0, 5.0], [8.0, 4.0]]);
+    ///         let args = FwdArgs { features, actors: vec!["Head".to_string()], ..Default::default() };
+    ///         (args, vec!["action"])
+    ///     })
+    ///     .collect();
+    /// let results = rogue_net.forward_batch(batch);
+    /// assert_eq!(results.len(), 3);
+    /// ```
+    pub fn forward_batch(&self, batch: Vec<(FwdArgs, Vec<&str>)>) -> Vec<BatchOutput> {
+        self.forward_batch_probs(batch)
+            .into_iter()
+            .map(|BatchProbs { probs, value }| BatchOutput {
+                actions: probs
+                    .into_iter()
+                    .map(|probs| {
+                        let acts = sample(&probs);
+                        (probs, acts)
+                    })
+                    .collect(),
+                value,
+            })
+            .collect()
+    }
+
+    /// Batched version of [`RogueNet::forward_probs`], see [`RogueNet::forward_batch`].
+    pub fn forward_batch_probs(&self, mut batch: Vec<(FwdArgs, Vec<&str>)>) -> Vec<BatchProbs> {
+        for (args, _) in &mut batch {
+            self.translate(args);
+        }
+
+        // Embed the entities of each type for all observations at once.
+        let embeddings = self
+            .embeddings
+            .iter()
+            .map(|(key, embedding)| {
+                let features = batch
+                    .iter()
+                    .map(|(args, _)| args.features[key].view())
+                    .collect::<Vec<_>>();
+                embedding.forward(concatenate(Axis(0), &features).unwrap().view())
+            })
+            .collect::<Vec<_>>();
+
+        // Arrange the embedded entities so that the entities of each observation are contiguous.
+        let mut rows = Vec::with_capacity(batch.len() * self.embeddings.len());
+        let mut segments = Vec::with_capacity(batch.len());
+        let mut offsets = Vec::with_capacity(batch.len());
+        let mut type_offsets = vec![0; self.embeddings.len()];
+        let mut i = 0;
+        for (args, _) in &batch {
+            let start = i;
+            let mut sample_offsets = HashMap::with_capacity(self.embeddings.len());
+            for (t, (key, _)) in self.embeddings.iter().enumerate() {
+                let count = args.features[key].dim().0;
+                rows.push(embeddings[t].slice(s![type_offsets[t]..type_offsets[t] + count, ..]));
+                type_offsets[t] += count;
+                sample_offsets.insert(key.as_str(), i..i + count);
+                i += count;
+            }
+            segments.push(start..i);
+            offsets.push(sample_offsets);
+        }
+        let x = concatenate(Axis(0), &rows).unwrap();
+        let features = batch
+            .iter()
+            .map(|(args, _)| &args.features)
+            .collect::<Vec<_>>();
+        let x = self.backbone.forward(x, &segments, &features);
+
+        batch
+            .iter()
+            .zip(offsets)
+            .zip(segments)
+            .map(|(((args, actions), offsets), segment)| {
+                let indices = |types: &[String]| {
+                    types
+                        .iter()
+                        .filter_map(|t| offsets.get(t.as_str()))
+                        .flat_map(|range| range.clone())
+                        .collect::<Vec<_>>()
+                };
+                // The value head is applied to the mean of the embeddings of all entities.
+                let value = self.value_head.as_ref().map(|value_head| {
+                    let x = x.slice(s![segment, ..]);
+                    let pooled = x
+                        .mean_axis(Axis(0))
+                        .unwrap_or_else(|| Array1::zeros(x.dim().1));
+                    value_head.forward(pooled.insert_axis(Axis(0)).view())[[0, 0]]
+                });
+                let probs = actions
+                    .iter()
+                    .map(|&action| {
+                        let actors =
+                            indices(args.action_actors.get(action).unwrap_or(&args.actors));
+                        match self
+                            .action_heads
+                            .get(action)
+                            .unwrap_or_else(|| panic!("Missing action head: {}", action))
+                        {
+                            ActionHead::Categorical(head) => {
+                                let mask = args.action_masks.get(action);
+                                if let Some(mask) = mask {
+                                    assert!(
+                                        mask.outer_iter().all(|row| row.iter().any(|&m| m)),
+                                        "Mask for action \"{}\" does not allow any choices",
+                                        action
+                                    );
+                                }
+                                head.probs(x.view(), actors, mask)
+                            }
+                            ActionHead::SelectEntity(head) => {
+                                let types = args.actees.get(action).map_or(&[][..], |t| &t[..]);
+                                let actees = indices(types);
+                                assert!(
+                                    actors.is_empty() || !actees.is_empty(),
+                                    "Observation contains no {} entities that could be selected",
+                                    types
+                                        .iter()
+                                        .map(|t| format!("\"{}\"", t))
+                                        .collect::<Vec<_>>()
+                                        .join(" or ")
+                                );
+                                head.probs(x.view(), actors, actees)
+                            }
+                        }
+                    })
+                    .collect();
+                BatchProbs { probs, value }
+            })
+            .collect()
+    }
+
+    /// Translates the positions of all entities to be relative to the reference entity.
+    fn translate(&self, args: &mut FwdArgs) {
+        if let Some(t) = &self.translation {
+            let reference_entity = args
+                .features
+                .get(&t.reference_entity)
+                .unwrap_or_else(|| panic!("Missing entity type: {}", t.reference_entity));
+            let origin = t.position_feature_indices[&t.reference_entity]
+                .iter()
+                .map(|&i| reference_entity[[0, i]])
+                .collect::<Vec<_>>();
+            let rotation = t
+                .rotation_vec_indices
+                .map(|r| (reference_entity[[0, r[0]]], reference_entity[[0, r[1]]]));
+            for (entity, feats) in args.features.iter_mut() {
+                if *entity != t.reference_entity {
+                    for i in 0..feats.dim().0 {
+                        match rotation {
+                            Some((rx, ry)) => {
+                                let x =
+                                    feats[[i, t.position_feature_indices[entity][0]]] - origin[0];
+                                let y =
+                                    feats[[i, t.position_feature_indices[entity][1]]] - origin[1];
+                                feats[[i, t.position_feature_indices[entity][0]]] = x * rx + y * ry;
+                                feats[[i, t.position_feature_indices[entity][1]]] =
+                                    -x * ry + y * rx;
+                            }
+                            None => {
+                                for (j, x) in
+                                    t.position_feature_indices[entity].iter().zip(origin.iter())
+                                {
+                                    feats[[i, *j]] -= x;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn new(state_dict: &TensorDict, config: RogueNetConfig, state: &State) -> Self {
+        assert!(
+            config.embd_pdrop == 0.0 && config.resid_pdrop == 0.0 && config.attn_pdrop == 0.0,
+            "dropout is not supported"
+        );
+        assert!(config.pooling.is_none(), "pooling is not supported");
+
+        let translation = config.translation.as_ref().map(|t| {
+            assert!(
+                t.rotation_angle_feature.is_none(),
+                "rotation_angle_feature not implemented",
+            );
+            assert!(!t.add_dist_feature, "add_dist_features not implemented");
+            let rotation_vec_indices = t.rotation_vec_features.as_ref().map(|rot| {
+                let indices = rot
+                    .iter()
+                    .map(|s| {
+                        state.obs_space.entities[&t.reference_entity]
+                            .features
+                            .iter()
+                            .position(|f| f == s)
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(indices.len(), 2, "rotation_vec_features must have length 2");
+                [indices[0], indices[1]]
+            });
+            let position_feature_indices = state
+                .obs_space
+                .entities
+                .iter()
+                .map(|(name, entity)| {
+                    let indices = t
+                        .position_features
+                        .iter()
+                        .map(|f| {
+                            entity
+                                .features
+                                .iter()
+                                .position(|f2| f2 == f)
+                                .unwrap_or_else(|| {
+                                    panic!("feature \"{}\" not found in reference entity", f)
+                                })
+                        })
+                        .collect::<Vec<_>>();
+                    (name.clone(), indices)
+                })
+                .collect();
+            Translate {
+                reference_entity: t.reference_entity.clone(),
+                rotation_vec_indices,
+                position_feature_indices,
+            }
+        });
+
+        let dict = state_dict.as_dict();
+        let mut embeddings = Vec::new();
+        for (key, value) in dict["embedding"].as_dict()["embeddings"].as_dict() {
+            let embedding = Embedding::from(value);
+            embeddings.push((key.clone(), embedding));
+        }
+        let backbone = Transformer::new(&dict["backbone"], &config, state);
+
+        let mut action_heads = IndexMap::new();
+        for (key, value) in dict["action_heads"].as_dict() {
+            let action_head = match state.action_space.get(key) {
+                Some(ActionSpace::SelectEntityActionSpace) => {
+                    ActionHead::SelectEntity(SelectEntityActionHead::new(value, config.d_qk))
+                }
+                _ => ActionHead::Categorical(CategoricalActionHead::from(value)),
+            };
+            action_heads.insert(key.clone(), action_head);
+        }
+        let value_head = dict
+            .get("auxiliary_heads")
+            .and_then(|heads| heads.as_dict().get("value"))
+            .map(Linear::from);
+
+        RogueNet {
+            embeddings,
+            translation,
+            backbone,
+            action_heads,
+            value_head,
+            config,
+            obs_space: state.obs_space.clone(),
+            action_space: state.action_space.clone(),
+            step: state.step,
+        }
+    }
+
+    /// Returns the parameters of the network as a [`Checkpoint`] that can be modified and saved.
+    ///
+    /// Observation filters applied with [`RogueNet::with_obs_filter`] are folded into the embedding weights,
+    /// so the checkpoint's observation space contains the adapted features and no filter is required when it is loaded.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut tensors = Tensors::new();
+        let mut obs_space = self.obs_space.clone();
+        for (name, embedding) in &self.embeddings {
+            embedding.tensors(&format!("embedding.embeddings.{}", name), &mut tensors);
+            if let Some(features) = embedding.received_features() {
+                if let Some(entity) = obs_space.entities.get_mut(name) {
+                    entity.features = features.to_vec();
+                }
+            }
+        }
+        self.backbone.tensors("backbone", &mut tensors);
+        for (name, head) in &self.action_heads {
+            let prefix = format!("action_heads.{}", name);
+            match head {
+                ActionHead::Categorical(head) => head.tensors(&prefix, &mut tensors),
+                ActionHead::SelectEntity(head) => head.tensors(&prefix, &mut tensors),
+            }
+        }
+        if let Some(value_head) = &self.value_head {
+            value_head.tensors("auxiliary_heads.value", &mut tensors);
+        }
+        Checkpoint {
+            config: self.config.clone(),
+            obs_space,
+            action_space: self.action_space.clone(),
+            step: self.step,
+            tensors,
+        }
+    }
+
+    /// Constructs a RogueNet neural network from the parameters in a [`Checkpoint`].
+    ///
+    /// # Example
+    /// ```
+    /// use rogue_net::RogueNet;
+    ///
+    /// let mut checkpoint = RogueNet::load("test-data/simple").checkpoint();
+    /// for (_, values) in checkpoint.tensors.values_mut() {
+    ///     values.iter_mut().for_each(|x| *x *= 0.5);
+    /// }
+    /// let rogue_net = RogueNet::from_checkpoint(&checkpoint);
+    /// ```
+    pub fn from_checkpoint(checkpoint: &Checkpoint) -> RogueNet {
+        let mut state_dict = TensorDict::Dict(IndexMap::new());
+        for (key, (shape, data)) in &checkpoint.tensors {
+            state_dict.insert(
+                key.clone(),
+                Tensor::F32 {
+                    shape: shape.clone(),
+                    data: data.clone(),
+                },
+            );
+        }
+        RogueNet::new(&state_dict, checkpoint.config.clone(), &checkpoint.state())
+    }
+
+    /// Writes the network to a checkpoint directory that can be loaded with [`RogueNet::load`].
+    ///
+    /// # Arguments
+    /// * `path` - Path to the checkpoint directory, which is created if it does not exist.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        self.checkpoint().save(path)
+    }
+
+    /// Writes the network as a tar archive that can be loaded with [`RogueNet::load_archive`].
+    pub fn save_archive<W: Write>(&self, w: W) -> Result<(), std::io::Error> {
+        self.checkpoint().save_archive(w)
+    }
+
+    /// Returns the names of all action heads.
+    pub fn actions(&self) -> impl Iterator<Item = &String> {
+        self.action_heads.keys()
+    }
+
+    /// Adapts the RogueNet neural network to the given observation space by
+    /// filtering out any features that were not present during training.
+    pub fn with_obs_filter(mut self, obs_space: HashMap<String, Vec<String>>) -> Self {
+        for (entity, received_features) in obs_space {
+            if let Some((_, embedding)) = self.embeddings.iter_mut().find(|(e, _)| *e == entity) {
+                embedding.set_obs_filter(
+                    &self.obs_space.entities[&entity].features,
+                    &received_features,
+                );
+            }
+        }
+        self
+    }
+}
@@ -0,0 +1,40 @@
+use ndarray::prelude::*;
+
+use crate::checkpoint::Tensors;
+use crate::fun::softmax;
+use crate::linear::Linear;
+use crate::msgpack::TensorDict;
+
+#[derive(Debug, Clone)]
+pub struct SelectEntityActionHead {
+    query_proj: Linear,
+    key_proj: Linear,
+    d_qk: usize,
+}
+
+impl SelectEntityActionHead {
+    pub fn new(state_dict: &TensorDict, d_qk: u32) -> Self {
+        let dict = state_dict.as_dict();
+        SelectEntityActionHead {
+            query_proj: Linear::from(&dict["query_proj"]),
+            key_proj: Linear::from(&dict["key_proj"]),
+            d_qk: d_qk as usize,
+        }
+    }
+
+    pub fn tensors(&self, prefix: &str, tensors: &mut Tensors) {
+        self.query_proj
+            .tensors(&format!("{}.query_proj", prefix), tensors);
+        self.key_proj
+            .tensors(&format!("{}.key_proj", prefix), tensors);
+    }
+
+    /// Returns the probabilities of selecting each actee for every actor without sampling.
+    pub fn probs(&self, x: ArrayView2<f32>, actors: Vec<usize>, actees: Vec<usize>) -> Array2<f32> {
+        let queries = self.query_proj.forward(x.select(Axis(0), &actors).view());
+        let keys = self.key_proj.forward(x.select(Axis(0), &actees).view());
+        let scale = 1.0 / (self.d_qk as f32).sqrt();
+        let logits = queries.dot(&keys.t()) * scale;
+        softmax(&logits)
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+use ndarray::prelude::*;
+
+use crate::checkpoint::Checkpoint;
+use crate::fun::softmax;
+use crate::msgpack::{decode_state_dict, Tensor, TensorDict};
+use crate::rogue_net::{FwdArgs, RogueNet};
+use crate::select_entity_action_head::SelectEntityActionHead;
+use crate::state::ActionSpace;
+
+#[test]
+fn test_vanilla_rogue_net() {
+    test_snake_net(
+        "test-data/simple",
+        array![[0.2477651, 0.24801087, 0.25257978, 0.25164425]],
+    );
+    test_snake_net(
+        "test-data/relpos-encoding",
+        array![[0.24349655, 0.2540197, 0.25025287, 0.25223088]],
+    );
+}
+
+fn test_snake_net(checkpoint: &str, expected: Array2<f32>) {
+    let rogue_net = RogueNet::load(checkpoint);
+    let mut entities = HashMap::new();
+    entities.insert("Head".to_string(), array![[3.0, 4.0]]);
+    entities.insert("SnakeSegment".to_string(), array![[3.0, 4.0], [4.0, 4.0]]);
+    entities.insert("Food".to_string(), array![[3.0, 5.0], [8.0, 4.0]]);
+    let args = FwdArgs {
+        features: entities,
+        actors: vec!["Head".to_string()],
+        ..Default::default()
+    };
+    let (probs, acts) = rogue_net.forward(args.clone());
+    assert_eq!(acts.len(), 1);
+    assert!(acts[0] < 4);
+    assert_eq!(rogue_net.forward_probs(args, &["action"])[0], probs);
+    assert!(
+        probs.abs_diff_eq(&expected, 1e-6),
+        "{:?} != {:?}\n{:?}",
+        probs,
+        expected,
+        &probs - &expected
+    );
+}
+
+#[test]
+fn test_select_entity_action_head() {
+    let mut state_dict = TensorDict::Dict(Default::default());
+    for proj in ["query_proj", "key_proj"] {
+        state_dict.insert(
+            format!("{}.weight", proj),
+            Tensor::F32 {
+                shape: vec![2, 2],
+                data: vec![1.0, 0.0, 0.0, 1.0],
+            },
+        );
+        state_dict.insert(
+            format!("{}.bias", proj),
+            Tensor::F32 {
+                shape: vec![2],
+                data: vec![0.0, 0.0],
+            },
+        );
+    }
+    let head = SelectEntityActionHead::new(&state_dict, 2);
+    let x = array![[1.0, 0.0], [0.0, 1.0], [2.0, 0.0]];
+    let probs = head.probs(x.view(), vec![0], vec![1, 2]);
+    let scale = 1.0 / 2.0f32.sqrt();
+    let z = 1.0 + (2.0 * scale).exp();
+    let expected = array![[1.0 / z, (2.0 * scale).exp() / z]];
+    assert!(
+        probs.abs_diff_eq(&expected, 1e-6),
+        "{:?} != {:?}",
+        probs,
+        expected
+    );
+}
+
+#[test]
+fn test_action_mask() {
+    let rogue_net = RogueNet::load("test-data/simple");
+    let mut entities = HashMap::new();
+    entities.insert("Head".to_string(), array![[3.0, 4.0]]);
+    entities.insert("SnakeSegment".to_string(), array![[3.0, 4.0], [4.0, 4.0]]);
+    entities.insert("Food".to_string(), array![[3.0, 5.0], [8.0, 4.0]]);
+    let mut action_masks = HashMap::new();
+    action_masks.insert("action".to_string(), array![[false, true, false, true]]);
+    for _ in 0..10 {
+        let (probs, acts) = rogue_net.forward_action(
+            FwdArgs {
+                features: entities.clone(),
+                actors: vec!["Head".to_string()],
+                action_masks: action_masks.clone(),
+                ..Default::default()
+            },
+            "action",
+        );
+        assert!(acts[0] == 1 || acts[0] == 3);
+        assert_eq!(probs[[0, 0]], 0.0);
+        assert_eq!(probs[[0, 2]], 0.0);
+        assert!((probs[[0, 1]] + probs[[0, 3]] - 1.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Mask for action \"action\" does not allow any choices")]
+fn test_action_mask_without_choices() {
+    let mut features = HashMap::new();
+    features.insert("Head".to_string(), array![[3.0, 4.0]]);
+    features.insert("SnakeSegment".to_string(), array![[3.0, 4.0]]);
+    features.insert("Food".to_string(), array![[3.0, 5.0]]);
+    let mut action_masks = HashMap::new();
+    action_masks.insert("action".to_string(), array![[false, false, false, false]]);
+    RogueNet::load("test-data/simple").forward_action(
+        FwdArgs {
+            features,
+            actors: vec!["Head".to_string()],
+            action_masks,
+            ..Default::default()
+        },
+        "action",
+    );
+}
+
+#[test]
+fn test_softmax_negative_logits() {
+    let probs = softmax(&array![[-1000.0, -1001.0], [f32::NEG_INFINITY, -5.0]]);
+    let z = 1.0 + (-1.0f32).exp();
+    assert!(probs.abs_diff_eq(&array![[1.0 / z, (-1.0f32).exp() / z], [0.0, 1.0]], 1e-6));
+}
+
+#[test]
+fn test_save_checkpoint() {
+    fn flatten(
+        prefix: &str,
+        dict: &TensorDict,
+        tensors: &mut indexmap::IndexMap<String, (Vec<usize>, Vec<f32>)>,
+    ) {
+        match dict {
+            TensorDict::Tensor(Tensor::F32 { shape, data }) => {
+                tensors.insert(prefix.to_string(), (shape.clone(), data.clone()));
+            }
+            TensorDict::Tensor(Tensor::I64 { .. }) => unreachable!(),
+            TensorDict::Dict(dict) => {
+                for (key, value) in dict {
+                    let key = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    flatten(&key, value, tensors);
+                }
+            }
+        }
+    }
+
+    let original = RogueNet::load("test-data/simple");
+    let state_dict =
+        decode_state_dict(std::fs::File::open("test-data/simple/state.agent.msgpack").unwrap())
+            .unwrap();
+    let mut tensors = indexmap::IndexMap::new();
+    flatten("", &state_dict, &mut tensors);
+    let mut action_space = indexmap::IndexMap::new();
+    action_space.insert(
+        "action".to_string(),
+        ActionSpace::CategoricalActionSpace {
+            index_to_label: vec!["Up", "Down", "Left", "Right"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        },
+    );
+    let path = std::env::temp_dir().join(format!("rogue-net-checkpoint-{}", std::process::id()));
+    Checkpoint {
+        config: original.config.clone(),
+        obs_space: original.obs_space.clone(),
+        action_space,
+        step: 0,
+        tensors,
+    }
+    .save(&path)
+    .unwrap();
+    let saved = RogueNet::load(&path);
+    std::fs::remove_dir_all(&path).unwrap();
+
+    let mut features = HashMap::new();
+    features.insert("Head".to_string(), array![[3.0, 4.0]]);
+    features.insert("SnakeSegment".to_string(), array![[3.0, 4.0], [4.0, 4.0]]);
+    features.insert("Food".to_string(), array![[3.0, 5.0], [8.0, 4.0]]);
+    let args = FwdArgs {
+        features,
+        actors: vec!["Head".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(saved.forward(args.clone()).0, original.forward(args).0);
+}
+
+fn snake_args(features: [&str; 3]) -> FwdArgs {
+    let entity = |rows: Vec<[f32; 2]>| {
+        let mut x = Array2::zeros((rows.len(), 3));
+        for (i, [px, py]) in rows.into_iter().enumerate() {
+            for (j, feature) in features.iter().enumerate() {
+                x[[i, j]] = match *feature {
+                    "x" => px,
+                    "y" => py,
+                    _ => 7.0,
+                };
+            }
+        }
+        x
+    };
+    let mut args = FwdArgs {
+        actors: vec!["Head".to_string()],
+        ..Default::default()
+    };
+    args.features
+        .insert("Head".to_string(), entity(vec![[3.0, 4.0]]));
+    args.features.insert(
+        "SnakeSegment".to_string(),
+        entity(vec![[3.0, 4.0], [4.0, 4.0]]),
+    );
+    args.features
+        .insert("Food".to_string(), entity(vec![[3.0, 5.0], [8.0, 4.0]]));
+    args
+}
+
+#[test]
+fn test_save_round_trip() {
+    for checkpoint in ["test-data/simple", "test-data/relpos-encoding"] {
+        let original = RogueNet::load(checkpoint);
+        let path =
+            std::env::temp_dir().join(format!("rogue-net-round-trip-{}", std::process::id()));
+        original.save(&path).unwrap();
+        let saved = RogueNet::load(&path);
+        std::fs::remove_dir_all(&path).unwrap();
+        let mut archive = Vec::new();
+        original.save_archive(&mut archive).unwrap();
+        let archived = RogueNet::load_archive(&archive[..]).unwrap();
+
+        let mut args = snake_args(["x", "y", "z"]);
+        for x in args.features.values_mut() {
+            *x = x.slice(s![.., ..2]).to_owned();
+        }
+        let expected = original.forward(args.clone()).0;
+        for net in [saved, archived] {
+            let probs = net.forward(args.clone()).0;
+            assert!(
+                probs.abs_diff_eq(&expected, 1e-6),
+                "{:?} != {:?}",
+                probs,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn test_save_obs_filter() {
+    let received = ["z", "y", "x"];
+    let obs_filter = ["Head", "SnakeSegment", "Food"]
+        .iter()
+        .map(|e| {
+            (
+                e.to_string(),
+                received.iter().map(|f| f.to_string()).collect(),
+            )
+        })
+        .collect();
+    let filtered = RogueNet::load("test-data/simple").with_obs_filter(obs_filter);
+    let checkpoint = filtered.checkpoint();
+    assert_eq!(checkpoint.obs_space.entities["Head"].features, received);
+
+    let mut archive = Vec::new();
+    checkpoint.save_archive(&mut archive).unwrap();
+    let saved = RogueNet::load_archive(&archive[..]).unwrap();
+    let args = snake_args(received);
+    let expected = filtered.forward(args.clone()).0;
+    let probs = saved.forward(args).0;
+    assert!(
+        probs.abs_diff_eq(&expected, 1e-6),
+        "{:?} != {:?}",
+        probs,
+        expected
+    );
+}
+
+#[test]
+fn test_from_modified_checkpoint() {
+    let mut checkpoint = RogueNet::load("test-data/simple").checkpoint();
+    for key in [
+        "action_heads.action.proj.weight",
+        "action_heads.action.proj.bias",
+    ] {
+        checkpoint.tensors[key].1.fill(0.0);
+    }
+    let mut args = snake_args(["x", "y", "z"]);
+    for x in args.features.values_mut() {
+        *x = x.slice(s![.., ..2]).to_owned();
+    }
+    let probs = RogueNet::from_checkpoint(&checkpoint).forward(args).0;
+    assert_eq!(probs, array![[0.25, 0.25, 0.25, 0.25]]);
+}
+
+#[test]
+fn test_forward_batch() {
+    for checkpoint in ["test-data/simple", "test-data/relpos-encoding"] {
+        let rogue_net = RogueNet::load(checkpoint);
+        let batch = (0..4)
+            .map(|i| {
+                let mut features = HashMap::new();
+                features.insert("Head".to_string(), array![[i as f32, 4.0]]);
+                features.insert(
+                    "SnakeSegment".to_string(),
+                    Array2::from_shape_fn((i, 2), |(j, k)| (i + j + k) as f32),
+                );
+                features.insert("Food".to_string(), array![[3.0, 5.0], [8.0, i as f32]]);
+                let args = FwdArgs {
+                    features,
+                    actors: vec!["Head".to_string()],
+                    ..Default::default()
+                };
+                (args, vec!["action"])
+            })
+            .collect::<Vec<_>>();
+        let batched = rogue_net.forward_batch(batch.clone());
+        assert_eq!(batched.len(), batch.len());
+        for ((args, actions), result) in batch.into_iter().zip(batched) {
+            let (expected, _) = rogue_net.forward_actions(args, &actions).remove(0);
+            let (probs, acts) = &result.actions[0];
+            assert!(result.value.is_some());
+            assert_eq!(acts.len(), 1);
+            assert!(
+                probs.abs_diff_eq(&expected, 1e-6),
+                "{:?} != {:?}",
+                probs,
+                expected
+            );
+        }
+    }
+}
@@ -20,6 +20,7 @@ pub trait Action<'a> {
     fn labels() -> Vec<String>;
 }
 
+#[allow(dead_code)]
 mod expand {
     use entity_gym_derive::Action;
 
@@ -194,7 +195,7 @@ mod test {
     #[test]
     fn test_round_trip() {
         for thrust in [Thrust::Full, Thrust::Half, Thrust::None] {
-            for direction in vec![
+            for direction in [
                 Direction::Up,
                 Direction::Down,
                 Direction::Left,
@@ -0,0 +1,39 @@
+use super::ActionType;
+
+/// An action chosen by an actor together with the probabilities and value estimate that the agent computed for it.
+///
+/// Returned by [`Agent::act_multi_info_dyn`](super::Agent::act_multi_info_dyn) and [`AgentOps::act_info`](super::AgentOps::act_info).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionInfo<A> {
+    /// The chosen action.
+    pub action: A,
+    /// Probability of each choice, or `None` if the agent does not expose probabilities.
+    ///
+    /// For categorical actions, the probabilities are in the same order as [`Action::labels`](super::Action::labels).
+    /// For select entity actions, the probabilities are in the same order as the target entities in the observation.
+    pub probs: Option<Vec<f32>>,
+    /// The agent's estimate of the expected future return for the observation, or `None` if the agent has no value function.
+    ///
+    /// The estimate is the same for all actors in an observation.
+    pub value: Option<f32>,
+}
+
+impl ActionInfo<u64> {
+    /// Returns an [`ActionInfo`] without probabilities or value estimate.
+    pub fn from_action(action: u64) -> Self {
+        ActionInfo {
+            action,
+            probs: None,
+            value: None,
+        }
+    }
+
+    /// Converts the raw action into the typed action of `A`.
+    pub fn into_typed<A: ActionType>(self) -> ActionInfo<A::Output> {
+        ActionInfo {
+            action: A::from_raw(self.action),
+            probs: self.probs,
+            value: self.value,
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use super::{Action, EntityRef, Featurizable, Obs, SelectEntity};
+
+/// Describes an action that is requested from an [`Agent`](super::Agent).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionRequest<'a> {
+    /// Each actor chooses one of `num_actions` choices of a categorical action.
+    Categorical {
+        /// Name of the action.
+        name: &'a str,
+        /// Number of choices.
+        num_actions: u64,
+    },
+    /// Each actor selects one of the entities of type `target`.
+    SelectEntity {
+        /// Name of the action.
+        name: &'a str,
+        /// Name of the entity type that can be selected.
+        target: &'a str,
+    },
+}
+
+impl<'a> ActionRequest<'a> {
+    /// Returns the name of the requested action.
+    pub fn name(&self) -> &'a str {
+        match self {
+            ActionRequest::Categorical { name, .. } => name,
+            ActionRequest::SelectEntity { name, .. } => name,
+        }
+    }
+}
+
+/// Marker type which requests the [`SelectEntity`] action `A` from an [`Agent`](super::Agent).
+///
+/// The selected entities are returned as [`EntityRef`]s.
+pub struct Select<A> {
+    phantom: PhantomData<fn() -> A>,
+}
+
+/// A single type of action that can be requested from an [`Agent`](super::Agent).
+///
+/// Implemented for all [`Action`]s and for [`Select<A>`] where `A` is a [`SelectEntity`] action.
+pub trait ActionType {
+    /// The action chosen by each actor.
+    type Output;
+
+    /// Describes the action.
+    fn request() -> ActionRequest<'static>;
+
+    /// Converts a raw action returned by an [`Agent`](super::Agent) into a typed action.
+    fn from_raw(raw: u64) -> Self::Output;
+}
+
+impl<A: Action<'static>> ActionType for A {
+    type Output = A;
+
+    fn request() -> ActionRequest<'static> {
+        ActionRequest::Categorical {
+            name: A::name(),
+            num_actions: A::num_actions(),
+        }
+    }
+
+    fn from_raw(raw: u64) -> A {
+        A::from_u64(raw)
+    }
+}
+
+impl<A: SelectEntity> ActionType for Select<A> {
+    type Output = EntityRef<A::Target>;
+
+    fn request() -> ActionRequest<'static> {
+        ActionRequest::SelectEntity {
+            name: A::name(),
+            target: A::Target::name(),
+        }
+    }
+
+    fn from_raw(raw: u64) -> EntityRef<A::Target> {
+        EntityRef::new(raw as usize)
+    }
+}
+
+/// One or more types of actions that are requested from an [`Agent`](super::Agent) for the same observation.
+///
+/// Implemented for every [`ActionType`] and for tuples of up to 8 [`ActionType`]s.
+/// The actions chosen by the actors are returned separately for each action type.
+///
+/// Can also be derived for structs with one field per action type, see the second example.
+///
+/// # Example
+/// ```rust
+/// use entity_gym_rs::agent::{self, Action, AgentOps, EntityRef, Featurizable, Obs, Select, SelectEntity};
+///
+/// #[derive(Featurizable)]
+/// struct Player { x: i32, y: i32 }
+///
+/// #[derive(Featurizable)]
+/// struct Enemy { x: i32, y: i32 }
+///
+/// #[derive(Action, Debug)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// struct Attack;
+///
+/// impl SelectEntity for Attack {
+///     type Target = Enemy;
+///
+///     fn name() -> &'static str {
+///         "Attack"
+///     }
+/// }
+///
+/// let obs = Obs::new(0.0)
+///     .actors([Player { x: 0, y: 0 }])
+///     .entities([Enemy { x: 1, y: 0 }, Enemy { x: 0, y: 2 }]);
+/// let mut agent = agent::random();
+/// let (moves, targets): (Vec<Move>, Vec<EntityRef<Enemy>>) =
+///     agent.act::<(Move, Select<Attack>)>(&obs).unwrap();
+/// ```
+///
+/// When derived for a struct, the struct itself is returned with the actions chosen by the actors.
+/// Every field must either be a `Vec` of an [`Action`], or be annotated with its [`ActionType`],
+/// such as `#[action(Select<Attack>)]`. Actions keyed by id are returned as a tuple with one map per field.
+///
+/// ```rust
+/// # use entity_gym_rs::agent::{Featurizable, SelectEntity};
+/// # #[derive(Featurizable)]
+/// # struct Player { x: i32, y: i32 }
+/// # #[derive(Featurizable)]
+/// # struct Enemy { x: i32, y: i32 }
+/// # struct Attack;
+/// # impl SelectEntity for Attack {
+/// #     type Target = Enemy;
+/// #     fn name() -> &'static str { "Attack" }
+/// # }
+/// use entity_gym_rs::agent::{self, Action, ActionSet, AgentOps, EntityRef, Obs, Select};
+///
+/// #[derive(Action, Debug)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(ActionSet)]
+/// struct PlayerActions {
+///     moves: Vec<Move>,
+///     #[action(Select<Attack>)]
+///     targets: Vec<EntityRef<Enemy>>,
+/// }
+///
+/// let obs = Obs::new(0.0)
+///     .actors([Player { x: 0, y: 0 }])
+///     .entities([Enemy { x: 1, y: 0 }, Enemy { x: 0, y: 2 }]);
+/// let actions = agent::random().act::<PlayerActions>(&obs).unwrap();
+/// assert_eq!(actions.moves.len(), 1);
+/// assert_eq!(actions.targets.len(), 1);
+/// ```
+pub trait ActionSet {
+    /// The actions chosen by the actors.
+    type Output;
+
+    /// The actions chosen by the actors, keyed by the id of each actor.
+    type Keyed;
+
+    /// Describes the requested actions.
+    fn requests() -> Vec<ActionRequest<'static>>;
+
+    /// Converts the raw actions returned by an [`Agent`](super::Agent) for each requested action into typed actions.
+    fn from_raw(raw: Vec<Vec<u64>>) -> Self::Output;
+
+    /// Associates the actions with the ids of the actors in `obs` that chose them.
+    ///
+    /// Panics if any of the actors was added without an id.
+    fn key_by_id(output: Self::Output, obs: &Obs) -> Self::Keyed;
+}
+
+impl<A: ActionType> ActionSet for A {
+    type Output = Vec<A::Output>;
+    type Keyed = HashMap<u64, A::Output>;
+
+    fn requests() -> Vec<ActionRequest<'static>> {
+        vec![A::request()]
+    }
+
+    fn from_raw(raw: Vec<Vec<u64>>) -> Self::Output {
+        let raw = raw.into_iter().next().expect("Missing actions");
+        raw.into_iter().map(A::from_raw).collect()
+    }
+
+    fn key_by_id(output: Self::Output, obs: &Obs) -> Self::Keyed {
+        key_by_id::<A>(output, obs)
+    }
+}
+
+fn key_by_id<A: ActionType>(output: Vec<A::Output>, obs: &Obs) -> HashMap<u64, A::Output> {
+    obs.actor_ids(A::request().name())
+        .into_iter()
+        .zip(output)
+        .collect()
+}
+
+macro_rules! impl_action_set_for_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: ActionType),+> ActionSet for ($($t,)+) {
+            type Output = ($(Vec<$t::Output>,)+);
+            type Keyed = ($(HashMap<u64, $t::Output>,)+);
+
+            fn requests() -> Vec<ActionRequest<'static>> {
+                vec![$($t::request()),+]
+            }
+
+            fn from_raw(raw: Vec<Vec<u64>>) -> Self::Output {
+                let mut raw = raw.into_iter();
+                ($(
+                    raw.next()
+                        .expect("Missing actions")
+                        .into_iter()
+                        .map($t::from_raw)
+                        .collect(),
+                )+)
+            }
+
+            #[allow(non_snake_case)]
+            fn key_by_id(output: Self::Output, obs: &Obs) -> Self::Keyed {
+                let ($($t,)+) = output;
+                ($(key_by_id::<$t>($t, obs),)+)
+            }
+        }
+    };
+}
+
+impl_action_set_for_tuple!(A);
+impl_action_set_for_tuple!(A, B);
+impl_action_set_for_tuple!(A, B, C);
+impl_action_set_for_tuple!(A, B, C, D);
+impl_action_set_for_tuple!(A, B, C, D, E);
+impl_action_set_for_tuple!(A, B, C, D, E, F);
+impl_action_set_for_tuple!(A, B, C, D, E, F, G);
+impl_action_set_for_tuple!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{ActionSet, AgentOps, RandomAgent};
+
+    #[derive(Featurizable)]
+    struct Unit {
+        x: f32,
+    }
+
+    #[derive(Action, Debug, Clone, Copy, PartialEq, Eq)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    struct Attack;
+
+    impl SelectEntity for Attack {
+        type Target = Unit;
+
+        fn name() -> &'static str {
+            "Attack"
+        }
+    }
+
+    #[derive(ActionSet)]
+    struct UnitActions {
+        moves: Vec<Move>,
+        #[action(Select<Attack>)]
+        targets: Vec<EntityRef<Unit>>,
+    }
+
+    #[derive(ActionSet)]
+    struct Moves(Vec<Move>);
+
+    #[test]
+    fn test_derive() {
+        assert_eq!(
+            UnitActions::requests(),
+            [Move::request(), Select::<Attack>::request()]
+        );
+        let obs = Obs::new(0.0)
+            .actors_with_ids([(4, Unit { x: 0.0 }), (9, Unit { x: 1.0 })])
+            .action_mask::<Move>([[true, false], [false, true]]);
+        let actions = RandomAgent::default().act::<UnitActions>(&obs).unwrap();
+        assert_eq!(actions.moves, [Move::Left, Move::Right]);
+        assert_eq!(actions.targets.len(), 2);
+
+        let (moves, targets) = RandomAgent::default()
+            .act_by_id::<UnitActions>(&obs)
+            .unwrap();
+        assert_eq!(moves[&9], Move::Right);
+        assert!(targets.contains_key(&4));
+
+        let Moves(moves) = Moves::from_raw(vec![vec![1, 0]]);
+        assert_eq!(moves, [Move::Right, Move::Left]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+use rogue_net::FwdArgs;
+
+use super::{
+    ActionInfo, ActionReceiver, ActionRequest, Agent, InnerActionReceiver, Memory, Obs,
+    RogueNetAgent,
+};
+
+/// [`RogueNetAgent`] that evaluates the observations of many agents in a single forward pass.
+///
+/// All clones of a [`BatchedRogueNetAgent`] share the same network and queue of pending observations.
+/// Calling [`AgentOps::act_async`](super::AgentOps::act_async) only enqueues the observation.
+/// The first [`ActionReceiver`] that is awaited (or an explicit call to [`BatchedRogueNetAgent::flush`])
+/// runs one forward pass for every queued observation and resolves all of their receivers.
+///
+/// If the [`RogueNetAgent`] has a [`Memory`], every clone keeps its own copy of the memory.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{Action, AgentOps, BatchedRogueNetAgent, Featurizable, Obs, RogueNetAgent};
+///
+/// #[derive(Action, Debug)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+/// #[derive(Featurizable)]
+/// struct SnakeSegment { x: f32, y: f32 }
+/// #[derive(Featurizable)]
+/// struct Food { x: f32, y: f32 }
+///
+/// let agent = BatchedRogueNetAgent::new(RogueNetAgent::load("rogue-net/test-data/simple").unwrap());
+/// let mut players = vec![agent.clone(), agent.clone(), agent];
+/// let receivers = players
+///     .iter_mut()
+///     .enumerate()
+///     .map(|(i, player)| {
+///         let obs = Obs::new(0.0)
+///             .actors([Head { x: i as f32, y: 4.0 }])
+///             .entities([SnakeSegment { x: 3.0, y: 4.0 }])
+///             .entities([Food { x: 3.0, y: 5.0 }]);
+///         player.act_async::<Move>(&obs)
+///     })
+///     .collect::<Vec<_>>();
+/// // Awaiting the first receiver runs a single forward pass for all three observations.
+/// for receiver in receivers {
+///     assert_eq!(receiver.rcv().unwrap().len(), 1);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct BatchedRogueNetAgent {
+    batch: Arc<Mutex<Batch>>,
+    memory: Option<Box<dyn Memory>>,
+}
+
+pub(crate) struct Batch {
+    agent: RogueNetAgent,
+    pending: Vec<PendingRequest>,
+}
+
+struct PendingRequest {
+    args: FwdArgs,
+    heads: Vec<String>,
+    sender: Sender<Vec<Vec<ActionInfo<u64>>>>,
+}
+
+impl BatchedRogueNetAgent {
+    /// Creates a batched agent that evaluates observations with the given [`RogueNetAgent`].
+    pub fn new(agent: RogueNetAgent) -> Self {
+        BatchedRogueNetAgent {
+            memory: agent.memory.clone(),
+            batch: Arc::new(Mutex::new(Batch {
+                agent,
+                pending: vec![],
+            })),
+        }
+    }
+
+    /// Runs a single forward pass for all pending observations and resolves their [`ActionReceiver`]s.
+    pub fn flush(&self) {
+        Batch::flush(&self.batch);
+    }
+
+    /// Returns the number of observations that are waiting for the next forward pass.
+    pub fn pending(&self) -> usize {
+        self.batch.lock().unwrap().pending.len()
+    }
+}
+
+impl BatchedRogueNetAgent {
+    fn enqueue(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Receiver<Vec<Vec<ActionInfo<u64>>>> {
+        let (sender, receiver) = bounded(1);
+        let mut batch = self.batch.lock().unwrap();
+        let (mut args, heads) = batch.agent.request_args(actions, obs);
+        if let Some(memory) = &mut self.memory {
+            memory.observe(obs, &mut args.features);
+        }
+        batch.pending.push(PendingRequest {
+            args,
+            heads,
+            sender,
+        });
+        receiver
+    }
+}
+
+impl Batch {
+    pub(crate) fn flush(batch: &Mutex<Batch>) {
+        // The lock is held for the whole forward pass, so once `flush` returns all requests
+        // that were pending when it was called have been resolved.
+        let mut batch = batch.lock().unwrap();
+        if batch.pending.is_empty() {
+            return;
+        }
+        let pending = std::mem::take(&mut batch.pending);
+        let mut args = Vec::with_capacity(pending.len());
+        let mut heads = Vec::with_capacity(pending.len());
+        let mut senders = Vec::with_capacity(pending.len());
+        for request in pending {
+            args.push(request.args);
+            heads.push(request.heads);
+            senders.push(request.sender);
+        }
+        let inputs = args
+            .into_iter()
+            .zip(&heads)
+            .map(|(args, heads)| (args, heads.iter().map(String::as_str).collect()))
+            .collect();
+        let results = batch.agent.net.forward_batch_probs(inputs);
+        for (sender, result) in senders.into_iter().zip(results) {
+            let infos = batch.agent.action_infos(result);
+            // The receiver may have been dropped without being awaited.
+            let _ = sender.send(infos);
+        }
+    }
+}
+
+impl Agent for BatchedRogueNetAgent {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        self.act_multi_async_dyn(actions, obs).rcv_raw_multi()
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        ActionReceiver {
+            inner: InnerActionReceiver::Batched {
+                receiver: self.enqueue(actions, obs),
+                batch: self.batch.clone(),
+            },
+        }
+    }
+
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        let receiver = self.enqueue(actions, obs);
+        self.flush();
+        receiver.recv().ok()
+    }
+
+    fn game_over(&mut self, _: &Obs) {
+        if let Some(memory) = &mut self.memory {
+            memory.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Action, AgentOps, Featurizable};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct SnakeSegment {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Action, Debug, PartialEq, Eq)]
+    enum Direction {
+        Up,
+        Down,
+        Left,
+        Right,
+    }
+
+    fn obs(i: usize) -> Obs {
+        Obs::new(0.0)
+            .actors([Head {
+                x: i as f32,
+                y: 4.0,
+            }])
+            .entities((0..i).map(|j| SnakeSegment {
+                x: j as f32,
+                y: 4.0,
+            }))
+            .entities([Food { x: 3.0, y: 5.0 }, Food { x: 8.0, y: 4.0 }])
+    }
+
+    #[test]
+    fn test_batched_act() {
+        let agent =
+            BatchedRogueNetAgent::new(RogueNetAgent::load("rogue-net/test-data/simple").unwrap());
+        let mut players = vec![agent.clone(); 5];
+        let receivers = players
+            .iter_mut()
+            .enumerate()
+            .map(|(i, player)| {
+                let obs = obs(i).action_mask::<Direction>([[
+                    i % 4 == 0,
+                    i % 4 == 1,
+                    i % 4 == 2,
+                    i % 4 == 3,
+                ]]);
+                player.act_async::<Direction>(&obs)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(agent.pending(), 5);
+        let expected = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+        ];
+        for (receiver, expected) in receivers.into_iter().rev().zip(expected.into_iter().rev()) {
+            assert_eq!(receiver.rcv().unwrap(), [expected]);
+            assert_eq!(agent.pending(), 0);
+        }
+    }
+}
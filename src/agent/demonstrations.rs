@@ -0,0 +1,335 @@
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+use crate::low_level::{Action, ActionMask, ActionSpace, ObsSpace, Observation};
+
+use super::training::{observation, remember};
+use super::{Memory, ObsNormalizer, RecordedRequest, TrajectoryEvent, TrajectoryReader};
+
+/// Supervised dataset of observations and the actions that a demonstrator chose for them.
+///
+/// The dataset is created with [`TrainEnvBuilder::demonstrations`](super::TrainEnvBuilder::demonstrations) and filled with
+/// trajectories recorded with e.g. a [`HumanAgent`](super::HumanAgent) or [`TrajectoryRecorder`](super::TrajectoryRecorder).
+/// Observations and actions use the observation and action space of the [`TrainEnvBuilder`](super::TrainEnvBuilder),
+/// so they are laid out exactly like the observations and actions that enn-trainer exchanges with the training environment.
+/// This allows policies to be pretrained on demonstrations before they are trained with reinforcement learning.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{Action, ActionRequest, Featurizable, Obs, TrainEnvBuilder, TrajectoryReader, TrajectoryWriter};
+///
+/// #[derive(Action, Debug)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let path = std::env::temp_dir().join(format!("demonstrations-doctest-{}.traj", std::process::id()));
+/// let mut writer = TrajectoryWriter::create(&path).unwrap();
+/// let obs = Obs::new(0.0).actors([Head { x: 1.0, y: 2.0 }]);
+/// let request = ActionRequest::Categorical { name: "Move", num_actions: 4 };
+/// writer.write_step(&obs, &[request], Some(&[vec![2]])).unwrap();
+/// writer.flush().unwrap();
+///
+/// let mut dataset = TrainEnvBuilder::default().entity::<Head>().action::<Move>().demonstrations();
+/// dataset.add_trajectory(TrajectoryReader::open(&path).unwrap()).unwrap();
+/// assert_eq!(dataset.samples[0].observation.features.data, [1.0, 2.0]);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct DemonstrationDataset {
+    /// Entity types and features of the observations.
+    pub obs_space: ObsSpace,
+    /// Names and types of the actions.
+    pub action_space: Vec<(String, ActionSpace)>,
+    /// Observations and chosen actions, in the order in which they were recorded.
+    pub samples: Vec<Demonstration>,
+    episodes: usize,
+    normalizer: Option<Arc<Mutex<ObsNormalizer>>>,
+    memory: Option<Box<dyn Memory>>,
+}
+
+/// An observation and the actions that a demonstrator chose for it.
+pub struct Demonstration {
+    /// The observation. The reward is the change in score since the previous observation of the episode.
+    pub observation: Observation,
+    /// Chosen actions for each action in the action space, or `None` for actions that were not requested.
+    ///
+    /// Actors and selected entities are identified by their position in the flattened entities of the observation.
+    pub actions: Vec<Option<Action>>,
+    /// Index of the episode that the observation belongs to.
+    pub episode: usize,
+}
+
+impl DemonstrationDataset {
+    pub(crate) fn new(
+        obs_space: ObsSpace,
+        action_space: Vec<(String, ActionSpace)>,
+        normalizer: Option<Arc<Mutex<ObsNormalizer>>>,
+        memory: Option<Box<dyn Memory>>,
+    ) -> Self {
+        DemonstrationDataset {
+            obs_space,
+            action_space,
+            samples: vec![],
+            episodes: 0,
+            normalizer,
+            memory,
+        }
+    }
+
+    /// Returns the number of episodes that the samples belong to.
+    pub fn episodes(&self) -> usize {
+        self.episodes
+    }
+
+    /// Adds every step of a recorded trajectory for which actions were recorded.
+    ///
+    /// Every game over and the end of the trajectory end an episode.
+    /// The memory of the dataset observes all steps, including those without actions, and is reset at the end of every episode.
+    pub fn add_trajectory<R: Read>(
+        &mut self,
+        trajectory: TrajectoryReader<R>,
+    ) -> Result<(), io::Error> {
+        let entity_names = self
+            .obs_space
+            .entities
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let action_names = self
+            .action_space
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let normalizer = self.normalizer.as_ref().map(|n| n.lock().unwrap());
+        if let Some(memory) = &mut self.memory {
+            memory.reset();
+        }
+        // Whether any samples were added since the last episode ended.
+        let mut in_episode = false;
+        for event in trajectory {
+            match event? {
+                TrajectoryEvent::Step {
+                    obs,
+                    score_delta,
+                    requests,
+                    actions,
+                } => {
+                    // Features are already normalized if the memory was applied.
+                    let (obs, normalizer) = match &mut self.memory {
+                        Some(memory) => {
+                            (remember(memory.as_mut(), &obs, normalizer.as_deref()), None)
+                        }
+                        None => (obs, normalizer.as_deref()),
+                    };
+                    let chosen = match actions {
+                        Some(chosen) => chosen,
+                        None => continue,
+                    };
+                    let requests = requests
+                        .iter()
+                        .map(RecordedRequest::as_request)
+                        .collect::<Vec<_>>();
+                    let (mut observation, requested) =
+                        observation(&entity_names, &action_names, &requests, &obs, normalizer);
+                    observation.reward = score_delta;
+                    let mut actions = vec![None; action_names.len()];
+                    for (chosen, (index, offset)) in chosen.into_iter().zip(requested) {
+                        actions[index] = match &observation.actions[index] {
+                            Some(ActionMask::DenseCategorical { actors, .. }) => {
+                                Some(Action::Categorical {
+                                    actors: actors.clone(),
+                                    action: chosen.into_iter().map(|a| a as usize).collect(),
+                                })
+                            }
+                            Some(ActionMask::SelectEntity { actors, .. }) => {
+                                Some(Action::SelectEntity {
+                                    actors: actors.clone(),
+                                    actees: chosen.into_iter().map(|a| a + offset).collect(),
+                                })
+                            }
+                            None => None,
+                        };
+                    }
+                    self.samples.push(Demonstration {
+                        observation,
+                        actions,
+                        episode: self.episodes,
+                    });
+                    in_episode = true;
+                }
+                TrajectoryEvent::GameOver { .. } => {
+                    if let Some(memory) = &mut self.memory {
+                        memory.reset();
+                    }
+                    if in_episode {
+                        self.episodes += 1;
+                        in_episode = false;
+                    }
+                }
+            }
+        }
+        if in_episode {
+            self.episodes += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{
+        Action, ActionRequest, AgentOps, Featurizable, LastSeen, Obs, Select, SelectEntity,
+        TrainAgent, TrainEnvBuilder, TrajectoryWriter,
+    };
+    use crate::low_level::Action as LowLevelAction;
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Action)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    struct Bite;
+
+    impl SelectEntity for Bite {
+        type Target = Head;
+
+        fn name() -> &'static str {
+            "Bite"
+        }
+    }
+
+    fn obs() -> Obs {
+        Obs::new(1.0)
+            .actors([Head { x: 1.0 }, Head { x: 2.0 }])
+            .entities([Food { x: 3.0, y: 4.0 }])
+    }
+
+    fn builder() -> TrainEnvBuilder {
+        TrainEnvBuilder::default()
+            .entity::<Food>()
+            .entity::<Head>()
+            .select_entity::<Bite>()
+            .action::<Move>()
+    }
+
+    fn run(_: (), mut agent: TrainAgent, _: u64) {
+        while agent.act::<(Move, Select<Bite>)>(&obs()).is_some() {}
+    }
+
+    #[test]
+    fn test_demonstrations() {
+        let path =
+            std::env::temp_dir().join(format!("test-demonstrations-{}.traj", std::process::id()));
+        let requests = [
+            ActionRequest::Categorical {
+                name: "Move",
+                num_actions: Move::num_actions(),
+            },
+            ActionRequest::SelectEntity {
+                name: "Bite",
+                target: "Head",
+            },
+        ];
+        let mut writer = TrajectoryWriter::create(&path).unwrap();
+        writer
+            .write_step(&obs(), &requests, Some(&[vec![1, 0], vec![1, 0]]))
+            .unwrap();
+        writer.write_step(&obs(), &requests, None).unwrap();
+        writer.write_game_over(&Obs::new(3.0)).unwrap();
+        writer.write_game_over(&Obs::new(0.0)).unwrap();
+        writer
+            .write_step(&obs(), &requests[..1], Some(&[vec![0, 1]]))
+            .unwrap();
+        writer.flush().unwrap();
+
+        let mut dataset = builder().demonstrations();
+        dataset
+            .add_trajectory(TrajectoryReader::open(&path).unwrap())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dataset.samples.len(), 2);
+        assert_eq!(dataset.episodes(), 2);
+
+        // Features are in the same order as the observations of the training environment.
+        let mut env = builder().build_vec_env((), run, 1, 1, 0);
+        let expected = &env.reset()[0].features;
+        let sample = &dataset.samples[0];
+        assert_eq!(sample.observation.features.counts, expected.counts);
+        assert_eq!(sample.observation.features.data, expected.data);
+        assert_eq!(sample.episode, 0);
+        match &sample.actions[..] {
+            [Some(LowLevelAction::SelectEntity { actors, actees }), Some(LowLevelAction::Categorical { action, .. })] =>
+            {
+                assert_eq!(actors, &[1, 2]);
+                assert_eq!(actees, &[2, 1]);
+                assert_eq!(action, &[1, 0]);
+            }
+            _ => panic!("unexpected actions"),
+        }
+
+        let sample = &dataset.samples[1];
+        assert_eq!(sample.episode, 1);
+        assert_eq!(sample.observation.reward, 0.0);
+        assert!(sample.actions[0].is_none());
+        assert!(
+            matches!(&sample.actions[1], Some(LowLevelAction::Categorical { action, .. }) if action == &[0, 1])
+        );
+    }
+
+    #[test]
+    fn test_demonstrations_memory() {
+        let path = std::env::temp_dir().join(format!(
+            "test-demonstrations-memory-{}.traj",
+            std::process::id()
+        ));
+        let requests = [ActionRequest::Categorical {
+            name: "Move",
+            num_actions: Move::num_actions(),
+        }];
+        let without_food = || Obs::new(0.0).actors([Head { x: 1.0 }, Head { x: 2.0 }]);
+        let mut writer = TrajectoryWriter::create(&path).unwrap();
+        // The food is only visible in a step without recorded actions.
+        writer
+            .write_step(
+                &without_food().entities_with_ids([(9, Food { x: 3.0, y: 4.0 })]),
+                &requests,
+                None,
+            )
+            .unwrap();
+        writer
+            .write_step(&without_food(), &requests, Some(&[vec![0, 1]]))
+            .unwrap();
+        writer.write_game_over(&Obs::new(0.0)).unwrap();
+        writer
+            .write_step(&without_food(), &requests, Some(&[vec![1, 1]]))
+            .unwrap();
+        writer.flush().unwrap();
+
+        let mut dataset = builder().memory(LastSeen::new::<Food>()).demonstrations();
+        dataset
+            .add_trajectory(TrajectoryReader::open(&path).unwrap())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dataset.obs_space.entities[2].0, "FoodLastSeen");
+        assert_eq!(dataset.samples.len(), 2);
+        let features = &dataset.samples[0].observation.features;
+        assert_eq!(features.counts, [0, 2, 1]);
+        assert_eq!(features.data, [1.0, 2.0, 3.0, 4.0, 1.0]);
+        // The memory is reset at game over.
+        assert_eq!(dataset.samples[1].observation.features.counts, [0, 2, 0]);
+    }
+}
@@ -1,13 +1,72 @@
 /// A data structure that can be serialized into a data format that can be processed by a neural network.
 ///
-/// Can be derived for structs where all fields are numeric, boolean, or [`Featurizable`].
+/// Can be derived for structs, tuple structs and enums where all fields are numeric, boolean, arrays, tuples, `Option`s, or [`Featurizable`].
+/// Fields of tuple structs and tuples are named by their position.
+/// An `Option<T>` is featurized as an `is_some` flag followed by the features of `T`, which are zero for `None`.
+/// With the `bevy` feature, [`Featurizable`] is also implemented for the `Vec2`, `Vec3` and `Quat` types in `bevy::math`.
+/// Enums are featurized as a one-hot encoding of the variant, followed by the payloads of all variants, where the payloads of inactive variants are zero.
+///
+/// Fields can be customized with `#[featurize(...)]` attributes:
+/// - `skip` excludes the field from the features.
+/// - `rename = "name"` changes the feature name of the field, e.g. to keep the features of a trained network stable when renaming a field.
+/// - `scale = <factor>`, `offset = <offset>` and `clamp(<min>, <max>)` normalize the field's features, which are first multiplied by `scale`, then shifted by `offset` and finally clamped to `[min, max]`.
+///   Feature names are not affected by these attributes. For `Option` fields, they only apply to the features of `Some` values,
+///   the `is_some` flag and the zeros of `None` are left unchanged.
+/// - `one_hot = <n>` encodes an integer field as `n` features `is_0` to `is_<n-1>`, values outside of `0..n` are encoded as all zeros.
+/// - `buckets(<b0>, <b1>, ...)` encodes a numeric field as a one-hot vector over the intervals delimited by the ascending boundaries,
+///   with features `lt_<b0>`, `<b0>_to_<b1>`, ..., `ge_<bn>`.
 ///
 /// # Example
 /// ```rust
 /// use entity_gym_rs::agent::Featurizable;
 ///
 /// #[derive(Featurizable)]
-/// struct Player { x: i32, y: i32, is_alive: bool }
+/// struct Player { x: i32, y: i32, is_alive: bool, weapon: Weapon }
+///
+/// #[derive(Featurizable)]
+/// struct Health(u32);
+///
+/// #[derive(Featurizable)]
+/// enum Weapon { Sword { dmg: f32 }, Bow { range: f32 }, Fists }
+///
+/// assert_eq!(
+///     Weapon::feature_names(),
+///     ["is_Sword", "is_Bow", "is_Fists", "Sword.dmg", "Bow.range"],
+/// );
+/// assert_eq!(Weapon::Bow { range: 5.0 }.featurize(), [0.0, 1.0, 0.0, 0.0, 5.0]);
+///
+/// #[derive(Featurizable)]
+/// struct Unit {
+///     #[featurize(skip)]
+///     last_seen_frame: u64,
+///     #[featurize(rename = "hp", scale = 0.01, clamp(0.0, 1.0))]
+///     health: u32,
+///     #[featurize(offset = -50.0)]
+///     x: f32,
+/// }
+///
+/// assert_eq!(Unit::feature_names(), ["hp", "x"]);
+///
+/// #[derive(Featurizable)]
+/// struct Soldier {
+///     #[featurize(one_hot = 3)]
+///     team: u8,
+///     #[featurize(buckets(10, 100))]
+///     range: f32,
+/// }
+///
+/// assert_eq!(
+///     Soldier::feature_names(),
+///     ["team.is_0", "team.is_1", "team.is_2", "range.lt_10", "range.10_to_100", "range.ge_100"],
+/// );
+/// assert_eq!(Soldier { team: 1, range: 25.0 }.featurize(), [0.0, 1.0, 0.0, 0.0, 1.0, 0.0]);
+///
+/// #[derive(Featurizable)]
+/// struct Turret { pos: (i32, i32), target: Option<f32> }
+///
+/// assert_eq!(Turret::feature_names(), ["pos.0", "pos.1", "target.is_some", "target"]);
+/// assert_eq!(Turret { pos: (3, 4), target: None }.featurize(), [3.0, 4.0, 0.0, 0.0]);
+/// assert_eq!(Unit { last_seen_frame: 7, health: 250, x: 60.0 }.featurize(), [1.0, 10.0]);
 /// ```
 pub trait Featurizable {
     /// Returns the number of features after conversion to a vector.
@@ -20,7 +79,7 @@ pub trait Featurizable {
     fn name() -> &'static str;
 }
 
-impl<'a, T: Featurizable> Featurizable for &'a T {
+impl<T: Featurizable> Featurizable for &T {
     fn num_feats() -> usize {
         T::num_feats()
     }
@@ -38,6 +97,37 @@ impl<'a, T: Featurizable> Featurizable for &'a T {
     }
 }
 
+#[cfg(feature = "bevy")]
+macro_rules! impl_featurizable_for_bevy_math {
+    ($ty:ident, [$($feature:literal),+]) => {
+        #[cfg_attr(docsrs, doc(cfg(feature = "bevy")))]
+        impl Featurizable for bevy::math::$ty {
+            fn num_feats() -> usize {
+                [$($feature),+].len()
+            }
+
+            fn feature_names() -> Vec<String> {
+                vec![$($feature.to_string()),+]
+            }
+
+            fn featurize(&self) -> Vec<f32> {
+                self.to_array().to_vec()
+            }
+
+            fn name() -> &'static str {
+                stringify!($ty)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "bevy")]
+impl_featurizable_for_bevy_math!(Vec2, ["x", "y"]);
+#[cfg(feature = "bevy")]
+impl_featurizable_for_bevy_math!(Vec3, ["x", "y", "z"]);
+#[cfg(feature = "bevy")]
+impl_featurizable_for_bevy_math!(Quat, ["x", "y", "z", "w"]);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -58,6 +148,67 @@ mod test {
         None,
     }
 
+    #[derive(Featurizable)]
+    struct Vec2(f32, f32);
+
+    #[derive(Featurizable)]
+    struct Health(u32);
+
+    #[derive(Featurizable)]
+    enum Weapon {
+        Sword { dmg: f32, sharp: bool },
+        Bow(Vec2),
+        Fists,
+    }
+
+    #[derive(Featurizable)]
+    struct Sensor {
+        #[featurize(skip)]
+        #[allow(dead_code)]
+        id: u64,
+        #[featurize(rename = "temp", scale = 0.5, offset = -1.0)]
+        temperature: f32,
+        #[featurize(clamp(-1, 1))]
+        readings: [i32; 3],
+        #[featurize(rename = "location")]
+        pos: Pos,
+    }
+
+    #[derive(Featurizable)]
+    enum Signal {
+        Ping {
+            #[featurize(scale = 0.001)]
+            latency_ms: u32,
+        },
+        Silent(#[featurize(skip)] u8),
+    }
+
+    #[derive(Featurizable)]
+    struct Projectile {
+        velocity: (f32, f32),
+        target: Option<Pos>,
+        fuse: Option<u32>,
+        trail: [Option<bool>; 2],
+    }
+
+    #[derive(Featurizable)]
+    struct Beacon {
+        #[featurize(offset = 10.0)]
+        shift: Option<f32>,
+        #[featurize(scale = 2, clamp(-1, 1))]
+        signals: [Option<f32>; 2],
+    }
+
+    #[derive(Featurizable)]
+    struct Tile {
+        #[featurize(one_hot = 4)]
+        kind: u32,
+        #[featurize(one_hot = 2, rename = "side")]
+        team: i8,
+        #[featurize(buckets(-1.0, 0.5, 2))]
+        height: f32,
+    }
+
     #[derive(Featurizable)]
     struct Hero {
         pos: Pos,
@@ -126,6 +277,170 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_tuple_struct() {
+        assert_eq!(Vec2::num_feats(), 2);
+        assert_eq!(Vec2::feature_names(), &["0", "1"]);
+        assert_eq!(Vec2(1.0, 2.0).featurize(), vec![1.0, 2.0]);
+        assert_eq!(Health::num_feats(), 1);
+        assert_eq!(Health::feature_names(), &["0"]);
+        assert_eq!(Health(7).featurize(), vec![7.0]);
+    }
+
+    #[test]
+    fn test_data_enum() {
+        assert_eq!(Weapon::num_feats(), 7);
+        assert_eq!(
+            Weapon::feature_names(),
+            &[
+                "is_Sword",
+                "is_Bow",
+                "is_Fists",
+                "Sword.dmg",
+                "Sword.sharp",
+                "Bow.0.0",
+                "Bow.0.1",
+            ]
+        );
+        assert_eq!(
+            Weapon::Sword {
+                dmg: 3.0,
+                sharp: true
+            }
+            .featurize(),
+            vec![1.0, 0.0, 0.0, 3.0, 1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            Weapon::Bow(Vec2(4.0, 5.0)).featurize(),
+            vec![0.0, 1.0, 0.0, 0.0, 0.0, 4.0, 5.0]
+        );
+        assert_eq!(
+            Weapon::Fists.featurize(),
+            vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_field_attributes() {
+        assert_eq!(Sensor::num_feats(), 6);
+        assert_eq!(
+            Sensor::feature_names(),
+            &[
+                "temp",
+                "readings.0",
+                "readings.1",
+                "readings.2",
+                "location.x",
+                "location.y",
+            ]
+        );
+        let sensor = Sensor {
+            id: 3,
+            temperature: 10.0,
+            readings: [-5, 0, 5],
+            pos: Pos { x: 1.0, y: 2.0 },
+        };
+        assert_eq!(sensor.featurize(), vec![4.0, -1.0, 0.0, 1.0, 1.0, 2.0]);
+        assert_eq!(
+            Signal::feature_names(),
+            &["is_Ping", "is_Silent", "Ping.latency_ms"]
+        );
+        assert_eq!(
+            Signal::Ping { latency_ms: 250 }.featurize(),
+            vec![1.0, 0.0, 0.25]
+        );
+        assert_eq!(Signal::Silent(3).featurize(), vec![0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_option_and_tuple() {
+        assert_eq!(Projectile::num_feats(), 11);
+        assert_eq!(
+            Projectile::feature_names(),
+            &[
+                "velocity.0",
+                "velocity.1",
+                "target.is_some",
+                "target.x",
+                "target.y",
+                "fuse.is_some",
+                "fuse",
+                "trail.0.is_some",
+                "trail.0",
+                "trail.1.is_some",
+                "trail.1",
+            ]
+        );
+        assert_eq!(
+            Projectile {
+                velocity: (1.0, -1.0),
+                target: Some(Pos { x: 3.0, y: 4.0 }),
+                fuse: None,
+                trail: [None, Some(true)],
+            }
+            .featurize(),
+            vec![1.0, -1.0, 1.0, 3.0, 4.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn test_option_field_attributes() {
+        assert_eq!(
+            Beacon {
+                shift: Some(1.0),
+                signals: [Some(0.25), Some(-3.0)],
+            }
+            .featurize(),
+            vec![1.0, 11.0, 1.0, 0.5, 1.0, -1.0]
+        );
+        assert_eq!(
+            Beacon {
+                shift: None,
+                signals: [None, Some(0.0)],
+            }
+            .featurize(),
+            vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_categorical() {
+        assert_eq!(Tile::num_feats(), 10);
+        assert_eq!(
+            Tile::feature_names(),
+            &[
+                "kind.is_0",
+                "kind.is_1",
+                "kind.is_2",
+                "kind.is_3",
+                "side.is_0",
+                "side.is_1",
+                "height.lt_-1",
+                "height.-1_to_0.5",
+                "height.0.5_to_2",
+                "height.ge_2",
+            ]
+        );
+        let tile = Tile {
+            kind: 2,
+            team: -1,
+            height: 0.5,
+        };
+        assert_eq!(
+            tile.featurize(),
+            vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        );
+        let tile = Tile {
+            kind: 7,
+            team: 1,
+            height: -3.0,
+        };
+        assert_eq!(
+            tile.featurize(),
+            vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0]
+        );
+    }
+
     #[test]
     fn test_name() {
         assert_eq!(Pos::name(), "Pos");
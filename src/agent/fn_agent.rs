@@ -0,0 +1,140 @@
+use std::marker::PhantomData;
+
+use super::{Action, ActionReceiver, ActionRequest, Agent, Obs};
+
+/// [`Agent`] that chooses the categorical action `A` with a closure.
+///
+/// The closure is called with every observation and returns one action per actor.
+/// This makes it easy to write scripted baselines, opponents or curriculum teachers that can be used wherever
+/// a `Box<dyn Agent>` is accepted, e.g. as opponents of a [`TrainAgent`](super::TrainAgent) in multi-agent training.
+/// Requests for any action other than `A` cause a panic.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{Action, Agent, AgentOps, Featurizable, FnAgent, Obs};
+///
+/// #[derive(Action, Debug, Clone, Copy, PartialEq, Eq)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let mut agent: Box<dyn Agent> = Box::new(FnAgent::new(|obs: &Obs| vec![Move::Left; obs.num_actors()]));
+/// let obs = Obs::new(0.0).actors([Head { x: 0.0, y: 0.0 }, Head { x: 1.0, y: 0.0 }]);
+/// assert_eq!(agent.act::<Move>(&obs), Some(vec![Move::Left, Move::Left]));
+/// ```
+pub struct FnAgent<A, F> {
+    f: F,
+    phantom: PhantomData<fn() -> A>,
+}
+
+impl<A: Action<'static>, F: FnMut(&Obs) -> Vec<A>> FnAgent<A, F> {
+    /// Creates an agent that chooses the actions returned by `f`.
+    pub fn new(f: F) -> Self {
+        FnAgent {
+            f,
+            phantom: PhantomData,
+        }
+    }
+
+    fn choose(&mut self, actions: &[ActionRequest], obs: &Obs) -> Vec<Vec<u64>> {
+        actions
+            .iter()
+            .map(|request| match *request {
+                ActionRequest::Categorical { name, .. } if name == A::name() => {
+                    let chosen = (self.f)(obs);
+                    assert_eq!(
+                        chosen.len(),
+                        obs.num_action_actors(name),
+                        "Closure must return one \"{}\" action per actor",
+                        name,
+                    );
+                    chosen.iter().map(A::to_u64).collect()
+                }
+                _ => panic!(
+                    "FnAgent can only choose \"{}\" actions, but \"{}\" was requested",
+                    A::name(),
+                    request.name()
+                ),
+            })
+            .collect()
+    }
+}
+
+impl<A: Action<'static>, F: FnMut(&Obs) -> Vec<A>> Agent for FnAgent<A, F> {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        Some(self.choose(actions, obs))
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        ActionReceiver::value(Some(self.choose(actions, obs)))
+    }
+
+    fn game_over(&mut self, _: &Obs) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{AgentOps, Featurizable, Select, SelectEntity};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+    }
+
+    #[derive(Action, Debug, Clone, Copy, PartialEq, Eq)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    struct Bite;
+
+    impl SelectEntity for Bite {
+        type Target = Head;
+
+        fn name() -> &'static str {
+            "Bite"
+        }
+    }
+
+    fn obs() -> Obs {
+        Obs::new(0.0).actors([Head { x: -1.0 }, Head { x: 2.0 }])
+    }
+
+    // Moves towards the origin.
+    fn script(obs: &Obs) -> Vec<Move> {
+        obs.features::<Head>()
+            .iter()
+            .map(|head| {
+                if head[0] < 0.0 {
+                    Move::Right
+                } else {
+                    Move::Left
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fn_agent() {
+        let mut agent: Box<dyn Agent> = Box::new(FnAgent::new(script));
+        assert_eq!(
+            agent.act::<Move>(&obs()),
+            Some(vec![Move::Right, Move::Left])
+        );
+        assert_eq!(
+            agent.act_async::<Move>(&obs()).rcv(),
+            Some(vec![Move::Right, Move::Left])
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "FnAgent can only choose \"Move\" actions, but \"Bite\" was requested"
+    )]
+    fn test_fn_agent_wrong_request() {
+        FnAgent::new(script).act::<(Move, Select<Bite>)>(&obs());
+    }
+}
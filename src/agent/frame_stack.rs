@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use super::{ActionInfo, ActionReceiver, ActionRequest, Agent, Featurizable, Obs};
+use crate::agent::obs::{intern, EntityFeatures};
+
+/// Selects the entity types that a [`FrameStack`] remembers and the number of past frames it keeps.
+///
+/// For every entity type `E` that is added with [`FrameStackConfig::entity`], the entities of type `E` in
+/// the last `frames` observations are added to the observation as entities of type `"{E::name()}History"`.
+/// History entities have the same features as `E`, followed by a `time_offset` feature that is `1` for
+/// entities of the previous observation, `2` for the observation before that, and so on.
+///
+/// The same config must be passed to [`TrainEnvBuilder::frame_stack`](super::TrainEnvBuilder::frame_stack)
+/// so that the history entity types are part of the observation space during training.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{Featurizable, FrameStackConfig, TrainEnvBuilder};
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let config = FrameStackConfig::new(2).entity::<Head>();
+/// assert_eq!(config.history_name("Head"), Some("HeadHistory"));
+/// assert_eq!(config.feature_names("Head").unwrap(), ["x", "y", "time_offset"]);
+///
+/// let builder = TrainEnvBuilder::default().entity::<Head>().frame_stack(&config);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrameStackConfig {
+    frames: usize,
+    entities: Vec<StackedEntity>,
+}
+
+#[derive(Debug, Clone)]
+struct StackedEntity {
+    name: &'static str,
+    history_name: &'static str,
+    feature_names: Arc<[String]>,
+}
+
+impl FrameStackConfig {
+    /// Creates a config that keeps the last `frames` observations.
+    pub fn new(frames: usize) -> Self {
+        assert!(frames > 0, "Frame stack must keep at least one frame");
+        FrameStackConfig {
+            frames,
+            entities: vec![],
+        }
+    }
+
+    /// Remembers entities of type `E`.
+    pub fn entity<E: Featurizable>(mut self) -> Self {
+        assert!(
+            self.entities.iter().all(|e| e.name != E::name()),
+            "Already stacking entity with name \"{}\"",
+            E::name(),
+        );
+        let mut feature_names = E::feature_names();
+        feature_names.push("time_offset".to_string());
+        self.entities.push(StackedEntity {
+            name: E::name(),
+            history_name: intern(&format!("{}History", E::name())),
+            feature_names: feature_names.into(),
+        });
+        self
+    }
+
+    /// Returns the number of past observations that are kept.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Returns the name of the entity type that past entities of type `entity` are added as.
+    pub fn history_name(&self, entity: &str) -> Option<&'static str> {
+        self.entities
+            .iter()
+            .find(|e| e.name == entity)
+            .map(|e| e.history_name)
+    }
+
+    /// Returns the names of the features of past entities of type `entity`.
+    pub fn feature_names(&self, entity: &str) -> Option<&[String]> {
+        self.entities
+            .iter()
+            .find(|e| e.name == entity)
+            .map(|e| &e.feature_names[..])
+    }
+
+    /// Returns the name and features of every history entity type.
+    pub(crate) fn history_entities(&self) -> impl Iterator<Item = (&'static str, &[String])> {
+        self.entities
+            .iter()
+            .map(|e| (e.history_name, &e.feature_names[..]))
+    }
+}
+
+/// [`Agent`] that adds entities from past observations to every observation before passing it to another agent.
+///
+/// This gives agents without recurrent state a short-term memory, e.g. to infer the velocity of moving entities.
+/// Which entities are remembered and how they are presented to the inner agent is defined by a [`FrameStackConfig`].
+/// The history is cleared when [`Agent::game_over`] is called.
+///
+/// During training, wrap the [`TrainAgent`](super::TrainAgent) in a [`FrameStack`] and register the same
+/// [`FrameStackConfig`] with [`TrainEnvBuilder::frame_stack`](super::TrainEnvBuilder::frame_stack).
+/// At inference time, wrap the trained agent with the same config.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{self, Action, AgentOps, Featurizable, FrameStack, FrameStackConfig, Obs};
+///
+/// #[derive(Action, Debug)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let config = FrameStackConfig::new(4).entity::<Head>();
+/// let mut agent = FrameStack::new(agent::random(), config);
+/// for step in 0..10 {
+///     let obs = Obs::new(0.0).actors([Head { x: step as f32, y: 0.0 }]);
+///     agent.act::<Move>(&obs);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct FrameStack<A> {
+    agent: A,
+    config: FrameStackConfig,
+    // Features and number of entities of each stacked entity type, most recent observation first.
+    history: VecDeque<Vec<(Vec<f32>, usize)>>,
+}
+
+impl<A: Agent> FrameStack<A> {
+    /// Wraps `agent` so that it observes the history defined by `config`.
+    pub fn new(agent: A, config: FrameStackConfig) -> Self {
+        FrameStack {
+            agent,
+            config,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Returns a reference to the inner agent.
+    pub fn inner(&self) -> &A {
+        &self.agent
+    }
+
+    /// Returns a mutable reference to the inner agent.
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.agent
+    }
+
+    /// Returns the inner agent.
+    pub fn into_inner(self) -> A {
+        self.agent
+    }
+
+    /// Returns a copy of `obs` with the history entities added and pushes `obs` onto the history.
+    fn stack(&mut self, obs: &Obs) -> Obs {
+        let mut stacked = obs.clone();
+        for (i, entity) in self.config.entities.iter().enumerate() {
+            let num_features = entity.feature_names.len();
+            let mut features = vec![];
+            let mut num_entities = 0;
+            for (offset, frame) in self.history.iter().enumerate() {
+                let (past, count) = &frame[i];
+                for j in 0..*count {
+                    features.extend_from_slice(
+                        &past[j * (num_features - 1)..(j + 1) * (num_features - 1)],
+                    );
+                    features.push((offset + 1) as f32);
+                }
+                num_entities += count;
+            }
+            // History entities are always present so that the network input has the same entity types every step.
+            stacked.entities.insert(
+                entity.history_name,
+                EntityFeatures {
+                    features,
+                    num_entities,
+                    num_features,
+                    is_actor: false,
+                    ids: None,
+                    feature_names: Some(entity.feature_names.clone()),
+                },
+            );
+        }
+
+        let frame = self
+            .config
+            .entities
+            .iter()
+            .map(|entity| match obs.entities.get(entity.name) {
+                Some(f) => (f.features.clone(), f.num_entities),
+                None => (vec![], 0),
+            })
+            .collect();
+        self.history.push_front(frame);
+        self.history.truncate(self.config.frames);
+        stacked
+    }
+}
+
+impl<A: Agent> Agent for FrameStack<A> {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        let obs = self.stack(obs);
+        self.agent.act_multi_dyn(actions, &obs)
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        let obs = self.stack(obs);
+        self.agent.act_multi_async_dyn(actions, &obs)
+    }
+
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        let obs = self.stack(obs);
+        self.agent.act_multi_info_dyn(actions, &obs)
+    }
+
+    fn game_over(&mut self, obs: &Obs) {
+        self.history.clear();
+        self.agent.game_over(obs);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Action, AgentOps, Featurizable, TrainAgent, TrainEnvBuilder};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Action)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        history: Vec<Vec<f32>>,
+    }
+
+    impl Agent for Recorder {
+        fn act_multi_dyn(&mut self, _: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+            self.history
+                .push(obs.entities["FoodHistory"].features.clone());
+            Some(vec![vec![0]])
+        }
+
+        fn act_multi_async_dyn(&mut self, _: &[ActionRequest], _: &Obs) -> ActionReceiver<u64> {
+            unimplemented!()
+        }
+
+        fn game_over(&mut self, _: &Obs) {}
+    }
+
+    #[test]
+    fn test_frame_stack() {
+        let config = FrameStackConfig::new(2).entity::<Food>();
+        let mut agent = FrameStack::new(Recorder::default(), config);
+        let obs = |x: f32| {
+            Obs::new(0.0)
+                .actors([Head { x }])
+                .entities([Food { x, y: 0.0 }])
+        };
+        for x in 0..4 {
+            agent.act::<Move>(&obs(x as f32));
+        }
+        agent.game_over(&obs(4.0));
+        agent.act::<Move>(&obs(5.0));
+
+        let history = &agent.inner().history;
+        assert_eq!(history[0], Vec::<f32>::new());
+        assert_eq!(history[1], [0.0, 0.0, 1.0]);
+        assert_eq!(history[2], [1.0, 0.0, 1.0, 0.0, 0.0, 2.0]);
+        assert_eq!(history[3], [2.0, 0.0, 1.0, 1.0, 0.0, 2.0]);
+        assert_eq!(history[4], Vec::<f32>::new());
+    }
+
+    fn run(_: (), agent: TrainAgent, _: u64) {
+        let mut agent = FrameStack::new(agent, FrameStackConfig::new(2).entity::<Food>());
+        let mut x = 0.0;
+        loop {
+            let obs = Obs::new(0.0)
+                .actors([Head { x }])
+                .entities([Food { x, y: 1.0 }]);
+            if agent.act::<Move>(&obs).is_none() {
+                break;
+            }
+            x += 1.0;
+        }
+    }
+
+    #[test]
+    fn test_train_env() {
+        let config = FrameStackConfig::new(2).entity::<Food>();
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Head>()
+            .entity::<Food>()
+            .frame_stack(&config)
+            .action::<Move>()
+            .build_vec_env((), run, 1, 1, 0);
+        let (name, entity) = &env.obs_space.entities[2];
+        assert_eq!(name, "FoodHistory");
+        assert_eq!(entity.features, ["x", "y", "time_offset"]);
+
+        env.reset();
+        let actions = || {
+            vec![Some(ragged_buffer::ragged_buffer::RaggedBuffer {
+                data: vec![0],
+                subarrays: (0..1).map(|i| i..i + 1).collect(),
+                features: 1,
+                items: 1,
+            })]
+        };
+        env.act(actions());
+        let obs = env.act(actions());
+        let features = &obs[0].features;
+        assert_eq!(features.counts, [1, 1, 2]);
+        assert_eq!(features.data[3..], [1.0, 1.0, 1.0, 0.0, 1.0, 2.0]);
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+use super::trajectory::Recording;
+use super::{Action, ActionInfo, ActionReceiver, ActionRequest, Agent, Obs, TrajectoryWriter};
+
+/// Source of actions chosen by a human player.
+pub trait InputSource {
+    /// Returns the actions chosen by the player for each of the requested actions, or `None` if there is no input.
+    ///
+    /// The actions for each request must contain one action per actor, like the return value of [`Agent::act_multi_dyn`].
+    fn poll(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>>;
+}
+
+/// [`InputSource`] that receives categorical actions of type `A` from a channel, e.g. one that is fed by input handling systems.
+///
+/// Only the most recently sent action that the action mask allows for every actor is used, masked actions are discarded.
+/// The action is applied to every actor, so this input source is meant for games in which the player controls all
+/// actors at once. Implement [`InputSource`] to choose a different action for each actor.
+/// If actions other than `A` are requested, there is no input.
+pub struct ChannelInput<A> {
+    receiver: Receiver<A>,
+}
+
+impl<A> ChannelInput<A> {
+    /// Creates an input source and the sender that actions chosen by the player are sent to.
+    pub fn new() -> (Sender<A>, Self) {
+        let (sender, receiver) = unbounded();
+        (sender, ChannelInput { receiver })
+    }
+}
+
+impl<'a, A: Action<'a>> InputSource for ChannelInput<A> {
+    fn poll(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        let allowed = |action: u64| {
+            actions.iter().all(|request| match *request {
+                ActionRequest::Categorical { name, num_actions } if name == A::name() => {
+                    obs.mask(name, num_actions).map_or(true, |mask| {
+                        mask.chunks(num_actions as usize)
+                            .all(|actor| actor[action as usize])
+                    })
+                }
+                _ => true,
+            })
+        };
+        let action = self
+            .receiver
+            .try_iter()
+            .map(|action| action.to_u64())
+            .filter(|&action| allowed(action))
+            .last()?;
+        actions
+            .iter()
+            .map(|request| match *request {
+                ActionRequest::Categorical { name, .. } if name == A::name() => {
+                    Some(vec![action; obs.num_action_actors(name)])
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// [`Agent`] that takes actions from a human player and falls back to another agent when there is no input.
+///
+/// Actions are taken from an [`InputSource`], such as a [`ChannelInput`] that is fed by the input handling systems of a game.
+/// When the input source has no input for an observation, the inner agent chooses the actions instead.
+///
+/// With [`HumanAgent::record`], the observations of all steps and the actions chosen by the human are written to a
+/// trajectory file, which can be read with [`TrajectoryReader`](super::TrajectoryReader) and used as demonstrations.
+/// Steps played by the inner agent are recorded without actions.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{self, Action, AgentOps, ChannelInput, Featurizable, HumanAgent, Obs};
+///
+/// #[derive(Action, Debug, Clone, Copy, PartialEq, Eq)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let (input, source) = ChannelInput::new();
+/// let mut agent = HumanAgent::new(agent::random(), source);
+/// let obs = Obs::new(0.0).actors([Head { x: 0.0, y: 0.0 }]);
+///
+/// // Keyboard input handling sends the chosen action.
+/// input.send(Move::Left).unwrap();
+/// assert_eq!(agent.act::<Move>(&obs), Some(vec![Move::Left]));
+///
+/// // Without input, the random agent acts.
+/// agent.act::<Move>(&obs);
+/// ```
+pub struct HumanAgent<A, I, W: Write = BufWriter<File>> {
+    agent: A,
+    input: I,
+    demonstrations: Option<Recording<W>>,
+}
+
+impl<A: Agent, I: InputSource> HumanAgent<A, I> {
+    /// Creates an agent that takes actions from `input` and falls back to `agent`.
+    pub fn new(agent: A, input: I) -> Self {
+        HumanAgent {
+            agent,
+            input,
+            demonstrations: None,
+        }
+    }
+}
+
+impl<A: Agent, I: InputSource, W: Write> HumanAgent<A, I, W> {
+    /// Writes every step and game over to `writer`.
+    ///
+    /// Only the actions chosen by the human are recorded, steps played by the inner agent have no actions.
+    /// These steps are still recorded so that the score delta of each step only covers the preceding step.
+    pub fn record<V: Write>(self, writer: TrajectoryWriter<V>) -> HumanAgent<A, I, V> {
+        HumanAgent {
+            agent: self.agent,
+            input: self.input,
+            demonstrations: Some(Recording::new(writer)),
+        }
+    }
+
+    /// Returns a reference to the inner agent.
+    pub fn inner(&self) -> &A {
+        &self.agent
+    }
+
+    /// Returns a mutable reference to the inner agent.
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.agent
+    }
+
+    /// Flushes the recorded demonstrations and returns the first error that occurred while recording, if any.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        match &mut self.demonstrations {
+            Some(demonstrations) => demonstrations.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the actions chosen by the player, if any, and records the step.
+    fn poll(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        let chosen = self.input.poll(actions, obs);
+        if let Some(chosen) = &chosen {
+            assert_eq!(
+                chosen.len(),
+                actions.len(),
+                "Input source must return actions for each of the {} requested actions",
+                actions.len(),
+            );
+        }
+        if let Some(demonstrations) = &mut self.demonstrations {
+            demonstrations.record(|w| w.write_step(obs, actions, chosen.as_deref()));
+        }
+        chosen
+    }
+}
+
+impl<A: Agent, I: InputSource, W: Write> Agent for HumanAgent<A, I, W> {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        match self.poll(actions, obs) {
+            Some(chosen) => Some(chosen),
+            None => self.agent.act_multi_dyn(actions, obs),
+        }
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        match self.poll(actions, obs) {
+            Some(chosen) => ActionReceiver::value(Some(chosen)),
+            None => self.agent.act_multi_async_dyn(actions, obs),
+        }
+    }
+
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        match self.poll(actions, obs) {
+            Some(chosen) => Some(
+                chosen
+                    .into_iter()
+                    .map(|acts| acts.into_iter().map(ActionInfo::from_action).collect())
+                    .collect(),
+            ),
+            None => self.agent.act_multi_info_dyn(actions, obs),
+        }
+    }
+
+    fn game_over(&mut self, obs: &Obs) {
+        if let Some(demonstrations) = &mut self.demonstrations {
+            demonstrations.record(|w| w.write_game_over(obs));
+        }
+        self.agent.game_over(obs);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{
+        ActionType, AgentOps, Featurizable, RandomAgent, Select, SelectEntity, TrajectoryEvent,
+        TrajectoryReader,
+    };
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+    }
+
+    #[derive(Action, Debug, Clone, Copy, PartialEq, Eq)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    struct Eat;
+
+    impl SelectEntity for Eat {
+        type Target = Head;
+
+        fn name() -> &'static str {
+            "Eat"
+        }
+    }
+
+    #[test]
+    fn test_human_agent() {
+        let path = std::env::temp_dir().join(format!("test-human-{}.traj", std::process::id()));
+        let (input, source) = ChannelInput::new();
+        let mut agent = HumanAgent::new(RandomAgent::from_seed(0), source)
+            .record(TrajectoryWriter::create(&path).unwrap());
+        let obs = |x: f32| Obs::new(x).actors([Head { x }, Head { x: -x }]);
+
+        input.send(Move::Left).unwrap();
+        input.send(Move::Right).unwrap();
+        assert_eq!(agent.act::<Move>(&obs(1.0)), Some(vec![Move::Right; 2]));
+        // Without input, the inner agent acts.
+        assert_eq!(agent.act::<Move>(&obs(2.0)).unwrap().len(), 2);
+        // Input for a different action is ignored.
+        input.send(Move::Left).unwrap();
+        assert_eq!(
+            agent.act::<(Move, Select<Eat>)>(&obs(3.0)).unwrap().1.len(),
+            2
+        );
+        input.send(Move::Left).unwrap();
+        let receiver = agent.act_async::<Move>(&obs(4.0));
+        assert_eq!(receiver.rcv(), Some(vec![Move::Left; 2]));
+        agent.game_over(&obs(5.0));
+        agent.flush().unwrap();
+
+        let events = TrajectoryReader::open(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let recorded = events
+            .iter()
+            .map(|event| match event {
+                TrajectoryEvent::Step {
+                    obs,
+                    score_delta,
+                    actions,
+                    ..
+                } => (obs.score, *score_delta, actions.clone()),
+                TrajectoryEvent::GameOver { obs, score_delta } => (obs.score, *score_delta, None),
+            })
+            .collect::<Vec<_>>();
+        // Steps played by the inner agent are recorded without actions.
+        assert_eq!(
+            recorded,
+            [
+                (1.0, 0.0, Some(vec![vec![1, 1]])),
+                (2.0, 1.0, None),
+                (3.0, 1.0, None),
+                (4.0, 1.0, Some(vec![vec![0, 0]])),
+                (5.0, 1.0, None),
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_channel_input_mask() {
+        let (input, mut source) = ChannelInput::new();
+        let request = Move::request();
+        let obs = Obs::new(0.0)
+            .actors([Head { x: 0.0 }, Head { x: 1.0 }])
+            .action_mask::<Move>([[true, true], [false, true]]);
+
+        // Left is masked for the second actor, so the earlier Right is used.
+        input.send(Move::Right).unwrap();
+        input.send(Move::Left).unwrap();
+        assert_eq!(source.poll(&[request], &obs), Some(vec![vec![1, 1]]));
+        // Masked actions are discarded.
+        input.send(Move::Left).unwrap();
+        assert_eq!(source.poll(&[request], &obs), None);
+        assert_eq!(source.poll(&[request], &obs), None);
+    }
+}
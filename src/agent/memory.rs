@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use indexmap::IndexMap;
+use ndarray::Array2;
+
+use super::{Featurizable, Obs};
+
+/// Per-episode memory of a [`RogueNetAgent`](super::RogueNetAgent) or [`TrainAgent`](super::TrainAgent).
+///
+/// Before every forward pass, [`Memory::observe`] is called with the observation and the (normalized) features
+/// of all entities that are passed to the network. The memory can update its state and add entities to the network input.
+/// The network must have been trained with the entity types that the memory adds, so the same memory should be
+/// passed to [`TrainEnvBuilder::memory`](super::TrainEnvBuilder::memory) during training.
+///
+/// The memory is cleared with [`Memory::reset`] when [`Agent::game_over`](super::Agent::game_over) is called.
+/// Every clone of an agent has its own copy of the memory, so a single agent can be cloned for multiple players.
+pub trait Memory: Send + Sync + 'static {
+    /// Updates the memory with the current observation and adds remembered entities to `features`.
+    ///
+    /// `features` maps each entity type to an array of shape `(num_entities, num_features)`.
+    fn observe(&mut self, obs: &Obs, features: &mut HashMap<String, Array2<f32>>);
+
+    /// Returns the name and feature names of every entity type that the memory adds.
+    ///
+    /// Defaults to none, e.g. for memories that only change the features of observed entities.
+    fn entities(&self) -> Vec<(String, Vec<String>)> {
+        vec![]
+    }
+
+    /// Clears the memory at the end of an episode.
+    fn reset(&mut self);
+
+    /// Returns a boxed copy of the memory.
+    fn box_clone(&self) -> Box<dyn Memory>;
+}
+
+impl fmt::Debug for dyn Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Memory")
+            .field("entities", &self.entities())
+            .finish()
+    }
+}
+
+impl Clone for Box<dyn Memory> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// [`Memory`] that remembers entities that were observed earlier in the episode but are no longer visible.
+///
+/// Each remembered entity of type `E` is added to the network input as an entity of type
+/// `"{E::name()}LastSeen"` with the features that `E` had when it was last observed,
+/// followed by a `steps_since_seen` feature.
+/// Entities are identified by their ids, so they must be added with [`Obs::entities_with_ids`] or [`Obs::actors_with_ids`].
+/// [`Memory::observe`] panics if an observation contains entities of type `E` without ids.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{Featurizable, LastSeen, Memory, Obs};
+/// use std::collections::HashMap;
+///
+/// #[derive(Featurizable)]
+/// struct Enemy { x: f32, y: f32 }
+///
+/// let mut memory = LastSeen::new::<Enemy>();
+/// assert_eq!(memory.feature_names(), ["x", "y", "steps_since_seen"]);
+///
+/// let mut features = HashMap::new();
+/// let obs = Obs::new(0.0).entities_with_ids([(1, Enemy { x: 2.0, y: 3.0 })]);
+/// memory.observe(&obs, &mut features);
+///
+/// // The enemy is no longer visible, but still remembered.
+/// let mut features = HashMap::new();
+/// memory.observe(&Obs::new(0.0), &mut features);
+/// assert_eq!(features["EnemyLastSeen"].row(0).to_vec(), [2.0, 3.0, 1.0]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LastSeen {
+    entity: &'static str,
+    name: String,
+    feature_names: Vec<String>,
+    step: u64,
+    // Features of each remembered entity keyed by id, and the step at which it was last observed.
+    seen: IndexMap<u64, (Vec<f32>, u64)>,
+}
+
+impl LastSeen {
+    /// Creates a memory for entities of type `E`.
+    pub fn new<E: Featurizable>() -> Self {
+        let mut feature_names = E::feature_names();
+        feature_names.push("steps_since_seen".to_string());
+        LastSeen {
+            entity: E::name(),
+            name: format!("{}LastSeen", E::name()),
+            feature_names,
+            step: 0,
+            seen: IndexMap::new(),
+        }
+    }
+
+    /// Returns the name of the entity type that remembered entities are added as.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the names of the features of remembered entities.
+    pub fn feature_names(&self) -> &[String] {
+        &self.feature_names
+    }
+}
+
+impl Memory for LastSeen {
+    fn observe(&mut self, obs: &Obs, features: &mut HashMap<String, Array2<f32>>) {
+        self.step += 1;
+        let num_features = self.feature_names.len() - 1;
+        let mut visible = Vec::new();
+        if let Some(entity) = obs.entities.get(self.entity) {
+            let ids = match &entity.ids {
+                Some(ids) => &ids[..],
+                None if entity.num_entities == 0 => &[],
+                None => panic!(
+                    "LastSeen requires ids for entities of type \"{}\", add them with `Obs::entities_with_ids` or `Obs::actors_with_ids`",
+                    self.entity
+                ),
+            };
+            let current = features.get(self.entity);
+            for (i, &id) in ids.iter().enumerate() {
+                let row = match current {
+                    Some(current) => current.row(i).to_vec(),
+                    None => entity.features[i * num_features..(i + 1) * num_features].to_vec(),
+                };
+                self.seen.insert(id, (row, self.step));
+                visible.push(id);
+            }
+        }
+        let mut data = Vec::new();
+        let mut count = 0;
+        for (id, (row, step)) in &self.seen {
+            if !visible.contains(id) {
+                data.extend_from_slice(row);
+                data.push((self.step - step) as f32);
+                count += 1;
+            }
+        }
+        features.insert(
+            self.name.clone(),
+            Array2::from_shape_vec((count, num_features + 1), data).unwrap(),
+        );
+    }
+
+    fn entities(&self) -> Vec<(String, Vec<String>)> {
+        vec![(self.name.clone(), self.feature_names.clone())]
+    }
+
+    fn reset(&mut self) {
+        self.step = 0;
+        self.seen.clear();
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Action, AgentOps, Featurizable, TrainAgent, TrainEnvBuilder};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Enemy {
+        x: f32,
+    }
+
+    #[derive(Action)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    #[test]
+    fn test_last_seen() {
+        let mut memory = LastSeen::new::<Enemy>();
+        let mut observe = |obs: Obs| {
+            let mut features = HashMap::new();
+            memory.observe(&obs, &mut features);
+            features.remove("EnemyLastSeen").unwrap()
+        };
+        let remembered = observe(
+            Obs::new(0.0).entities_with_ids([(1, Enemy { x: 1.0 }), (2, Enemy { x: 2.0 })]),
+        );
+        assert_eq!(remembered.dim(), (0, 2));
+        let remembered = observe(Obs::new(0.0).entities_with_ids([(2, Enemy { x: 3.0 })]));
+        assert_eq!(remembered, ndarray::array![[1.0, 1.0]]);
+        let remembered = observe(Obs::new(0.0));
+        assert_eq!(remembered, ndarray::array![[1.0, 2.0], [3.0, 1.0]]);
+
+        memory.reset();
+        let mut features = HashMap::new();
+        memory.observe(&Obs::new(0.0), &mut features);
+        assert_eq!(features["EnemyLastSeen"].dim(), (0, 2));
+    }
+
+    // The enemy is only visible in the first observation.
+    fn run(_: (), mut agent: TrainAgent, _: u64) {
+        let mut x = 0.0;
+        loop {
+            let obs = Obs::new(0.0).actors([Head { x }]);
+            let obs = if x == 0.0 {
+                obs.entities_with_ids([(1, Enemy { x: 5.0 })])
+            } else {
+                obs
+            };
+            if agent.act::<Move>(&obs).is_none() {
+                break;
+            }
+            x += 1.0;
+        }
+    }
+
+    #[test]
+    fn test_train_env() {
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Head>()
+            .entity::<Enemy>()
+            .memory(LastSeen::new::<Enemy>())
+            .action::<Move>()
+            .build_vec_env((), run, 1, 1, 0);
+        let (name, entity) = &env.obs_space.entities[2];
+        assert_eq!(name, "EnemyLastSeen");
+        assert_eq!(entity.features, ["x", "steps_since_seen"]);
+
+        let obs = env.reset();
+        assert_eq!(obs[0].features.counts, [1, 1, 0]);
+        let actions = || {
+            vec![Some(ragged_buffer::ragged_buffer::RaggedBuffer {
+                data: vec![0],
+                subarrays: (0..1).map(|i| i..i + 1).collect(),
+                features: 1,
+                items: 1,
+            })]
+        };
+        env.act(actions());
+        let obs = env.act(actions());
+        let features = &obs[0].features;
+        assert_eq!(features.counts, [1, 0, 1]);
+        assert_eq!(features.data, [2.0, 5.0, 2.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "LastSeen requires ids for entities of type \"Enemy\"")]
+    fn test_last_seen_without_ids() {
+        LastSeen::new::<Enemy>().observe(
+            &Obs::new(0.0).entities([Enemy { x: 1.0 }]),
+            &mut HashMap::new(),
+        );
+    }
+}
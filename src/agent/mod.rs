@@ -1,29 +1,58 @@
 mod action;
+mod action_info;
+mod action_set;
+mod batched_rogue_net;
+mod demonstrations;
 mod featurizable;
+mod fn_agent;
+mod frame_stack;
+mod human;
+mod memory;
+mod normalizer;
 mod obs;
 mod random;
+mod replay;
 mod rogue_net;
 #[cfg(feature = "bevy")]
 mod rogue_net_asset;
-#[cfg(feature = "python")]
+mod select_entity;
 mod training;
+mod trajectory;
+mod validation;
 
 use std::io::Read;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-pub use self::rogue_net::RogueNetAgent;
+pub use self::rogue_net::{RogueNetAgent, Sampling};
 pub use action::Action;
+pub use action_info::ActionInfo;
+pub use action_set::{ActionRequest, ActionSet, ActionType, Select};
+use batched_rogue_net::Batch;
+pub use batched_rogue_net::BatchedRogueNetAgent;
 use crossbeam_channel::Receiver;
+pub use demonstrations::{Demonstration, DemonstrationDataset};
 pub use entity_gym_derive::*;
 pub use featurizable::Featurizable;
-pub use obs::Obs;
+pub use fn_agent::FnAgent;
+pub use frame_stack::{FrameStack, FrameStackConfig};
+pub use human::{ChannelInput, HumanAgent, InputSource};
+pub use memory::{LastSeen, Memory};
+pub use normalizer::ObsNormalizer;
+pub(crate) use normalizer::RunningMeanStd;
+pub use obs::{Actor, EntityRows, Obs};
 pub use random::RandomAgent;
+pub use replay::ReplayAgent;
 #[cfg(feature = "bevy")]
 pub use rogue_net_asset::{RogueNetAsset, RogueNetAssetLoader};
-#[cfg(feature = "python")]
+pub use select_entity::{EntityRef, SelectEntity};
 pub use training::{TrainAgent, TrainAgentEnv, TrainEnvBuilder};
+pub use trajectory::{
+    EntitySchema, RecordedRequest, TrajectoryEvent, TrajectoryReader, TrajectoryRecorder,
+    TrajectoryWriter,
+};
+pub use validation::{ObsError, ObsValidation};
 
 /// Agents are given observations and return actions.
 ///
@@ -31,55 +60,218 @@ pub use training::{TrainAgent, TrainAgentEnv, TrainEnvBuilder};
 /// There are three main ways of obtaining an [`Agent`]:
 /// 1. [`random()`] creates an agent that chooses actions uniformly at random.
 /// 2. [`load`] and [`load_archive`] loads a trained neural network agent from an [enn-trainer](https://github.com/entity-neural-network/enn-trainer) checkpoint directory or an archive of a checkpoint directory.
-/// 3. [`TrainEnvBuilder`] can be used to obtain a [`TrainAgent`]/[`TrainAgentEnv`] pair which can be used to train a neural network agent.
+/// 3. [`BatchedRogueNetAgent`] evaluates the observations of many agents with a single forward pass of a neural network.
+/// 4. [`FrameStack`] wraps another agent and adds entities from past observations to every observation.
+/// 5. [`TrajectoryRecorder`] wraps another agent and records its observations and actions to a trajectory file.
+/// 6. [`ReplayAgent`] plays back the actions of a recorded trajectory.
+/// 7. [`HumanAgent`] takes actions from a human player and falls back to another agent when there is no input.
+/// 8. [`FnAgent`] chooses actions with a closure, e.g. to implement scripted opponents.
+/// 9. [`TrainEnvBuilder`] can be used to obtain a [`TrainAgent`]/[`TrainAgentEnv`] pair which can be used to train a neural network agent.
 ///
-/// Every [`Agent`] also implements the [`AgentOps`] trait which provides more ergonomic typed versions of the [`Agent::act_dyn`] and [`Agent::act_async_dyn`] methods.
+/// Every [`Agent`] also implements the [`AgentOps`] trait which provides more ergonomic typed versions of the [`Agent::act_multi_dyn`] and [`Agent::act_multi_async_dyn`] methods.
 pub trait Agent {
+    /// Returns the actions chosen by the actors for each of the requested actions.
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>>;
+
+    /// Returns receiver that can be blocked on to receive the actions chosen by the actors for each of the requested actions.
+    #[must_use]
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64>;
+
     /// Returns an action for the given observation.
-    fn act_dyn(&mut self, action: &str, num_actions: u64, obs: &Obs) -> Option<Vec<u64>>;
+    fn act_dyn(&mut self, action: &str, num_actions: u64, obs: &Obs) -> Option<Vec<u64>> {
+        let request = ActionRequest::Categorical {
+            name: action,
+            num_actions,
+        };
+        self.act_multi_dyn(&[request], obs)
+            .map(|mut acts| acts.remove(0))
+    }
 
     /// Returns receiver that can be blocked on to receive an action for the given observation.
     #[must_use]
-    fn act_async_dyn(&mut self, action: &str, num_actions: u64, obs: &Obs) -> ActionReceiver<u64>;
+    fn act_async_dyn(&mut self, action: &str, num_actions: u64, obs: &Obs) -> ActionReceiver<u64> {
+        let request = ActionRequest::Categorical {
+            name: action,
+            num_actions,
+        };
+        self.act_multi_async_dyn(&[request], obs)
+    }
+
+    /// Returns the index of the selected `target` entity for each actor in the given observation.
+    fn select_entity_dyn(&mut self, action: &str, target: &str, obs: &Obs) -> Option<Vec<u64>> {
+        let request = ActionRequest::SelectEntity {
+            name: action,
+            target,
+        };
+        self.act_multi_dyn(&[request], obs)
+            .map(|mut acts| acts.remove(0))
+    }
+
+    /// Returns receiver that can be blocked on to receive the index of the selected `target` entity for each actor in the given observation.
+    #[must_use]
+    fn select_entity_async_dyn(
+        &mut self,
+        action: &str,
+        target: &str,
+        obs: &Obs,
+    ) -> ActionReceiver<u64> {
+        let request = ActionRequest::SelectEntity {
+            name: action,
+            target,
+        };
+        self.act_multi_async_dyn(&[request], obs)
+    }
+
+    /// Returns the actions chosen by the actors for each of the requested actions together with
+    /// the probability of every choice and the agent's value estimate.
+    ///
+    /// The default implementation calls [`Agent::act_multi_dyn`] and returns no probabilities or value estimates.
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        self.act_multi_dyn(actions, obs).map(|acts| {
+            acts.into_iter()
+                .map(|acts| acts.into_iter().map(ActionInfo::from_action).collect())
+                .collect()
+        })
+    }
 
     /// Indicates that the agent has reached the end of the training episode.
     fn game_over(&mut self, obs: &Obs);
 }
 
-/// Augments the [`Agent`] trait with more ergonomic typed versions of the [`Agent::act_dyn`] and [`Agent::act_async_dyn`] methods.
+/// Augments the [`Agent`] trait with more ergonomic typed versions of the [`Agent::act_multi_dyn`] and [`Agent::act_multi_async_dyn`] methods.
 pub trait AgentOps {
-    /// Returns an action for the given observation.
-    fn act<'a, A: Action<'a>>(&mut self, obs: &Obs) -> Option<Vec<A>>;
+    /// Returns the actions chosen by the actors for the given observation.
+    ///
+    /// `A` can be a single [`Action`], a [`Select`] action, or a tuple of several action types (see [`ActionSet`]).
+    fn act<A: ActionSet>(&mut self, obs: &Obs) -> Option<A::Output>;
 
-    /// Returns receiver that can be blocked on to receive an action for the given observation.
+    /// Returns receiver that can be blocked on to receive the actions chosen by the actors for the given observation.
     #[must_use]
-    fn act_async<'a, A: Action<'a>>(&mut self, obs: &Obs) -> ActionReceiver<A>;
+    fn act_async<A: ActionSet>(&mut self, obs: &Obs) -> ActionReceiver<A>;
+
+    /// Returns the actions chosen by the actors for the given observation, keyed by the id of each actor.
+    ///
+    /// All actors must have been added with ids, e.g. with [`Obs::actors_with_ids`].
+    fn act_by_id<A: ActionSet>(&mut self, obs: &Obs) -> Option<A::Keyed>;
+
+    /// Returns the entity selected by each actor for the given observation.
+    fn select_entity<A: SelectEntity>(&mut self, obs: &Obs) -> Option<Vec<EntityRef<A::Target>>>;
+
+    /// Returns receiver that can be blocked on to receive the entity selected by each actor for the given observation.
+    #[must_use]
+    fn select_entity_async<A: SelectEntity>(&mut self, obs: &Obs) -> ActionReceiver<Select<A>>;
+
+    /// Returns the action chosen by each actor together with the probability of every choice and the agent's value estimate.
+    ///
+    /// # Example
+    /// ```
+    /// use entity_gym_rs::agent::{self, Action, AgentOps, Featurizable, Obs};
+    ///
+    /// #[derive(Action, Debug)]
+    /// enum Move { Up, Down, Left, Right }
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Player { x: i32, y: i32 }
+    ///
+    /// let obs = Obs::new(0.0).actors([Player { x: 0, y: 0 }]);
+    /// let info = agent::random().act_info::<Move>(&obs).unwrap();
+    /// assert_eq!(info[0].probs, Some(vec![0.25; 4]));
+    /// ```
+    fn act_info<A: ActionType>(&mut self, obs: &Obs) -> Option<Vec<ActionInfo<A::Output>>>;
 }
 
 impl<T: Agent> AgentOps for T {
-    fn act<'a, A: Action<'a>>(&mut self, obs: &Obs) -> Option<Vec<A>> {
-        self.act_dyn(A::name(), A::num_actions(), obs)
-            .map(|x| x.into_iter().map(A::from_u64).collect())
+    fn act<A: ActionSet>(&mut self, obs: &Obs) -> Option<A::Output> {
+        self.act_multi_dyn(&A::requests(), obs).map(A::from_raw)
     }
 
-    #[must_use]
-    fn act_async<'a, A: Action<'a>>(&mut self, obs: &Obs) -> ActionReceiver<A> {
-        let receiver = self.act_async_dyn(A::name(), A::num_actions(), obs);
+    fn act_async<A: ActionSet>(&mut self, obs: &Obs) -> ActionReceiver<A> {
+        let receiver = self.act_multi_async_dyn(&A::requests(), obs);
         unsafe { std::mem::transmute::<ActionReceiver<u64>, ActionReceiver<A>>(receiver) }
     }
+
+    fn act_by_id<A: ActionSet>(&mut self, obs: &Obs) -> Option<A::Keyed> {
+        self.act::<A>(obs).map(|acts| A::key_by_id(acts, obs))
+    }
+
+    fn select_entity<A: SelectEntity>(&mut self, obs: &Obs) -> Option<Vec<EntityRef<A::Target>>> {
+        self.act::<Select<A>>(obs)
+    }
+
+    fn select_entity_async<A: SelectEntity>(&mut self, obs: &Obs) -> ActionReceiver<Select<A>> {
+        self.act_async::<Select<A>>(obs)
+    }
+
+    fn act_info<A: ActionType>(&mut self, obs: &Obs) -> Option<Vec<ActionInfo<A::Output>>> {
+        self.act_multi_info_dyn(&[A::request()], obs)
+            .map(|mut infos| {
+                infos
+                    .remove(0)
+                    .into_iter()
+                    .map(ActionInfo::into_typed::<A>)
+                    .collect()
+            })
+    }
 }
 
 impl AgentOps for dyn Agent {
-    fn act<'a, A: Action<'a>>(&mut self, obs: &Obs) -> Option<Vec<A>> {
-        self.act_dyn(A::name(), A::num_actions(), obs)
-            .map(|x| x.into_iter().map(A::from_u64).collect())
+    fn act<A: ActionSet>(&mut self, obs: &Obs) -> Option<A::Output> {
+        self.act_multi_dyn(&A::requests(), obs).map(A::from_raw)
     }
 
-    #[must_use]
-    fn act_async<'a, A: Action<'a>>(&mut self, obs: &Obs) -> ActionReceiver<A> {
-        let receiver = self.act_async_dyn(A::name(), A::num_actions(), obs);
+    fn act_async<A: ActionSet>(&mut self, obs: &Obs) -> ActionReceiver<A> {
+        let receiver = self.act_multi_async_dyn(&A::requests(), obs);
         unsafe { std::mem::transmute::<ActionReceiver<u64>, ActionReceiver<A>>(receiver) }
     }
+
+    fn act_by_id<A: ActionSet>(&mut self, obs: &Obs) -> Option<A::Keyed> {
+        self.act::<A>(obs).map(|acts| A::key_by_id(acts, obs))
+    }
+
+    fn select_entity<A: SelectEntity>(&mut self, obs: &Obs) -> Option<Vec<EntityRef<A::Target>>> {
+        self.act::<Select<A>>(obs)
+    }
+
+    fn select_entity_async<A: SelectEntity>(&mut self, obs: &Obs) -> ActionReceiver<Select<A>> {
+        self.act_async::<Select<A>>(obs)
+    }
+
+    fn act_info<A: ActionType>(&mut self, obs: &Obs) -> Option<Vec<ActionInfo<A::Output>>> {
+        self.act_multi_info_dyn(&[A::request()], obs)
+            .map(|mut infos| {
+                infos
+                    .remove(0)
+                    .into_iter()
+                    .map(ActionInfo::into_typed::<A>)
+                    .collect()
+            })
+    }
+}
+
+impl<T: Agent + ?Sized> Agent for Box<T> {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        (**self).act_multi_dyn(actions, obs)
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        (**self).act_multi_async_dyn(actions, obs)
+    }
+
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        (**self).act_multi_info_dyn(actions, obs)
+    }
+
+    fn game_over(&mut self, obs: &Obs) {
+        (**self).game_over(obs)
+    }
 }
 
 /// A channel for receiving an agent action returned by [`AgentOps::act_async`] or [`Agent::act_async_dyn`].
@@ -87,26 +279,46 @@ pub struct ActionReceiver<A> {
     inner: InnerActionReceiver<A>,
 }
 
+type RecordActions = Box<dyn FnOnce(Option<&Vec<Vec<u64>>>) + Send>;
+
 enum InnerActionReceiver<A> {
-    // Variant is only constructed when cfg(feature = "python").
-    #[allow(dead_code)]
     Receiver {
-        receiver: Receiver<Vec<u64>>,
+        receiver: Receiver<Vec<Vec<u64>>>,
         observations_remaining: Arc<AtomicUsize>,
         agent_count: usize,
+        // Index into the received actions and offset for each requested action.
+        // The offset is subtracted from received values to convert global entity positions into indices of selected entities.
+        requested: Vec<(usize, u64)>,
         phantom: std::marker::PhantomData<A>,
     },
-    Value(Vec<u64>),
+    Batched {
+        receiver: Receiver<Vec<Vec<ActionInfo<u64>>>>,
+        batch: Arc<Mutex<Batch>>,
+    },
+    Value(Option<Vec<Vec<u64>>>),
+    // Calls `record` with the actions once they are received from the inner receiver.
+    Recorded {
+        receiver: Box<ActionReceiver<u64>>,
+        record: RecordActions,
+    },
 }
 
 impl<A> ActionReceiver<A> {
     /// Blocks on the receiver until an action is received.
+    ///
+    /// If multiple actions were requested, only returns the first action.
     pub fn rcv_raw(self) -> Option<Vec<u64>> {
+        self.rcv_raw_multi().map(|mut acts| acts.remove(0))
+    }
+
+    /// Blocks on the receiver until actions are received and returns the actions for each of the requested actions.
+    pub fn rcv_raw_multi(self) -> Option<Vec<Vec<u64>>> {
         match self.inner {
             InnerActionReceiver::Receiver {
                 receiver,
                 observations_remaining,
                 agent_count,
+                requested,
                 ..
             } => {
                 let remaining = observations_remaining.load(Ordering::SeqCst);
@@ -115,23 +327,57 @@ impl<A> ActionReceiver<A> {
                 }
                 let act = receiver.recv();
                 observations_remaining.store(agent_count, Ordering::SeqCst);
-                act.ok()
+                act.ok().map(|act| {
+                    requested
+                        .iter()
+                        .map(|&(index, offset)| act[index].iter().map(|a| a - offset).collect())
+                        .collect()
+                })
+            }
+            InnerActionReceiver::Batched { receiver, batch } => {
+                let infos = match receiver.try_recv() {
+                    Ok(infos) => infos,
+                    Err(_) => {
+                        Batch::flush(&batch);
+                        receiver.recv().ok()?
+                    }
+                };
+                Some(
+                    infos
+                        .into_iter()
+                        .map(|infos| infos.into_iter().map(|info| info.action).collect())
+                        .collect(),
+                )
+            }
+            InnerActionReceiver::Value(value) => value,
+            InnerActionReceiver::Recorded { receiver, record } => {
+                let actions = receiver.rcv_raw_multi();
+                record(actions.as_ref());
+                actions
             }
-            InnerActionReceiver::Value(value) => Some(value),
         }
     }
 
     /// Blocks on the receiver until an action is received.
-    pub fn rcv<'a>(self) -> Option<Vec<A>>
+    pub fn rcv(self) -> Option<A::Output>
+    where
+        A: ActionSet,
+    {
+        self.rcv_raw_multi().map(A::from_raw)
+    }
+
+    /// Blocks on the receiver until an action is received and keys the actions by the ids of the actors in `obs`.
+    ///
+    /// `obs` must be the observation that the actions were requested for.
+    pub fn rcv_by_id(self, obs: &Obs) -> Option<A::Keyed>
     where
-        A: Action<'a>,
+        A: ActionSet,
     {
-        self.rcv_raw()
-            .map(|x| x.into_iter().map(A::from_u64).collect())
+        self.rcv().map(|acts| A::key_by_id(acts, obs))
     }
 
     /// Creates a new [`ActionReceiver`] which will return the given value.
-    pub(crate) fn value(val: Vec<u64>) -> ActionReceiver<A> {
+    pub(crate) fn value(val: Option<Vec<Vec<u64>>>) -> ActionReceiver<A> {
         ActionReceiver {
             inner: InnerActionReceiver::Value(val),
         }
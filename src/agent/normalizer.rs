@@ -0,0 +1,217 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use super::Obs;
+
+/// Normalizes the features of each entity type with a running estimate of their mean and variance.
+///
+/// During training, the normalizer is passed to [`TrainEnvBuilder::normalize_obs`](super::TrainEnvBuilder::normalize_obs),
+/// which updates the statistics with every observation and normalizes the features before they are sent to the trainer.
+/// The statistics can then be saved to the checkpoint directory as [`ObsNormalizer::FILE_NAME`], from where
+/// [`RogueNetAgent::load`](super::RogueNetAgent::load) picks them up to apply identical normalization at inference time.
+///
+/// # Example
+/// ```rust
+/// use entity_gym_rs::agent::{Featurizable, Obs, ObsNormalizer};
+///
+/// #[derive(Featurizable)]
+/// struct Player { x: f32, y: f32 }
+///
+/// let mut normalizer = ObsNormalizer::default();
+/// normalizer.update(&Obs::new(0.0).entities([Player { x: 100.0, y: 0.0 }, Player { x: 300.0, y: 2.0 }]));
+/// assert_eq!(normalizer.mean("Player"), Some(vec![200.0, 1.0]));
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObsNormalizer {
+    clip: f32,
+    entities: IndexMap<String, RunningMeanStd>,
+}
+
+/// Running estimate of the mean and variance of each feature, computed with Welford's algorithm.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RunningMeanStd {
+    pub count: f64,
+    pub mean: Vec<f64>,
+    // Sum of squared differences from the mean.
+    pub m2: Vec<f64>,
+}
+
+impl RunningMeanStd {
+    pub fn new(num_features: usize) -> Self {
+        RunningMeanStd {
+            count: 0.0,
+            mean: vec![0.0; num_features],
+            m2: vec![0.0; num_features],
+        }
+    }
+
+    /// Updates the statistics with the flattened features of any number of entities.
+    pub fn update(&mut self, features: &[f32]) {
+        if self.mean.is_empty() {
+            return;
+        }
+        for row in features.chunks(self.mean.len()) {
+            self.count += 1.0;
+            for (i, &x) in row.iter().enumerate() {
+                let delta = x as f64 - self.mean[i];
+                self.mean[i] += delta / self.count;
+                self.m2[i] += delta * (x as f64 - self.mean[i]);
+            }
+        }
+    }
+
+    /// Returns the sample standard deviation of each feature, or 1 where it is zero or undefined.
+    pub fn std(&self) -> Vec<f64> {
+        self.m2
+            .iter()
+            .map(|m2| {
+                let var = if self.count > 1.0 {
+                    m2 / (self.count - 1.0)
+                } else {
+                    0.0
+                };
+                if var == 0.0 {
+                    1.0
+                } else {
+                    var.sqrt()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for ObsNormalizer {
+    fn default() -> Self {
+        ObsNormalizer {
+            clip: 5.0,
+            entities: IndexMap::new(),
+        }
+    }
+}
+
+impl ObsNormalizer {
+    /// Name of the file that stores the normalizer in a checkpoint directory.
+    pub const FILE_NAME: &'static str = "obs_normalizer.ron";
+
+    /// Sets the range `[-clip, clip]` that normalized features are clipped to. Defaults to 5.
+    pub fn with_clip(mut self, clip: f32) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    /// Updates the statistics with the features of all entities in the observation.
+    pub fn update(&mut self, obs: &Obs) {
+        for (name, entity) in &obs.entities {
+            if entity.num_features == 0 {
+                continue;
+            }
+            let stats = self
+                .entities
+                .entry(name.to_string())
+                .or_insert_with(|| RunningMeanStd::new(entity.num_features));
+            assert_eq!(
+                stats.mean.len(),
+                entity.num_features,
+                "Entity \"{}\" has {} features, but the normalizer was created with {} features",
+                name,
+                entity.num_features,
+                stats.mean.len(),
+            );
+            stats.update(&entity.features);
+        }
+    }
+
+    /// Returns the current mean of each feature of the given entity type.
+    pub fn mean(&self, entity: &str) -> Option<Vec<f32>> {
+        let stats = self.entities.get(entity)?;
+        Some(stats.mean.iter().map(|&m| m as f32).collect())
+    }
+
+    /// Returns the current standard deviation of each feature of the given entity type.
+    pub fn std(&self, entity: &str) -> Option<Vec<f32>> {
+        let stats = self.entities.get(entity)?;
+        Some(stats.std().into_iter().map(|s| s as f32).collect())
+    }
+
+    /// Normalizes the flattened features of entities of the given type in place.
+    /// Features of entity types that the normalizer has not seen are left unchanged.
+    pub(crate) fn normalize(&self, entity: &str, features: &mut [f32]) {
+        let stats = match self.entities.get(entity) {
+            Some(stats) => stats,
+            None => return,
+        };
+        let std = stats.std();
+        for row in features.chunks_mut(stats.mean.len()) {
+            for (i, x) in row.iter_mut().enumerate() {
+                let normalized = (*x as f64 - stats.mean[i]) / std[i];
+                *x = (normalized as f32).clamp(-self.clip, self.clip);
+            }
+        }
+    }
+
+    /// Saves the normalizer to a file, usually `checkpoint_dir.join(ObsNormalizer::FILE_NAME)`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        std::fs::write(path, self.to_ron()?)
+    }
+
+    pub(crate) fn to_ron(&self) -> Result<String, io::Error> {
+        ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Loads a normalizer from a file written by [`ObsNormalizer::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        Self::from_reader(File::open(path)?)
+    }
+
+    pub(crate) fn from_reader<R: Read>(reader: R) -> Result<Self, io::Error> {
+        ron::de::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::Featurizable;
+
+    #[derive(Featurizable)]
+    struct Unit {
+        x: f32,
+        hp: f32,
+    }
+
+    #[test]
+    fn test_running_stats() {
+        let mut normalizer = ObsNormalizer::default();
+        normalizer.update(&Obs::new(0.0).entities([Unit { x: 1.0, hp: 10.0 }]));
+        normalizer.update(
+            &Obs::new(0.0).entities([Unit { x: 2.0, hp: 10.0 }, Unit { x: 3.0, hp: 10.0 }]),
+        );
+        assert_eq!(normalizer.mean("Unit"), Some(vec![2.0, 10.0]));
+        assert_eq!(normalizer.std("Unit"), Some(vec![1.0, 1.0]));
+        assert_eq!(normalizer.mean("Enemy"), None);
+
+        let mut features = vec![4.0, 10.0, 100.0, 0.0];
+        normalizer.normalize("Unit", &mut features);
+        assert_eq!(features, vec![2.0, 0.0, 5.0, -5.0]);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut normalizer = ObsNormalizer::default().with_clip(3.0);
+        normalizer.update(
+            &Obs::new(0.0).entities([Unit { x: 1.0, hp: 10.0 }, Unit { x: 5.0, hp: 20.0 }]),
+        );
+        let path = std::env::temp_dir().join(format!("obs_normalizer_{}.ron", std::process::id()));
+        normalizer.save(&path).unwrap();
+        let loaded = ObsNormalizer::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.mean("Unit"), normalizer.mean("Unit"));
+        assert_eq!(loaded.std("Unit"), normalizer.std("Unit"));
+        assert_eq!(loaded.clip, 3.0);
+    }
+}
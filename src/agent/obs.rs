@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
+use indexmap::IndexMap;
 use rustc_hash::FxHashMap;
 
-use super::Featurizable;
+use super::validation::validate;
+use super::{Action, ActionRequest, ActionType, EntityRef, Featurizable, ObsError};
 
 /// An observation that defines what an agent can see.
 ///
@@ -30,20 +32,45 @@ use super::Featurizable;
 ///     .entities([Player { x: 0, y: -3 }])
 ///     .entities([Cake { is_a_lie: true }, Cake { is_a_lie: false }]);
 /// ```
+///
+/// Actors are ordered by the order in which their entity types were first added to the observation.
+/// Agents return one action per actor in this order.
+/// By default, entities added with [`Obs::actors`] act for every action, [`Obs::action_actors`] restricts an action to specific entity types.
+#[derive(Clone)]
 pub struct Obs {
-    pub(crate) entities: HashMap<&'static str, EntityFeatures>,
-    // Field is only accessed when cfg(feature = "python").
-    #[allow(dead_code)]
+    pub(crate) entities: IndexMap<&'static str, EntityFeatures>,
     pub(crate) done: bool,
     pub(crate) score: f32,
     pub(crate) metrics: FxHashMap<String, f32>,
+    // Flattened masks of shape `(num_actors, num_actions)` for each action.
+    pub(crate) action_masks: FxHashMap<String, Vec<bool>>,
+    // Entity types that act for each action. Actions without an entry use all actor entities.
+    pub(crate) action_actors: FxHashMap<String, Vec<&'static str>>,
 }
 
+#[derive(Clone)]
 pub(crate) struct EntityFeatures {
     pub features: Vec<f32>,
     pub num_entities: usize,
     pub num_features: usize,
     pub is_actor: bool,
+    pub ids: Option<Vec<u64>>,
+    // Names of the features, if known. Entities added by a `Memory` have no feature names.
+    pub feature_names: Option<Arc<[String]>>,
+}
+
+// Observations are keyed by `&'static str`, so entity names that are created at runtime are leaked once and reused.
+pub(crate) fn intern(name: &str) -> &'static str {
+    static NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    let mut names = NAMES.lock().unwrap();
+    match names.iter().find(|n| **n == name) {
+        Some(n) => n,
+        None => {
+            let n: &'static str = Box::leak(name.to_string().into_boxed_str());
+            names.push(n);
+            n
+        }
+    }
 }
 
 impl Obs {
@@ -57,6 +84,8 @@ impl Obs {
             entities: Default::default(),
             done: false,
             metrics: Default::default(),
+            action_masks: Default::default(),
+            action_actors: Default::default(),
         }
     }
 
@@ -65,7 +94,7 @@ impl Obs {
     /// # Arguments
     /// * `entities` - An iterator of [`Featurizable`] entities to add to the observation.
     pub fn entities<E: Featurizable, I: IntoIterator<Item = E>>(self, entities: I) -> Self {
-        self._entities(entities, false)
+        self._entities(entities.into_iter().map(|e| (None, e)), false)
     }
 
     /// Adds a set of actor entities to the observation.
@@ -73,20 +102,77 @@ impl Obs {
     /// # Arguments
     /// * `entities` - An iterator of [`Featurizable`] entities to add to the observation.
     pub fn actors<E: Featurizable, I: IntoIterator<Item = E>>(self, entities: I) -> Self {
-        self._entities(entities, true)
+        self._entities(entities.into_iter().map(|e| (None, e)), true)
+    }
+
+    /// Adds a set of entities with stable ids to the observation.
+    ///
+    /// Ids allow actions to be mapped back to the actors that took them with [`AgentOps::act_by_id`](super::AgentOps::act_by_id),
+    /// and selected entities to be resolved with [`Obs::entity_id`].
+    ///
+    /// # Arguments
+    /// * `entities` - An iterator of ids and [`Featurizable`] entities to add to the observation.
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{Featurizable, Obs};
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Enemy { x: i32, y: i32 }
+    ///
+    /// let obs = Obs::new(0.0).entities_with_ids([(17, Enemy { x: 3, y: 1 }), (4, Enemy { x: 0, y: 2 })]);
+    /// ```
+    pub fn entities_with_ids<E: Featurizable, I: IntoIterator<Item = (u64, E)>>(
+        self,
+        entities: I,
+    ) -> Self {
+        self._entities(entities.into_iter().map(|(id, e)| (Some(id), e)), false)
+    }
+
+    /// Adds a set of actor entities with stable ids to the observation.
+    ///
+    /// # Arguments
+    /// * `entities` - An iterator of ids and [`Featurizable`] entities to add to the observation.
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{self, Action, AgentOps, Featurizable, Obs};
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Unit { x: i32, y: i32 }
+    ///
+    /// #[derive(Action, Debug)]
+    /// enum Move { Up, Down, Left, Right }
+    ///
+    /// let obs = Obs::new(0.0).actors_with_ids([(17, Unit { x: 3, y: 1 }), (4, Unit { x: 0, y: 2 })]);
+    /// let moves = agent::random().act_by_id::<Move>(&obs).unwrap();
+    /// println!("Unit 17 moves {:?}", moves[&17]);
+    /// ```
+    pub fn actors_with_ids<E: Featurizable, I: IntoIterator<Item = (u64, E)>>(
+        self,
+        entities: I,
+    ) -> Self {
+        self._entities(entities.into_iter().map(|(id, e)| (Some(id), e)), true)
     }
 
-    fn _entities<E: Featurizable, I: IntoIterator<Item = E>>(
+    fn _entities<E: Featurizable, I: IntoIterator<Item = (Option<u64>, E)>>(
         mut self,
         entities: I,
         is_actor: bool,
     ) -> Self {
         let mut feats = vec![];
         let mut count = 0;
-        for entity in entities.into_iter() {
+        let mut ids = vec![];
+        for (id, entity) in entities.into_iter() {
             feats.extend(entity.featurize());
             count += 1;
+            ids.extend(id);
         }
+        assert!(
+            ids.is_empty() || ids.len() == count,
+            "Either all or none of the \"{}\" entities must have ids",
+            E::name(),
+        );
         self.entities.insert(
             E::name(),
             EntityFeatures {
@@ -94,6 +180,12 @@ impl Obs {
                 num_entities: count,
                 num_features: E::num_feats(),
                 is_actor,
+                ids: if count > 0 && ids.len() == count {
+                    Some(ids)
+                } else {
+                    None
+                },
+                feature_names: Some(E::feature_names().into()),
             },
         );
         self
@@ -118,6 +210,95 @@ impl Obs {
         self
     }
 
+    /// Restricts the choices that each actor can make for the action `A`.
+    ///
+    /// # Arguments
+    /// * `mask` - One mask per actor of `A`, in the same order as the actors. Each mask contains one `bool` for every choice of `A` which is `true` if the choice is allowed.
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{Action, Featurizable, Obs};
+    ///
+    /// #[derive(Action)]
+    /// enum Move { Up, Down, Left, Right }
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Head { x: i32, y: i32 }
+    ///
+    /// // The snake is moving up and can't reverse direction.
+    /// let obs = Obs::new(0.0)
+    ///     .actors([Head { x: 3, y: 4 }])
+    ///     .action_mask::<Move>([[true, false, true, true]]);
+    /// ```
+    pub fn action_mask<'a, A: Action<'a>>(
+        mut self,
+        mask: impl IntoIterator<Item = impl AsRef<[bool]>>,
+    ) -> Self {
+        let mut flat_mask = vec![];
+        for actor_mask in mask {
+            let actor_mask = actor_mask.as_ref();
+            assert_eq!(
+                actor_mask.len() as u64,
+                A::num_actions(),
+                "Mask for action \"{}\" must have one entry for each of its {} choices",
+                A::name(),
+                A::num_actions(),
+            );
+            flat_mask.extend_from_slice(actor_mask);
+        }
+        self.action_masks.insert(A::name().to_string(), flat_mask);
+        self
+    }
+
+    /// Declares that entities of type `E` act for the action `A`.
+    ///
+    /// Once any entity type is declared for an action, only the declared entity types act for that action.
+    /// Actions without declared entity types are taken by all entities added with [`Obs::actors`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{Action, Featurizable, Obs, Select, SelectEntity};
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Worker { x: i32, y: i32 }
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Soldier { x: i32, y: i32 }
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Enemy { x: i32, y: i32 }
+    ///
+    /// #[derive(Action)]
+    /// enum Build { Barracks, Farm }
+    ///
+    /// struct Attack;
+    ///
+    /// impl SelectEntity for Attack {
+    ///     type Target = Enemy;
+    ///
+    ///     fn name() -> &'static str {
+    ///         "Attack"
+    ///     }
+    /// }
+    ///
+    /// let obs = Obs::new(0.0)
+    ///     .entities([Worker { x: 0, y: 0 }])
+    ///     .entities([Soldier { x: 1, y: 0 }, Soldier { x: 2, y: 0 }])
+    ///     .entities([Enemy { x: 5, y: 5 }])
+    ///     .action_actors::<Build, Worker>()
+    ///     .action_actors::<Select<Attack>, Soldier>();
+    /// ```
+    pub fn action_actors<A: ActionType, E: Featurizable>(mut self) -> Self {
+        let actors = self
+            .action_actors
+            .entry(A::request().name().to_string())
+            .or_default();
+        if !actors.contains(&E::name()) {
+            actors.push(E::name());
+        }
+        self
+    }
+
     /// Returns the score.
     pub fn score(&self) -> f32 {
         self.score
@@ -133,4 +314,334 @@ impl Obs {
         }
         actor_count
     }
+
+    /// Returns the id of an entity selected by a [`SelectEntity`](super::SelectEntity) action, if the entity was added with an id.
+    pub fn entity_id<E: Featurizable>(&self, entity: EntityRef<E>) -> Option<u64> {
+        self.entities
+            .get(E::name())?
+            .ids
+            .as_ref()?
+            .get(entity.index())
+            .copied()
+    }
+
+    /// Returns the number of entities of type `E` in the observation.
+    pub fn num_entities<E: Featurizable>(&self) -> usize {
+        self.entities.get(E::name()).map_or(0, |e| e.num_entities)
+    }
+
+    /// Returns the features of the entities of type `E`, one row per entity.
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{Featurizable, Obs};
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Enemy { x: i32, y: i32 }
+    ///
+    /// let obs = Obs::new(0.0).entities([Enemy { x: 3, y: 1 }, Enemy { x: 0, y: 2 }]);
+    /// let enemies = obs.features::<Enemy>();
+    /// assert_eq!(enemies.feature_names(), ["x", "y"]);
+    /// assert_eq!(enemies.row(1), [0.0, 2.0]);
+    /// assert_eq!(enemies.get(0, "x"), Some(3.0));
+    /// ```
+    pub fn features<E: Featurizable>(&self) -> EntityRows<'_> {
+        let feature_names = E::feature_names();
+        match self.entities.get(E::name()) {
+            Some(e) => EntityRows {
+                feature_names,
+                features: &e.features,
+                num_entities: e.num_entities,
+                ids: e.ids.as_deref(),
+            },
+            None => EntityRows {
+                feature_names,
+                features: &[],
+                num_entities: 0,
+                ids: None,
+            },
+        }
+    }
+
+    /// Returns an iterator over all entities that were added with [`Obs::actors`] or [`Obs::actors_with_ids`].
+    ///
+    /// These are the actors of all actions that have no actors declared with [`Obs::action_actors`],
+    /// in the order in which agents return those actions. Use [`Obs::iter_action_actors`] for the actors of a specific action.
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{Featurizable, Obs};
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Unit { x: i32, y: i32 }
+    ///
+    /// let obs = Obs::new(0.0).actors_with_ids([(17, Unit { x: 3, y: 1 }), (4, Unit { x: 0, y: 2 })]);
+    /// for actor in obs.iter_actors() {
+    ///     println!("{} {:?} is at {:?}", actor.entity, actor.id, actor.features);
+    /// }
+    /// ```
+    pub fn iter_actors(&self) -> impl Iterator<Item = Actor<'_>> {
+        self.entities
+            .iter()
+            .filter(|(_, e)| e.is_actor)
+            .flat_map(|(name, e)| actors(name, e))
+    }
+
+    /// Returns an iterator over the actors of action `A`, in the order in which agents return actions of type `A`.
+    ///
+    /// If entity types were declared with [`Obs::action_actors`] for `A`, these are its actors. Otherwise, returns the same actors as [`Obs::iter_actors`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{Action, Featurizable, Obs};
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Worker { x: i32, y: i32 }
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Soldier { x: i32, y: i32 }
+    ///
+    /// #[derive(Action)]
+    /// enum Build { House, Barracks }
+    ///
+    /// let obs = Obs::new(0.0)
+    ///     .actors([Soldier { x: 1, y: 0 }])
+    ///     .entities([Worker { x: 0, y: 0 }])
+    ///     .action_actors::<Build, Worker>();
+    /// assert_eq!(obs.iter_action_actors::<Build>().map(|actor| actor.entity).collect::<Vec<_>>(), ["Worker"]);
+    /// ```
+    pub fn iter_action_actors<A: ActionType>(&self) -> impl Iterator<Item = Actor<'_>> {
+        self.actor_types(A::request().name())
+            .flat_map(|(name, e)| actors(name, e))
+    }
+
+    /// Checks the observation for NaN or infinite features and for requested actions without any actors.
+    ///
+    /// Returns all problems that were found. A [`TrainAgent`](super::TrainAgent) additionally checks that all entity types
+    /// were registered with the [`TrainEnvBuilder`](super::TrainEnvBuilder), see [`TrainEnvBuilder::validate_obs`](super::TrainEnvBuilder::validate_obs).
+    pub fn validate(&self, requests: &[ActionRequest]) -> Vec<ObsError> {
+        validate(self, requests, None)
+    }
+
+    /// Returns the ids of the actors for the given action.
+    pub(crate) fn actor_ids(&self, action: &str) -> Vec<u64> {
+        let mut ids = vec![];
+        for (name, entity) in self.actor_types(action) {
+            match &entity.ids {
+                Some(entity_ids) => ids.extend(entity_ids),
+                None if entity.num_entities == 0 => {}
+                None => panic!(
+                    "Actors of type \"{}\" have no ids, use `Obs::actors_with_ids` or `Obs::entities_with_ids` to add them",
+                    name
+                ),
+            }
+        }
+        ids
+    }
+
+    /// Returns the entity types that act for the given action, in the order of the observation.
+    pub(crate) fn actor_types<'a>(
+        &'a self,
+        action: &'a str,
+    ) -> impl Iterator<Item = (&'static str, &'a EntityFeatures)> + 'a {
+        let declared = self.action_actors.get(action);
+        self.entities
+            .iter()
+            .filter(move |(name, e)| match declared {
+                Some(declared) => declared.contains(name),
+                None => e.is_actor,
+            })
+            .map(|(name, e)| (*name, e))
+    }
+
+    /// The number of actors for the given action.
+    pub(crate) fn num_action_actors(&self, action: &str) -> usize {
+        self.actor_types(action).map(|(_, e)| e.num_entities).sum()
+    }
+
+    /// Returns the flattened mask for the given action, if any.
+    pub(crate) fn mask(&self, action: &str, num_actions: u64) -> Option<&Vec<bool>> {
+        let mask = self.action_masks.get(action)?;
+        assert_eq!(
+            mask.len(),
+            self.num_action_actors(action) * num_actions as usize,
+            "Mask for action \"{}\" must contain one entry per actor",
+            action,
+        );
+        Some(mask)
+    }
+}
+
+/// Features of the entities of one type in an [`Obs`], returned by [`Obs::features`].
+pub struct EntityRows<'a> {
+    feature_names: Vec<String>,
+    features: &'a [f32],
+    num_entities: usize,
+    ids: Option<&'a [u64]>,
+}
+
+impl<'a> EntityRows<'a> {
+    /// Returns the names of the features in each row.
+    pub fn feature_names(&self) -> &[String] {
+        &self.feature_names
+    }
+
+    /// Returns the number of entities.
+    pub fn len(&self) -> usize {
+        self.num_entities
+    }
+
+    /// Returns `true` if there are no entities of this type.
+    pub fn is_empty(&self) -> bool {
+        self.num_entities == 0
+    }
+
+    /// Returns the features of the entity at `index`.
+    pub fn row(&self, index: usize) -> &'a [f32] {
+        assert!(
+            index < self.num_entities,
+            "Entity index {} out of bounds for {} entities",
+            index,
+            self.num_entities
+        );
+        let n = self.feature_names.len();
+        &self.features[index * n..(index + 1) * n]
+    }
+
+    /// Returns the feature called `name` of the entity at `index`, or `None` if there is no such feature.
+    pub fn get(&self, index: usize, name: &str) -> Option<f32> {
+        let feature = self.feature_names.iter().position(|n| n == name)?;
+        Some(self.row(index)[feature])
+    }
+
+    /// Returns the ids of the entities, if they were added with ids.
+    pub fn ids(&self) -> Option<&'a [u64]> {
+        self.ids
+    }
+
+    /// Returns an iterator over the features of every entity.
+    pub fn iter(&self) -> impl Iterator<Item = &'a [f32]> + '_ {
+        (0..self.num_entities).map(move |i| self.row(i))
+    }
+}
+
+// Returns every entity of type `name` as an actor.
+fn actors<'a>(name: &'static str, e: &'a EntityFeatures) -> impl Iterator<Item = Actor<'a>> {
+    (0..e.num_entities).map(move |index| Actor {
+        entity: name,
+        index,
+        id: e.ids.as_ref().map(|ids| ids[index]),
+        features: &e.features[index * e.num_features..(index + 1) * e.num_features],
+    })
+}
+
+/// An actor entity in an [`Obs`], returned by [`Obs::iter_actors`] and [`Obs::iter_action_actors`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Actor<'a> {
+    /// Name of the entity type.
+    pub entity: &'static str,
+    /// Position of the actor among the entities of its type.
+    pub index: usize,
+    /// Id of the actor, if it was added with an id.
+    pub id: Option<u64>,
+    /// Features of the actor.
+    pub features: &'a [f32],
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        value: u32,
+    }
+
+    #[derive(Featurizable)]
+    struct Wall {}
+
+    #[test]
+    fn test_read_access() {
+        let obs = Obs::new(0.0)
+            .entities([Food { value: 3 }])
+            .actors_with_ids([(7, Head { x: 1.0, y: 2.0 }), (3, Head { x: 3.0, y: 4.0 })])
+            .entities([Wall {}, Wall {}]);
+
+        assert_eq!(obs.num_entities::<Head>(), 2);
+        assert_eq!(obs.num_entities::<Wall>(), 2);
+        let heads = obs.features::<Head>();
+        assert_eq!(heads.feature_names(), ["x", "y"]);
+        assert_eq!(heads.len(), 2);
+        assert_eq!(heads.row(1), [3.0, 4.0]);
+        assert_eq!(heads.get(0, "y"), Some(2.0));
+        assert_eq!(heads.get(0, "z"), None);
+        assert_eq!(heads.ids(), Some(&[7, 3][..]));
+        assert_eq!(heads.iter().collect::<Vec<_>>(), [[1.0, 2.0], [3.0, 4.0]]);
+        assert_eq!(obs.features::<Wall>().iter().count(), 2);
+        assert_eq!(obs.features::<Food>().ids(), None);
+
+        let empty = Obs::new(0.0);
+        assert_eq!(empty.num_entities::<Head>(), 0);
+        assert!(empty.features::<Head>().is_empty());
+
+        let actors = obs.iter_actors().collect::<Vec<_>>();
+        assert_eq!(
+            actors,
+            [
+                Actor {
+                    entity: "Head",
+                    index: 0,
+                    id: Some(7),
+                    features: &[1.0, 2.0],
+                },
+                Actor {
+                    entity: "Head",
+                    index: 1,
+                    id: Some(3),
+                    features: &[3.0, 4.0],
+                },
+            ]
+        );
+    }
+
+    #[derive(Action)]
+    enum Eat {
+        Yes,
+        No,
+    }
+
+    #[derive(Action)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    #[test]
+    fn test_iter_action_actors() {
+        let obs = Obs::new(0.0)
+            .actors([Head { x: 1.0, y: 2.0 }])
+            .entities_with_ids([(5, Food { value: 3 })])
+            .action_actors::<Eat, Food>();
+        let eaters = obs.iter_action_actors::<Eat>().collect::<Vec<_>>();
+        assert_eq!(
+            eaters,
+            [Actor {
+                entity: "Food",
+                index: 0,
+                id: Some(5),
+                features: &[3.0],
+            }]
+        );
+        assert_eq!(
+            obs.iter_action_actors::<Move>().collect::<Vec<_>>(),
+            obs.iter_actors().collect::<Vec<_>>()
+        );
+        assert_eq!(obs.iter_actors().next().unwrap().entity, "Head");
+    }
 }
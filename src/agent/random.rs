@@ -1,7 +1,7 @@
 use rand::prelude::SmallRng;
 use rand::{Rng, SeedableRng};
 
-use super::{ActionReceiver, Agent, Obs};
+use super::{ActionInfo, ActionReceiver, ActionRequest, Agent, Obs};
 
 /// Agent that samples all actions uniformly at random.
 pub struct RandomAgent {
@@ -9,18 +9,36 @@ pub struct RandomAgent {
 }
 
 impl Agent for RandomAgent {
-    fn act_dyn(&mut self, _action: &str, num_actions: u64, obs: &Obs) -> Option<Vec<u64>> {
-        let actions = (0..obs.num_actors())
-            .map(|_| self.rng.gen_range(0..num_actions))
-            .collect();
-        Some(actions)
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        Some(self.random_multi(actions, obs))
     }
 
-    fn act_async_dyn(&mut self, _action: &str, num_actions: u64, obs: &Obs) -> ActionReceiver<u64> {
-        let actions = (0..obs.num_actors())
-            .map(|_| self.rng.gen_range(0..num_actions))
-            .collect();
-        ActionReceiver::value(actions)
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        ActionReceiver::value(Some(self.random_multi(actions, obs)))
+    }
+
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        let acts = self.random_multi(actions, obs);
+        Some(
+            actions
+                .iter()
+                .zip(acts)
+                .map(|(request, acts)| {
+                    acts.into_iter()
+                        .enumerate()
+                        .map(|(actor, action)| ActionInfo {
+                            action,
+                            probs: Some(uniform_probs(request, actor, obs)),
+                            value: None,
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
     }
 
     fn game_over(&mut self, _: &Obs) {}
@@ -33,6 +51,81 @@ impl RandomAgent {
             rng: SmallRng::seed_from_u64(seed),
         }
     }
+
+    fn random_multi(&mut self, actions: &[ActionRequest], obs: &Obs) -> Vec<Vec<u64>> {
+        actions
+            .iter()
+            .map(|request| match *request {
+                ActionRequest::Categorical { name, num_actions } => {
+                    self.random_actions(name, num_actions, obs)
+                }
+                ActionRequest::SelectEntity { name, target } => {
+                    self.select_random_entities(name, target, obs)
+                }
+            })
+            .collect()
+    }
+
+    fn random_actions(&mut self, action: &str, num_actions: u64, obs: &Obs) -> Vec<u64> {
+        match obs.mask(action, num_actions) {
+            Some(mask) => mask
+                .chunks(num_actions as usize)
+                .map(|actor_mask| {
+                    let allowed = actor_mask.iter().filter(|&&m| m).count();
+                    assert!(
+                        allowed > 0,
+                        "Mask for action \"{}\" does not allow any choices",
+                        action
+                    );
+                    let choice = self.rng.gen_range(0..allowed);
+                    actor_mask
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, &m)| m)
+                        .nth(choice)
+                        .unwrap()
+                        .0 as u64
+                })
+                .collect(),
+            None => (0..obs.num_action_actors(action))
+                .map(|_| self.rng.gen_range(0..num_actions))
+                .collect(),
+        }
+    }
+
+    fn select_random_entities(&mut self, action: &str, target: &str, obs: &Obs) -> Vec<u64> {
+        let num_actors = obs.num_action_actors(action);
+        let num_targets = obs.entities.get(target).map_or(0, |e| e.num_entities) as u64;
+        assert!(
+            num_actors == 0 || num_targets > 0,
+            "Observation contains no \"{}\" entities that could be selected",
+            target
+        );
+        (0..num_actors)
+            .map(|_| self.rng.gen_range(0..num_targets))
+            .collect()
+    }
+}
+
+/// Returns the probabilities with which the `actor` chooses each option of the requested action.
+fn uniform_probs(request: &ActionRequest, actor: usize, obs: &Obs) -> Vec<f32> {
+    let allowed = match *request {
+        ActionRequest::Categorical { name, num_actions } => match obs.mask(name, num_actions) {
+            Some(mask) => {
+                let n = num_actions as usize;
+                mask[actor * n..(actor + 1) * n].to_vec()
+            }
+            None => vec![true; num_actions as usize],
+        },
+        ActionRequest::SelectEntity { target, .. } => {
+            vec![true; obs.entities.get(target).map_or(0, |e| e.num_entities)]
+        }
+    };
+    let p = 1.0 / allowed.iter().filter(|&&a| a).count() as f32;
+    allowed
+        .into_iter()
+        .map(|a| if a { p } else { 0.0 })
+        .collect()
 }
 
 impl Default for RandomAgent {
@@ -40,3 +133,63 @@ impl Default for RandomAgent {
         RandomAgent::from_seed(0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Action, AgentOps, Featurizable};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: i32,
+    }
+
+    #[derive(Action, Debug, PartialEq, Eq)]
+    enum Move {
+        Up,
+        Down,
+        Left,
+        Right,
+    }
+
+    #[test]
+    fn test_action_mask() {
+        let obs = Obs::new(0.0)
+            .actors([Head { x: 0 }, Head { x: 1 }])
+            .action_mask::<Move>([[false, false, true, false], [true, false, false, true]]);
+        let mut agent = RandomAgent::default();
+        for _ in 0..100 {
+            let actions = agent.act::<Move>(&obs).unwrap();
+            assert_eq!(actions[0], Move::Left);
+            assert!(actions[1] == Move::Up || actions[1] == Move::Right);
+        }
+    }
+
+    #[test]
+    fn test_act_by_id() {
+        let obs = Obs::new(0.0)
+            .actors_with_ids([(7, Head { x: 0 }), (3, Head { x: 1 })])
+            .action_mask::<Move>([[false, false, true, false], [true, false, false, false]]);
+        let actions = RandomAgent::default().act_by_id::<Move>(&obs).unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[&7], Move::Left);
+        assert_eq!(actions[&3], Move::Up);
+    }
+
+    #[test]
+    fn test_act_info() {
+        let obs = Obs::new(0.0)
+            .actors([Head { x: 0 }, Head { x: 1 }, Head { x: 2 }])
+            .action_mask::<Move>([
+                [false, false, true, false],
+                [true, false, false, true],
+                [true, true, true, true],
+            ]);
+        let info = RandomAgent::default().act_info::<Move>(&obs).unwrap();
+        assert_eq!(info[0].action, Move::Left);
+        assert_eq!(info[0].probs, Some(vec![0.0, 0.0, 1.0, 0.0]));
+        assert_eq!(info[1].probs, Some(vec![0.5, 0.0, 0.0, 0.5]));
+        assert_eq!(info[2].probs, Some(vec![0.25; 4]));
+        assert_eq!(info[2].value, None);
+    }
+}
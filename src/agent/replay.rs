@@ -0,0 +1,335 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use super::{
+    ActionReceiver, ActionRequest, Agent, Obs, RecordedRequest, TrajectoryEvent, TrajectoryReader,
+};
+
+/// [`Agent`] that plays back the actions of a trajectory recorded with [`TrajectoryRecorder`](super::TrajectoryRecorder).
+///
+/// Every request for actions returns the actions of the next recorded step, and every call to [`Agent::game_over`]
+/// consumes the next recorded game over. Once the end of the trajectory is reached, the agent returns `None`.
+/// The replayed game must request the same actions as the recorded game, otherwise the agent panics.
+///
+/// With [`ReplayAgent::check_obs`], the agent also panics if an observation differs from the recorded observation.
+/// This makes it possible to deterministically reproduce a recorded game and to check that changes to the game logic
+/// don't alter its behavior.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{self, Action, Agent, AgentOps, Featurizable, Obs, ReplayAgent, TrajectoryRecorder, TrajectoryWriter};
+///
+/// #[derive(Action, Debug, PartialEq, Eq)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let path = std::env::temp_dir().join(format!("replay-doctest-{}.traj", std::process::id()));
+/// let obs = Obs::new(0.0).actors([Head { x: 0.0, y: 0.0 }]);
+///
+/// let mut recorder = TrajectoryRecorder::new(agent::random(), TrajectoryWriter::create(&path).unwrap());
+/// let recorded = recorder.act::<Move>(&obs);
+/// recorder.flush().unwrap();
+///
+/// let mut replay = ReplayAgent::open(&path).unwrap().check_obs(1e-6);
+/// assert_eq!(replay.act::<Move>(&obs), recorded);
+/// assert_eq!(replay.act::<Move>(&obs), None);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct ReplayAgent<R: Read> {
+    reader: TrajectoryReader<R>,
+    tolerance: Option<f32>,
+    // Index of the next step or game over in the trajectory.
+    record: usize,
+}
+
+impl ReplayAgent<BufReader<File>> {
+    /// Opens the trajectory file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        Ok(ReplayAgent::new(TrajectoryReader::open(path)?))
+    }
+}
+
+impl<R: Read> ReplayAgent<R> {
+    /// Creates an agent that plays back the trajectory read by `reader`.
+    pub fn new(reader: TrajectoryReader<R>) -> Self {
+        ReplayAgent {
+            reader,
+            tolerance: None,
+            record: 0,
+        }
+    }
+
+    /// Panics if a feature, metric or score of an observation differs from the recorded value by more than `tolerance`,
+    /// or if the entities or action masks of an observation differ from the recording.
+    pub fn check_obs(mut self, tolerance: f32) -> Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+
+    fn next_event(&mut self) -> Option<TrajectoryEvent> {
+        let event = self
+            .reader
+            .read_event()
+            .unwrap_or_else(|err| panic!("Failed to read trajectory: {}", err));
+        self.record += 1;
+        event
+    }
+
+    fn replay(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        match self.next_event()? {
+            TrajectoryEvent::Step {
+                obs: recorded,
+                requests,
+                actions: recorded_actions,
+                ..
+            } => {
+                let requested = actions
+                    .iter()
+                    .map(RecordedRequest::from)
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    requested,
+                    requests,
+                    "Actions requested at record {} do not match the recording",
+                    self.record - 1,
+                );
+                self.check(&recorded, obs);
+                recorded_actions
+            }
+            TrajectoryEvent::GameOver { .. } => panic!(
+                "Actions requested at record {}, but the recording contains a game over",
+                self.record - 1
+            ),
+        }
+    }
+
+    fn check(&self, recorded: &Obs, obs: &Obs) {
+        if let Some(tolerance) = self.tolerance {
+            if let Err(diff) = compare_obs(recorded, obs, tolerance) {
+                panic!(
+                    "Observation at record {} does not match the recording: {}",
+                    self.record - 1,
+                    diff
+                );
+            }
+        }
+    }
+}
+
+impl<R: Read> Agent for ReplayAgent<R> {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        self.replay(actions, obs)
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        ActionReceiver::value(self.replay(actions, obs))
+    }
+
+    fn game_over(&mut self, obs: &Obs) {
+        match self.next_event() {
+            Some(TrajectoryEvent::GameOver { obs: recorded, .. }) => self.check(&recorded, obs),
+            Some(TrajectoryEvent::Step { .. }) => panic!(
+                "Game over at record {}, but the recording requests actions",
+                self.record - 1
+            ),
+            None => {}
+        }
+    }
+}
+
+fn close(recorded: f32, actual: f32, tolerance: f32) -> bool {
+    recorded == actual
+        || (recorded - actual).abs() <= tolerance
+        || (recorded.is_nan() && actual.is_nan())
+}
+
+/// Returns a description of the first difference between a recorded and an actual observation.
+fn compare_obs(recorded: &Obs, actual: &Obs, tolerance: f32) -> Result<(), String> {
+    if !close(recorded.score, actual.score, tolerance) {
+        return Err(format!(
+            "score is {} but {} was recorded",
+            actual.score, recorded.score
+        ));
+    }
+
+    let count = |obs: &Obs, name: &str| obs.entities.get(name).map_or(0, |e| e.num_entities);
+    for name in recorded.entities.keys().chain(actual.entities.keys()) {
+        let (expected, observed) = (count(recorded, name), count(actual, name));
+        if expected != observed {
+            return Err(format!(
+                "observation contains {} entities of type \"{}\" but {} were recorded",
+                observed, name, expected
+            ));
+        }
+    }
+    for (name, expected) in &recorded.entities {
+        let observed = match actual.entities.get(name) {
+            Some(observed) => observed,
+            None => continue,
+        };
+        if expected.num_entities == 0 {
+            continue;
+        }
+        if expected.num_features != observed.num_features {
+            return Err(format!(
+                "entities of type \"{}\" have {} features but {} were recorded",
+                name, observed.num_features, expected.num_features
+            ));
+        }
+        if expected.is_actor != observed.is_actor {
+            return Err(format!(
+                "entities of type \"{}\" are {}actors but were {}recorded as actors",
+                name,
+                if observed.is_actor { "" } else { "not " },
+                if expected.is_actor { "" } else { "not " },
+            ));
+        }
+        if expected.ids != observed.ids {
+            return Err(format!(
+                "entities of type \"{}\" have ids {:?} but {:?} were recorded",
+                name, observed.ids, expected.ids
+            ));
+        }
+        let mismatch = expected
+            .features
+            .iter()
+            .zip(&observed.features)
+            .position(|(&e, &o)| !close(e, o, tolerance));
+        if let Some(i) = mismatch {
+            return Err(format!(
+                "feature {} of entity {} of type \"{}\" is {} but {} was recorded",
+                i % expected.num_features,
+                i / expected.num_features,
+                name,
+                observed.features[i],
+                expected.features[i]
+            ));
+        }
+    }
+
+    for name in recorded.metrics.keys().chain(actual.metrics.keys()) {
+        match (recorded.metrics.get(name), actual.metrics.get(name)) {
+            (Some(&expected), Some(&observed)) if close(expected, observed, tolerance) => {}
+            (expected, observed) => {
+                return Err(format!(
+                    "metric \"{}\" is {:?} but {:?} was recorded",
+                    name, observed, expected
+                ))
+            }
+        }
+    }
+
+    if recorded.action_masks != actual.action_masks {
+        return Err(format!(
+            "action masks are {:?} but {:?} were recorded",
+            actual.action_masks, recorded.action_masks
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{
+        Action, AgentOps, Featurizable, RandomAgent, Select, SelectEntity, TrajectoryRecorder,
+        TrajectoryWriter,
+    };
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        x: f32,
+    }
+
+    #[derive(Action, Debug, Clone, Copy, PartialEq, Eq)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    struct Eat;
+
+    impl SelectEntity for Eat {
+        type Target = Food;
+
+        fn name() -> &'static str {
+            "Eat"
+        }
+    }
+
+    fn obs(step: usize) -> Obs {
+        Obs::new(step as f32)
+            .actors([Head { x: step as f32 }])
+            .entities((0..step).map(|i| Food { x: i as f32 }))
+            .metric("step", step as f32)
+    }
+
+    fn record(name: &str) -> (std::path::PathBuf, Vec<Option<Vec<Move>>>) {
+        let path =
+            std::env::temp_dir().join(format!("test-replay-{}-{}.traj", name, std::process::id()));
+        let writer = TrajectoryWriter::create(&path).unwrap();
+        let mut agent = TrajectoryRecorder::new(RandomAgent::from_seed(0), writer);
+        let mut moves = vec![];
+        for episode in 0..2 {
+            for step in 1..4 {
+                moves.push(agent.act::<Move>(&obs(step)));
+                agent.select_entity::<Eat>(&obs(step));
+            }
+            agent.game_over(&obs(episode));
+        }
+        agent.flush().unwrap();
+        (path, moves)
+    }
+
+    #[test]
+    fn test_replay() {
+        let (path, moves) = record("replay");
+        let mut agent = ReplayAgent::open(&path).unwrap().check_obs(0.0);
+        let mut replayed = vec![];
+        for episode in 0..2 {
+            for step in 1..4 {
+                replayed.push(agent.act_async::<Move>(&obs(step)).rcv());
+                agent.select_entity::<Eat>(&obs(step)).unwrap();
+            }
+            agent.game_over(&obs(episode));
+        }
+        assert_eq!(replayed, moves);
+        assert_eq!(agent.act::<Move>(&obs(1)), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Observation at record 2 does not match the recording: feature 0 of entity 1 of type \"Food\" is 1.5 but 1 was recorded"
+    )]
+    fn test_replay_mismatch() {
+        let (path, _) = record("mismatch");
+        let mut agent = ReplayAgent::open(&path).unwrap().check_obs(0.1);
+        agent.act::<Move>(&obs(1));
+        agent.select_entity::<Eat>(&obs(1));
+        std::fs::remove_file(&path).unwrap();
+        let obs = Obs::new(2.0)
+            .actors([Head { x: 2.05 }])
+            .entities([Food { x: 0.0 }, Food { x: 1.5 }])
+            .metric("step", 2.0);
+        agent.act::<Move>(&obs);
+    }
+
+    #[test]
+    #[should_panic(expected = "Actions requested at record 1 do not match the recording")]
+    fn test_replay_wrong_request() {
+        let (path, _) = record("request");
+        let mut agent = ReplayAgent::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        agent.act::<Move>(&obs(1));
+        agent.act::<(Move, Select<Eat>)>(&obs(1));
+    }
+}
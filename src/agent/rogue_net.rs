@@ -1,30 +1,58 @@
+use std::collections::HashMap;
 use std::fs::File;
+use std::path::Path;
 
 use ndarray::Array2;
-use rogue_net::{FwdArgs, RogueNet};
+use rand::prelude::SmallRng;
+use rand::{Rng, SeedableRng};
+use rogue_net::{BatchProbs, FwdArgs, RogueNet};
 
 use super::obs::EntityFeatures;
-use super::{ActionReceiver, Agent};
-use super::{Featurizable, Obs};
+use super::{ActionInfo, ActionReceiver, ActionRequest, ActionSet, Agent};
+use super::{Featurizable, Memory, Obs, ObsNormalizer};
 
 /// Agent that implements the [RogueNet entity neural network](https://github.com/entity-neural-network/rogue-net).
 /// Can be loaded from checkpoints produced by [enn-trainer](https://github.com/entity-neural-network/enn-trainer).
 #[derive(Clone)]
 pub struct RogueNetAgent {
     pub(crate) net: RogueNet,
+    normalizer: Option<ObsNormalizer>,
+    sampling: Sampling,
+    rng: SmallRng,
+    pub(crate) memory: Option<Box<dyn Memory>>,
+}
+
+/// Determines how a [`RogueNetAgent`] chooses actions from the probabilities computed by the network.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sampling {
+    /// Samples actions from the probabilities computed by the network. This is the default.
+    #[default]
+    Stochastic,
+    /// Always chooses the most likely action.
+    Greedy,
+    /// Samples actions from the probabilities computed by the network after dividing the logits by the temperature.
+    ///
+    /// Temperatures below 1 make the agent more deterministic, temperatures above 1 make it more random.
+    Temperature(f32),
 }
 
 impl RogueNetAgent {
     /// Loads a neural network agent from an [enn-trainer](https://github.com/entity-neural-network/enn-trainer) checkpoint directory.
+    ///
+    /// If the checkpoint contains an [`ObsNormalizer`] file, observations are normalized with its statistics.
     pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self, std::io::Error> {
         let path = path.as_ref();
         match path.extension() {
-            Some(ext) if ext == "roguenet" => Ok(RogueNetAgent {
-                net: RogueNet::load_archive(File::open(path)?)?,
-            }),
-            _ => Ok(RogueNetAgent {
-                net: RogueNet::load(path),
-            }),
+            Some(ext) if ext == "roguenet" => Self::load_archive(File::open(path)?),
+            _ => {
+                let normalizer_path = path.join(ObsNormalizer::FILE_NAME);
+                let normalizer = if normalizer_path.exists() {
+                    Some(ObsNormalizer::load(normalizer_path)?)
+                } else {
+                    None
+                };
+                Ok(RogueNetAgent::new(RogueNet::load(path), normalizer))
+            }
         }
     }
 
@@ -36,8 +64,125 @@ impl RogueNetAgent {
     /// $ rogue-net archive --path path/to/checkpoint/dir
     /// ```
     pub fn load_archive<R: std::io::Read>(reader: R) -> Result<Self, std::io::Error> {
-        let net = RogueNet::load_archive(reader)?;
-        Ok(RogueNetAgent { net })
+        let (net, extra_files) = RogueNet::load_archive_with_extra_files(reader)?;
+        let mut normalizer = None;
+        for (path, contents) in extra_files {
+            if Path::new(&path).file_name() == Some(ObsNormalizer::FILE_NAME.as_ref()) {
+                normalizer = Some(ObsNormalizer::from_reader(&contents[..])?);
+            } else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Unexpected file: {}", path),
+                ));
+            }
+        }
+        Ok(RogueNetAgent::new(net, normalizer))
+    }
+
+    /// Saves the agent to a checkpoint directory that can be loaded with [`RogueNetAgent::load`].
+    ///
+    /// Networks adapted with [`RogueNetAgent::with_feature_adaptor`] are saved for the adapted observation space,
+    /// and the [`ObsNormalizer`] is saved alongside the network if one is set.
+    /// If the path has the extension `.roguenet`, the checkpoint is written as an archive instead.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        let path = path.as_ref();
+        match path.extension() {
+            Some(ext) if ext == "roguenet" => self.save_archive(File::create(path)?),
+            _ => {
+                self.net.save(path)?;
+                if let Some(normalizer) = &self.normalizer {
+                    normalizer.save(path.join(ObsNormalizer::FILE_NAME))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Saves the agent as an archive of a checkpoint directory that can be loaded with [`RogueNetAgent::load_archive`].
+    pub fn save_archive<W: std::io::Write>(&self, writer: W) -> Result<(), std::io::Error> {
+        let mut extra_files = HashMap::new();
+        if let Some(normalizer) = &self.normalizer {
+            extra_files.insert(
+                ObsNormalizer::FILE_NAME.to_string(),
+                normalizer.to_ron()?.into_bytes(),
+            );
+        }
+        self.net
+            .checkpoint()
+            .save_archive_with_extra_files(writer, &extra_files)
+    }
+
+    /// Sets how actions are chosen from the probabilities computed by the network.
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        if let Sampling::Temperature(temperature) = sampling {
+            assert!(temperature > 0.0, "Temperature must be positive");
+        }
+        self.sampling = sampling;
+        self
+    }
+
+    /// Seeds the random number generator used to sample actions, which otherwise uses a random seed.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Gives the agent a per-episode [`Memory`] that can add remembered entities to the network input.
+    ///
+    /// The memory is cleared when [`Agent::game_over`] is called. Clones of the agent have independent copies of the memory.
+    pub fn with_memory<M: Memory>(mut self, memory: M) -> Self {
+        self.memory = Some(Box::new(memory));
+        self
+    }
+
+    /// Returns the probability of each choice for every actor and each of the requested actions.
+    ///
+    /// The probabilities are computed by the network and do not depend on the [`Sampling`] mode.
+    /// The observation is not added to the agent's [`Memory`].
+    /// For categorical actions, each row holds the probabilities of the actions' variants for one actor.
+    /// For select entity actions, each row holds the probabilities of selecting each of the target entities.
+    pub fn action_probs_dyn(&self, actions: &[ActionRequest], obs: &Obs) -> Vec<Array2<f32>> {
+        let (mut args, heads) = self.request_args(actions, obs);
+        if let Some(memory) = &self.memory {
+            memory.clone().observe(obs, &mut args.features);
+        }
+        let heads = heads.iter().map(String::as_str).collect::<Vec<_>>();
+        self.net.forward_probs(args, &heads)
+    }
+
+    /// Typed version of [`RogueNetAgent::action_probs_dyn`] that returns the probabilities for each action in `A`.
+    ///
+    /// # Example
+    /// ```
+    /// use entity_gym_rs::agent::{Action, Featurizable, Obs, RogueNetAgent};
+    ///
+    /// #[derive(Action, Debug)]
+    /// enum Move { Up, Down, Left, Right }
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Head { x: f32, y: f32 }
+    /// #[derive(Featurizable)]
+    /// struct SnakeSegment { x: f32, y: f32 }
+    /// #[derive(Featurizable)]
+    /// struct Food { x: f32, y: f32 }
+    ///
+    /// let agent = RogueNetAgent::load("rogue-net/test-data/simple").unwrap();
+    /// let obs = Obs::new(0.0)
+    ///     .actors([Head { x: 3.0, y: 4.0 }])
+    ///     .entities([SnakeSegment { x: 3.0, y: 4.0 }])
+    ///     .entities([Food { x: 3.0, y: 5.0 }]);
+    /// let probs = agent.action_probs::<Move>(&obs);
+    /// // One row for the `Head` actor with the probabilities of `Up`, `Down`, `Left` and `Right`.
+    /// assert_eq!(probs[0].dim(), (1, 4));
+    /// ```
+    pub fn action_probs<A: ActionSet>(&self, obs: &Obs) -> Vec<Array2<f32>> {
+        self.action_probs_dyn(&A::requests(), obs)
+    }
+
+    /// Normalizes all observations with the given [`ObsNormalizer`] before passing them to the network.
+    pub fn with_obs_normalizer(mut self, normalizer: ObsNormalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
     }
 
     /// Adapts the network to a changed observation space.
@@ -55,8 +200,70 @@ impl RogueNetAgent {
     }
 }
 
-impl Agent for RogueNetAgent {
-    fn act_dyn(&mut self, _action: &str, _num_actions: u64, obs: &Obs) -> Option<Vec<u64>> {
+impl RogueNetAgent {
+    fn new(net: RogueNet, normalizer: Option<ObsNormalizer>) -> Self {
+        RogueNetAgent {
+            net,
+            normalizer,
+            sampling: Sampling::default(),
+            rng: SmallRng::from_entropy(),
+            memory: None,
+        }
+    }
+
+    /// Chooses an action for each row of `probs` according to the sampling mode.
+    pub(crate) fn sample(&mut self, probs: &Array2<f32>) -> Vec<u64> {
+        probs
+            .outer_iter()
+            .map(|probs| {
+                let probs = match self.sampling {
+                    Sampling::Stochastic => probs.to_owned(),
+                    Sampling::Greedy => return argmax(probs.iter().copied()),
+                    Sampling::Temperature(temperature) => {
+                        let logits = probs.mapv(|p| p.ln() / temperature);
+                        let max = logits.fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+                        let exp = logits.mapv(|l| (l - max).exp());
+                        &exp / exp.sum()
+                    }
+                };
+                let mut r = self.rng.gen::<f32>();
+                let mut choice = 0;
+                for (i, &p) in probs.iter().enumerate() {
+                    // Guards against rounding errors that would otherwise select a masked choice.
+                    if p > 0.0 {
+                        choice = i as u64;
+                    }
+                    r -= p;
+                    if r <= 0.0 {
+                        break;
+                    }
+                }
+                choice
+            })
+            .collect()
+    }
+
+    /// Chooses the actions for the output of a forward pass and attaches the probabilities and value estimate.
+    pub(crate) fn action_infos(&mut self, output: BatchProbs) -> Vec<Vec<ActionInfo<u64>>> {
+        let value = output.value;
+        output
+            .probs
+            .iter()
+            .map(|probs| {
+                self.sample(probs)
+                    .into_iter()
+                    .zip(probs.outer_iter())
+                    .map(|(action, probs)| ActionInfo {
+                        action,
+                        probs: Some(probs.to_vec()),
+                        value,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub(crate) fn fwd_args(&self, obs: &Obs) -> FwdArgs {
         let features = obs
             .entities
             .iter()
@@ -70,10 +277,13 @@ impl Agent for RogueNetAgent {
                         ..
                     },
                 )| {
+                    let mut features = features.clone();
+                    if let Some(normalizer) = &self.normalizer {
+                        normalizer.normalize(name, &mut features);
+                    }
                     (
                         name.to_string(),
-                        Array2::from_shape_vec((*num_entities, *num_features), features.clone())
-                            .unwrap(),
+                        Array2::from_shape_vec((*num_entities, *num_features), features).unwrap(),
                     )
                 },
             )
@@ -89,13 +299,464 @@ impl Agent for RogueNetAgent {
                 }
             })
             .collect();
-        let (_probs, acts) = self.net.forward(FwdArgs { features, actors });
-        Some(acts)
+        FwdArgs {
+            features,
+            actors,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the forward pass arguments for the observation and the action head for each requested action.
+    pub(crate) fn request_args(
+        &self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> (FwdArgs, Vec<String>) {
+        let mut heads = Vec::with_capacity(actions.len());
+        let mut args = self.fwd_args(obs);
+        for request in actions {
+            let name = request.name();
+            let head = match *request {
+                ActionRequest::Categorical { num_actions, .. } => {
+                    let head = self.action_head(name);
+                    if let Some(mask) = obs.mask(name, num_actions) {
+                        let shape = (obs.num_action_actors(name), num_actions as usize);
+                        args.action_masks.insert(
+                            head.to_string(),
+                            Array2::from_shape_vec(shape, mask.clone()).unwrap(),
+                        );
+                    }
+                    head
+                }
+                ActionRequest::SelectEntity { target, .. } => {
+                    args.actees
+                        .insert(name.to_string(), vec![target.to_string()]);
+                    name
+                }
+            };
+            if obs.action_actors.contains_key(name) {
+                let actors = obs
+                    .actor_types(name)
+                    .map(|(entity, _)| entity.to_string())
+                    .collect();
+                args.action_actors.insert(head.to_string(), actors);
+            }
+            heads.push(head.to_string());
+        }
+        (args, heads)
+    }
+
+    /// Returns the action head for the given categorical action.
+    /// Falls back to the only action head of single-action checkpoints where the action was registered under a different name.
+    fn action_head<'a>(&'a self, action: &'a str) -> &'a str {
+        if let Some(head) = self.net.actions().find(|a| *a == action) {
+            return head;
+        }
+        let mut heads = self.net.actions();
+        match (heads.next(), heads.next()) {
+            (Some(head), None) => head,
+            _ => panic!("Missing action head \"{}\"", action),
+        }
+    }
+}
+
+impl Agent for RogueNetAgent {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        let (mut args, heads) = self.request_args(actions, obs);
+        if let Some(memory) = &mut self.memory {
+            memory.observe(obs, &mut args.features);
+        }
+        let heads = heads.iter().map(String::as_str).collect::<Vec<_>>();
+        let probs = self.net.forward_probs(args, &heads);
+        Some(probs.iter().map(|probs| self.sample(probs)).collect())
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        ActionReceiver::value(Some(self.act_multi_dyn(actions, obs).unwrap()))
+    }
+
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        let (mut args, heads) = self.request_args(actions, obs);
+        if let Some(memory) = &mut self.memory {
+            memory.observe(obs, &mut args.features);
+        }
+        let heads = heads.iter().map(String::as_str).collect::<Vec<_>>();
+        let output = self.net.forward_batch_probs(vec![(args, heads)]).remove(0);
+        Some(self.action_infos(output))
+    }
+
+    fn game_over(&mut self, _: &Obs) {
+        if let Some(memory) = &mut self.memory {
+            memory.reset();
+        }
+    }
+}
+
+fn argmax(values: impl Iterator<Item = f32>) -> u64 {
+    let mut best = (0, f32::NEG_INFINITY);
+    for (i, v) in values.enumerate() {
+        if v > best.1 {
+            best = (i as u64, v);
+        }
+    }
+    best.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Action, AgentOps, Select, SelectEntity};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct SnakeSegment {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Action, Debug, PartialEq, Eq)]
+    enum Direction {
+        Up,
+        Down,
+        Left,
+        Right,
+    }
+
+    fn obs() -> Obs {
+        Obs::new(0.0)
+            .actors([Head { x: 3.0, y: 4.0 }])
+            .entities([
+                SnakeSegment { x: 3.0, y: 4.0 },
+                SnakeSegment { x: 4.0, y: 4.0 },
+            ])
+            .entities([Food { x: 3.0, y: 5.0 }, Food { x: 8.0, y: 4.0 }])
+    }
+
+    #[test]
+    fn test_act() {
+        let mut agent = RogueNetAgent::load("rogue-net/test-data/simple").unwrap();
+        let actions = agent.act::<Direction>(&obs()).unwrap();
+        assert_eq!(actions.len(), 1);
+    }
+
+    #[derive(Action, Debug, PartialEq, Eq)]
+    enum Ability {
+        Dash,
+        Wait,
     }
 
-    fn act_async_dyn(&mut self, action: &str, num_actions: u64, obs: &Obs) -> ActionReceiver<u64> {
-        ActionReceiver::value(self.act_dyn(action, num_actions, obs).unwrap())
+    #[derive(Action, Debug, PartialEq, Eq)]
+    enum Thrust {
+        Full,
+        Half,
     }
 
-    fn game_over(&mut self, _: &Obs) {}
+    // Turns the single head of the test checkpoint into a "Direction" head and adds an "Ability" head.
+    fn two_heads() -> RogueNetAgent {
+        let mut checkpoint = RogueNet::load("rogue-net/test-data/simple").checkpoint();
+        let mut tensors = indexmap::IndexMap::new();
+        for (key, (shape, data)) in &checkpoint.tensors {
+            match key.strip_prefix("action_heads.action.") {
+                Some(param) => {
+                    tensors.insert(
+                        format!("action_heads.Direction.{}", param),
+                        (shape.clone(), data.clone()),
+                    );
+                    // Keep the parameters of the first two choices.
+                    let len = data.len() / shape[0] * 2;
+                    let mut shape = shape.clone();
+                    shape[0] = 2;
+                    tensors.insert(
+                        format!("action_heads.Ability.{}", param),
+                        (shape, data[..len].to_vec()),
+                    );
+                }
+                None => {
+                    tensors.insert(key.clone(), (shape.clone(), data.clone()));
+                }
+            }
+        }
+        checkpoint.tensors = tensors;
+        let direction = checkpoint.action_space.swap_remove("action").unwrap();
+        checkpoint
+            .action_space
+            .insert("Direction".to_string(), direction);
+        checkpoint.action_space.insert(
+            "Ability".to_string(),
+            rogue_net::ActionSpace::CategoricalActionSpace {
+                index_to_label: Ability::labels(),
+            },
+        );
+        RogueNetAgent::new(RogueNet::from_checkpoint(&checkpoint), None)
+    }
+
+    struct Eat;
+
+    impl SelectEntity for Eat {
+        type Target = Food;
+
+        fn name() -> &'static str {
+            "Eat"
+        }
+    }
+
+    // Adds a select entity head for `Eat` to the simple checkpoint.
+    fn with_eat_head() -> RogueNetAgent {
+        let mut checkpoint = RogueNet::load("rogue-net/test-data/simple").checkpoint();
+        let d_model = checkpoint.config.d_model as usize;
+        let d_qk = checkpoint.config.d_qk as usize;
+        for proj in ["query_proj", "key_proj"] {
+            checkpoint.tensors.insert(
+                format!("action_heads.Eat.{}.weight", proj),
+                (vec![d_qk, d_model], vec![0.1; d_qk * d_model]),
+            );
+            checkpoint.tensors.insert(
+                format!("action_heads.Eat.{}.bias", proj),
+                (vec![d_qk], vec![0.0; d_qk]),
+            );
+        }
+        checkpoint.action_space.insert(
+            "Eat".to_string(),
+            rogue_net::ActionSpace::SelectEntityActionSpace,
+        );
+        RogueNetAgent::new(RogueNet::from_checkpoint(&checkpoint), None)
+    }
+
+    #[test]
+    fn test_select_entity() {
+        let mut agent = with_eat_head();
+        let targets = agent.act::<Select<Eat>>(&obs()).unwrap();
+        assert_eq!(targets.len(), 1);
+        assert!(targets[0].index() < 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Observation contains no \"Food\" entities that could be selected")]
+    fn test_select_entity_without_targets() {
+        let obs = Obs::new(0.0)
+            .actors([Head { x: 3.0, y: 4.0 }])
+            .entities([SnakeSegment { x: 3.0, y: 4.0 }])
+            .entities(Vec::<Food>::new());
+        with_eat_head().act::<Select<Eat>>(&obs);
+    }
+
+    #[test]
+    fn test_two_heads() {
+        let mut agent = two_heads();
+        let obs = obs().action_mask::<Ability>([[false, true]]);
+        let (moves, abilities) = agent.act::<(Direction, Ability)>(&obs).unwrap();
+        assert_eq!(moves.len(), 1);
+        assert_eq!(abilities, [Ability::Wait]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing action head \"Thrust\"")]
+    fn test_missing_head() {
+        two_heads().act::<(Direction, Thrust)>(&obs());
+    }
+
+    #[test]
+    fn test_action_actors() {
+        let mut agent = RogueNetAgent::load("rogue-net/test-data/simple").unwrap();
+        let obs = obs().action_actors::<Direction, Food>();
+        let actions = agent.act::<Direction>(&obs).unwrap();
+        assert_eq!(actions.len(), 2);
+    }
+
+    #[test]
+    fn test_action_mask() {
+        let mut agent = RogueNetAgent::load("rogue-net/test-data/simple").unwrap();
+        let obs = obs().action_mask::<Direction>([[false, false, true, false]]);
+        for _ in 0..10 {
+            assert_eq!(agent.act::<Direction>(&obs).unwrap(), [Direction::Left]);
+        }
+    }
+
+    #[test]
+    fn test_greedy() {
+        let mut agent = RogueNetAgent::load("rogue-net/test-data/simple")
+            .unwrap()
+            .with_sampling(Sampling::Greedy);
+        let obs = obs().action_actors::<Direction, Food>();
+        let probs = agent.action_probs::<Direction>(&obs).remove(0);
+        assert_eq!(probs.dim(), (2, 4));
+        let expected = probs
+            .outer_iter()
+            .map(|row| Direction::from_u64(argmax(row.iter().copied())))
+            .collect::<Vec<_>>();
+        for _ in 0..10 {
+            assert_eq!(agent.act::<Direction>(&obs).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let agent = RogueNetAgent::load("rogue-net/test-data/simple").unwrap();
+        let act = |seed| {
+            let mut agent = agent.clone().with_seed(seed);
+            (0..20)
+                .map(|_| agent.act::<Direction>(&obs()).unwrap().remove(0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(act(1), act(1));
+        assert_ne!(act(1), act(2));
+    }
+
+    #[test]
+    fn test_temperature() {
+        let agent = RogueNetAgent::load("rogue-net/test-data/simple")
+            .unwrap()
+            .with_seed(0);
+        let probs = ndarray::array![[0.2, 0.0, 0.8]];
+        let mut cold = agent.clone().with_sampling(Sampling::Temperature(0.05));
+        let mut hot = agent.with_sampling(Sampling::Temperature(100.0));
+        let mut hot_counts = [0; 3];
+        for _ in 0..1000 {
+            assert_eq!(cold.sample(&probs), [2]);
+            hot_counts[hot.sample(&probs)[0] as usize] += 1;
+        }
+        assert_eq!(hot_counts[1], 0);
+        assert!(hot_counts[0] > 400, "{:?}", hot_counts);
+    }
+
+    #[test]
+    fn test_act_info() {
+        let mut agent = RogueNetAgent::load("rogue-net/test-data/simple")
+            .unwrap()
+            .with_sampling(Sampling::Greedy);
+        let obs = obs().action_actors::<Direction, Food>();
+        let info = agent.act_info::<Direction>(&obs).unwrap();
+        let probs = agent.action_probs::<Direction>(&obs).remove(0);
+        assert_eq!(info.len(), 2);
+        for (info, expected) in info.iter().zip(probs.outer_iter()) {
+            let info_probs = info.probs.as_ref().unwrap();
+            assert_eq!(info_probs.len(), Direction::labels().len());
+            assert_eq!(info_probs, &expected.to_vec());
+            assert_eq!(info.action.to_u64(), argmax(expected.iter().copied()));
+        }
+        assert!(info[0].value.is_some());
+        assert_eq!(info[0].value, info[1].value);
+
+        let mut batched = crate::agent::BatchedRogueNetAgent::new(agent);
+        let batched_info = batched.act_info::<Direction>(&obs).unwrap();
+        assert_eq!(batched_info[0].action, info[0].action);
+        assert_eq!(batched_info[0].value, info[0].value);
+    }
+
+    #[derive(Clone)]
+    struct CountingMemory {
+        count: usize,
+        log: std::sync::Arc<std::sync::Mutex<Vec<usize>>>,
+    }
+
+    impl Memory for CountingMemory {
+        fn observe(&mut self, _: &Obs, features: &mut HashMap<String, Array2<f32>>) {
+            self.count += 1;
+            self.log.lock().unwrap().push(self.count);
+            features.insert("Food".to_string(), ndarray::array![[3.0, 5.0], [8.0, 4.0]]);
+        }
+
+        fn reset(&mut self) {
+            self.count = 0;
+        }
+
+        fn box_clone(&self) -> Box<dyn Memory> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_memory() {
+        let log = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let agent = RogueNetAgent::load("rogue-net/test-data/simple").unwrap();
+        let mut a = agent.clone().with_memory(CountingMemory {
+            count: 0,
+            log: log.clone(),
+        });
+        // The memory supplies the `Food` entities that are missing from the observation.
+        let obs_without_food = Obs::new(0.0).actors([Head { x: 3.0, y: 4.0 }]).entities([
+            SnakeSegment { x: 3.0, y: 4.0 },
+            SnakeSegment { x: 4.0, y: 4.0 },
+        ]);
+        let probs = a.action_probs::<Direction>(&obs_without_food).remove(0);
+        let expected = agent.action_probs::<Direction>(&obs()).remove(0);
+        assert!(probs.abs_diff_eq(&expected, 1e-6));
+
+        a.act::<Direction>(&obs_without_food).unwrap();
+        a.act::<Direction>(&obs_without_food).unwrap();
+        let mut b = a.clone();
+        b.act::<Direction>(&obs_without_food).unwrap();
+        a.game_over(&obs_without_food);
+        a.act::<Direction>(&obs_without_food).unwrap();
+        b.act::<Direction>(&obs_without_food).unwrap();
+        // `action_probs` observes a copy of the memory that is discarded afterwards.
+        assert_eq!(*log.lock().unwrap(), [1, 1, 2, 3, 1, 4]);
+    }
+
+    mod adapted {
+        use crate::agent::Featurizable;
+
+        #[derive(Featurizable)]
+        pub struct Head {
+            pub alive: f32,
+            pub y: f32,
+            pub x: f32,
+        }
+    }
+
+    #[test]
+    fn test_save() {
+        let obs = Obs::new(0.0)
+            .actors([adapted::Head {
+                alive: 1.0,
+                y: 4.0,
+                x: 3.0,
+            }])
+            .entities([
+                SnakeSegment { x: 3.0, y: 4.0 },
+                SnakeSegment { x: 4.0, y: 4.0 },
+            ])
+            .entities([Food { x: 3.0, y: 5.0 }, Food { x: 8.0, y: 4.0 }]);
+        let mut normalizer = ObsNormalizer::default();
+        normalizer.update(&obs);
+        normalizer.update(&Obs::new(0.0).entities([Food { x: 0.0, y: 1.0 }]));
+        let agent = RogueNetAgent::load("rogue-net/test-data/simple")
+            .unwrap()
+            .with_feature_adaptor::<adapted::Head>()
+            .with_obs_normalizer(normalizer);
+        let (expected, _) = agent.net.forward(agent.fwd_args(&obs));
+
+        let dir = std::env::temp_dir().join(format!("entity-gym-save-{}", std::process::id()));
+        let archive = dir.with_extension("roguenet");
+        agent.save(&dir).unwrap();
+        agent.save(&archive).unwrap();
+        for path in [&dir, &archive] {
+            let saved = RogueNetAgent::load(path).unwrap();
+            assert!(saved.normalizer.is_some());
+            let (probs, _) = saved.net.forward(saved.fwd_args(&obs));
+            assert!(
+                probs.abs_diff_eq(&expected, 1e-6),
+                "{:?} != {:?}",
+                probs,
+                expected
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&archive).unwrap();
+    }
 }
@@ -0,0 +1,131 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use super::Featurizable;
+
+/// Defines an action which allows each actor to select one of the entities in the observation, e.g. a unit choosing which enemy to attack.
+///
+/// # Example
+/// ```rust
+/// use entity_gym_rs::agent::{Featurizable, SelectEntity};
+///
+/// #[derive(Featurizable)]
+/// struct Enemy { x: i32, y: i32, health: u32 }
+///
+/// struct Attack;
+///
+/// impl SelectEntity for Attack {
+///     type Target = Enemy;
+///
+///     fn name() -> &'static str {
+///         "Attack"
+///     }
+/// }
+/// ```
+pub trait SelectEntity {
+    /// The type of entity that can be selected.
+    type Target: Featurizable;
+    /// Returns the human readable name of the action.
+    fn name() -> &'static str;
+}
+
+/// Reference to an entity of type `E` that was selected by a [`SelectEntity`] action.
+pub struct EntityRef<E> {
+    index: usize,
+    phantom: PhantomData<fn() -> E>,
+}
+
+impl<E> EntityRef<E> {
+    /// Creates a reference to the entity at the given index.
+    pub fn new(index: usize) -> Self {
+        EntityRef {
+            index,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the position of the selected entity within the iterator of `E` entities that was passed to the observation.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<E> Clone for EntityRef<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for EntityRef<E> {}
+
+impl<E> PartialEq for EntityRef<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<E> Eq for EntityRef<E> {}
+
+impl<E> Hash for EntityRef<E> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<E: Featurizable> fmt::Debug for EntityRef<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EntityRef<{}>({})", E::name(), self.index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{AgentOps, Obs, RandomAgent};
+
+    #[derive(Featurizable)]
+    struct Unit {
+        x: i32,
+    }
+
+    #[derive(Featurizable)]
+    struct Enemy {
+        health: u32,
+    }
+
+    struct Attack;
+
+    impl SelectEntity for Attack {
+        type Target = Enemy;
+
+        fn name() -> &'static str {
+            "Attack"
+        }
+    }
+
+    #[test]
+    fn test_random_select_entity() {
+        let obs = Obs::new(0.0)
+            .actors([Unit { x: 0 }, Unit { x: 1 }])
+            .entities((0..3).map(|health| Enemy { health }));
+        let mut agent = RandomAgent::from_seed(0);
+        let targets = agent.select_entity::<Attack>(&obs).unwrap();
+        assert_eq!(targets.len(), 2);
+        assert!(targets.iter().all(|t| t.index() < 3));
+        let targets = agent.select_entity_async::<Attack>(&obs).rcv().unwrap();
+        assert_eq!(targets.len(), 2);
+        assert!(targets.iter().all(|t| t.index() < 3));
+    }
+
+    #[test]
+    fn test_selected_entity_id() {
+        let obs = Obs::new(0.0)
+            .actors([Unit { x: 0 }])
+            .entities_with_ids([(42, Enemy { health: 1 })]);
+        let targets = RandomAgent::from_seed(0)
+            .select_entity::<Attack>(&obs)
+            .unwrap();
+        assert_eq!(obs.entity_id(targets[0]), Some(42));
+    }
+}
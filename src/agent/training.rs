@@ -1,37 +1,45 @@
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
+#[cfg(feature = "python")]
+use crate::low_level::py_vec_env::PyVecEnv;
+use crate::low_level::VecEnv;
 use crate::low_level::{
     Action, ActionMask, ActionSpace, ActionType, CompactFeatures, Entity, Environment, ObsSpace,
     Observation,
 };
-use crate::python::py_vec_env::PyVecEnv;
-use crate::python::VecEnv;
 use arrayvec::ArrayVec;
 use crossbeam::channel::{bounded, Receiver, Sender};
+use ndarray::Array2;
+use rustc_hash::FxHashMap;
 
-use super::{ActionReceiver, Agent, Featurizable, InnerActionReceiver, Obs};
+use super::{
+    ActionReceiver, ActionRequest, Agent, DemonstrationDataset, Featurizable, FrameStackConfig,
+    InnerActionReceiver, Memory, Obs, ObsNormalizer, ObsValidation, SelectEntity,
+};
+use crate::agent::obs::{intern, EntityFeatures};
+use crate::agent::validation::Validator;
 
 /// An [`Environment`] implementation that is paired with one or more [`TrainAgent`].
 ///
 /// To create a [`TrainAgent`], use [`TrainEnvBuilder`].
-#[cfg_attr(docsrs, doc(cfg(feature = "python")))]
 pub struct TrainAgentEnv {
     obs_space: ObsSpace,
     action_space: Vec<(String, ActionSpace)>,
-    action: Vec<Sender<Vec<u64>>>,
+    action: Vec<Sender<Vec<Vec<u64>>>>,
     observation: Vec<Receiver<Observation>>,
 }
 
 /// Used during training to interface with an external agent implementation.
 ///
-/// Train agents are created when constructing a Python training environment with [`TrainEnvBuilder`].
-#[cfg_attr(docsrs, doc(cfg(feature = "python")))]
+/// Train agents are created when constructing a training environment with [`TrainEnvBuilder`].
 pub struct TrainAgent {
-    action: Receiver<Vec<u64>>,
+    action: Receiver<Vec<Vec<u64>>>,
     observation: Sender<Observation>,
     entity_names: Vec<String>,
+    action_names: Vec<String>,
     score: Option<f32>,
 
     // The `obs_remaining` counters are shared between all agents that connect to the same
@@ -45,76 +53,59 @@ pub struct TrainAgent {
     iremaining: usize,
     observation_sent: bool,
     agent_count: usize,
+    normalizer: Option<Arc<Mutex<ObsNormalizer>>>,
+    validator: Validator,
+    memory: Option<Box<dyn Memory>>,
 }
 
-/// Used to export an application defines its own run loop and contains one or more [`Agent`]s to Python as a [`PyVecEnv`].
+/// Used to export an application defines its own run loop and contains one or more [`Agent`]s as a vectorized training environment.
 ///
-/// # Example
+/// [`TrainEnvBuilder::build_vec_env`] returns a [`VecEnv`] that can be stepped directly from Rust, e.g. in benchmarks, tests or Rust training loops.
+/// With the `python` feature enabled, [`TrainEnvBuilder::build`] returns a `PyVecEnv` that can be exported to Python.
 ///
-/// The following code uses Pyo# to export a Python module with a `create_env` function that can be used from Python to create a
-/// [enn-trainer](https://github.com/entity-neural-network/enn-trainer)-compatible training environment.
-/// See [examples/bevy_snake](https://github.com/entity-neural-network/entity-gym-rs/tree/main/examples/bevy_snake) for a full example
-/// of how to run training.
+/// # Example
 ///
 /// ```rust
-/// use entity_gym_rs::agent::{TrainEnvBuilder, TrainAgent, Featurizable, Action};
-/// use entity_gym_rs::low_level::py_vec_env::PyVecEnv;
-/// use pyo3::prelude::*;
-///
-/// #[derive(Featurizable)]
-/// struct Head;
+/// use entity_gym_rs::agent::{Action, AgentOps, Featurizable, Obs, TrainAgent, TrainEnvBuilder};
+/// use ragged_buffer::ragged_buffer::RaggedBuffer;
 ///
 /// #[derive(Featurizable)]
-/// struct Food;
+/// struct Head { x: i32 }
 ///
 /// #[derive(Action)]
-/// enum Move { Up, Down, Left, Right }
-///
-/// #[derive(Clone)]
-/// #[pyclass]
-/// pub struct Config;
+/// enum Move { Left, Right }
 ///
-/// #[pymethods]
-/// impl Config {
-///     #[new]
-///     fn new() -> Self {
-///         Config
+/// fn run_headless(_config: (), mut agent: TrainAgent, _seed: u64) {
+///     let mut x = 0;
+///     while let Some(moves) = agent.act::<Move>(&Obs::new(x as f32).actors([Head { x }])) {
+///         match moves[0] {
+///             Move::Left => x -= 1,
+///             Move::Right => x += 1,
+///         }
 ///     }
 /// }
 ///
-/// fn run_headless(config: Config, agent: TrainAgent, seed: u64) {
-///    // Run the environment.
-///    todo!()
-/// }
-///
-/// #[pyfunction]
-/// fn create_env(config: Config, num_envs: usize, threads: usize, first_env_index: u64) -> PyVecEnv {
-///     TrainEnvBuilder::default()
-///         // Declar all entity and action types that will be used
-///         .entity::<Head>()
-///         .entity::<Food>()
-///         .action::<Move>()
-///         .build(
-///             config,
-///             run_headless,
-///             num_envs,
-///             threads,
-///             first_env_index,
-///         )
-/// }
-///
-/// #[pymodule]
-/// fn bevy_snake_ai(_py: Python, m: &PyModule) -> PyResult<()> {
-///     m.add_function(wrap_pyfunction!(create_env, m)?)?;
-///     m.add_class::<Config>()?;
-///     Ok(())
-/// }
-///```
+/// let mut env = TrainEnvBuilder::default()
+///     .entity::<Head>()
+///     .action::<Move>()
+///     .build_vec_env((), run_headless, 4, 2, 0);
+/// let obs = env.reset();
+/// assert_eq!(obs.len(), 4);
+/// let obs = env.act(vec![Some(RaggedBuffer {
+///     data: vec![1, 1, 0, 1],
+///     subarrays: (0..4).map(|i| i..i + 1).collect(),
+///     features: 1,
+///     items: 4,
+/// })]);
+/// assert_eq!(obs[0].reward, 1.0);
+/// ```
 #[derive(Debug, Clone, Default)]
-#[cfg_attr(docsrs, doc(cfg(feature = "python")))]
 pub struct TrainEnvBuilder {
     entities: Vec<(String, Entity)>,
     actions: Vec<(String, ActionSpace)>,
+    normalizer: Option<Arc<Mutex<ObsNormalizer>>>,
+    validation: ObsValidation,
+    memory: Option<Box<dyn Memory>>,
 }
 
 impl Environment for TrainAgentEnv {
@@ -140,18 +131,23 @@ impl Environment for TrainAgentEnv {
     fn act(&mut self, action: &[Vec<Option<Action>>]) -> Vec<Box<Observation>> {
         assert!(action.len() == self.action.len());
         for (sender, action) in self.action.iter().zip(action.iter()) {
-            assert!(action.len() == 1);
-            match &action[0] {
-                Some(Action::Categorical { actors: _, action }) => {
-                    sender
-                        .send(action.iter().map(|a| *a as u64).collect())
-                        .unwrap();
-                }
-                Some(_) => panic!("unexpected action"),
-                None => {
-                    panic!("No action provided");
-                }
-            }
+            assert!(action.len() == self.action_space.len());
+            assert!(
+                action.iter().any(|action| action.is_some()),
+                "No action provided"
+            );
+            // Actions that were not requested are sent as empty vectors.
+            let action = action
+                .iter()
+                .map(|action| match action {
+                    Some(Action::Categorical { actors: _, action }) => {
+                        action.iter().map(|a| *a as u64).collect()
+                    }
+                    Some(Action::SelectEntity { actors: _, actees }) => actees.clone(),
+                    None => vec![],
+                })
+                .collect();
+            sender.send(action).unwrap();
         }
         self.observation
             .iter()
@@ -161,8 +157,38 @@ impl Environment for TrainAgentEnv {
 }
 
 impl Agent for TrainAgent {
-    fn act_dyn(&mut self, action: &str, _num_actions: u64, obs: &Obs) -> Option<Vec<u64>> {
-        self.send_obs_raw(action, obs);
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        let requested = self.send_obs_raw(actions, obs);
+        self.recv_action(&requested)
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        let requested = self.send_obs_raw(actions, obs);
+        self.action_receiver(requested)
+    }
+
+    fn game_over(&mut self, obs: &Obs) {
+        let obs = Observation {
+            features: CompactFeatures {
+                counts: vec![0; self.entity_names.len()],
+                data: vec![],
+            },
+            ids: vec![None; self.entity_names.len()],
+            actions: vec![None; self.action_names.len()],
+            done: true,
+            reward: obs.score - self.score.unwrap_or(0.0),
+            metrics: obs.metrics.clone(),
+        };
+        self.score = None;
+        if let Some(memory) = &mut self.memory {
+            memory.reset();
+        }
+        let _ = self.observation.send(obs);
+    }
+}
+
+impl TrainAgent {
+    fn recv_action(&mut self, requested: &[(usize, u64)]) -> Option<Vec<Vec<u64>>> {
         let remaining = self.obs_remaining[self.iremaining].load(Ordering::SeqCst);
         if remaining != 0 && (remaining != self.agent_count || self.agent_count == 1) {
             panic!("TrainAgent::act called before all agents have received observations. This is not allowed. If you have multiple agents, call the `act_async` on every agent before awaiting any actions.");
@@ -172,14 +198,18 @@ impl Agent for TrainAgent {
                 self.obs_remaining[self.iremaining].store(self.agent_count, Ordering::SeqCst);
                 self.iremaining = 1 - self.iremaining;
                 self.observation_sent = false;
-                Some(action)
+                Some(
+                    requested
+                        .iter()
+                        .map(|&(index, offset)| action[index].iter().map(|a| a - offset).collect())
+                        .collect(),
+                )
             }
             Err(_) => None,
         }
     }
 
-    fn act_async_dyn(&mut self, action: &str, _num_actions: u64, obs: &Obs) -> ActionReceiver<u64> {
-        self.send_obs_raw(action, obs);
+    fn action_receiver(&mut self, requested: Vec<(usize, u64)>) -> ActionReceiver<u64> {
         self.observation_sent = false;
         self.iremaining = 1 - self.iremaining;
         ActionReceiver {
@@ -187,75 +217,189 @@ impl Agent for TrainAgent {
                 receiver: self.action.clone(),
                 observations_remaining: self.obs_remaining[1 - self.iremaining].clone(),
                 agent_count: self.agent_count,
+                requested,
                 phantom: Default::default(),
             },
         }
     }
 
-    fn game_over(&mut self, obs: &Obs) {
-        let obs = Observation {
-            features: CompactFeatures {
-                counts: vec![0; self.entity_names.len()],
-                data: vec![],
-            },
-            ids: vec![None],
-            actions: vec![None],
-            done: true,
-            reward: obs.score - self.score.unwrap_or(0.0),
-            metrics: obs.metrics.clone(),
+    /// Sends the observation to the training process.
+    ///
+    /// Returns the index of each requested action in the action space, and the position of the first
+    /// `target` entity for select entity actions.
+    fn send_obs_raw(&mut self, requests: &[ActionRequest], obs: &Obs) -> Vec<(usize, u64)> {
+        assert!(
+            !self.observation_sent,
+            "Observation already sent, await the next action before sending a new observation."
+        );
+        self.obs_remaining[self.iremaining].fetch_sub(1, Ordering::SeqCst);
+        self.validator.check(obs, requests);
+
+        let normalizer = self.normalizer.as_ref().map(|normalizer| {
+            let mut normalizer = normalizer.lock().unwrap();
+            normalizer.update(obs);
+            normalizer
+        });
+        let (mut observation, requested) = match &mut self.memory {
+            Some(memory) => {
+                let obs = remember(memory.as_mut(), obs, normalizer.as_deref());
+                observation(&self.entity_names, &self.action_names, requests, &obs, None)
+            }
+            None => observation(
+                &self.entity_names,
+                &self.action_names,
+                requests,
+                obs,
+                normalizer.as_deref(),
+            ),
         };
-        self.score = None;
-        let _ = self.observation.send(obs);
+        let last_score = self.score.replace(obs.score).unwrap_or(obs.score);
+        observation.reward = obs.score - last_score;
+        let _ = self.observation.send(observation);
+        requested
     }
 }
 
-impl TrainAgent {
-    fn send_obs_raw(&mut self, _action: &str, obs: &Obs) {
-        assert!(
-            self.observation_sent == false,
-            "Observation already sent, await the next action before sending a new observation."
+/// Returns a copy of `obs` with normalized features and the entities that `memory` adds, like [`RogueNetAgent`](super::RogueNetAgent) passes them to the network.
+pub(crate) fn remember(
+    memory: &mut dyn Memory,
+    obs: &Obs,
+    normalizer: Option<&ObsNormalizer>,
+) -> Obs {
+    let mut features: HashMap<String, Array2<f32>> = obs
+        .entities
+        .iter()
+        .map(|(name, e)| {
+            let mut features = e.features.clone();
+            if let Some(normalizer) = normalizer {
+                normalizer.normalize(name, &mut features);
+            }
+            (
+                name.to_string(),
+                Array2::from_shape_vec((e.num_entities, e.num_features), features).unwrap(),
+            )
+        })
+        .collect();
+    memory.observe(obs, &mut features);
+
+    let mut remembered = obs.clone();
+    for (name, features) in features {
+        let name = intern(&name);
+        let (num_entities, num_features) = features.dim();
+        let (is_actor, ids, feature_names) = match obs.entities.get(name) {
+            Some(e) if e.num_entities == num_entities => {
+                (e.is_actor, e.ids.clone(), e.feature_names.clone())
+            }
+            Some(e) => (e.is_actor, None, e.feature_names.clone()),
+            None => (false, None, None),
+        };
+        remembered.entities.insert(
+            name,
+            EntityFeatures {
+                features: features.iter().copied().collect(),
+                num_entities,
+                num_features,
+                is_actor,
+                ids,
+                feature_names,
+            },
         );
-        self.obs_remaining[self.iremaining].fetch_sub(1, Ordering::SeqCst);
+    }
+    remembered
+}
 
-        let mut data = vec![];
-        let mut counts = vec![];
-        for name in &self.entity_names {
-            match obs.entities.get(name.as_str()) {
-                Some(f) => {
-                    data.extend(f.features.iter());
-                    counts.push(f.num_entities);
-                }
-                None => {
-                    counts.push(0);
+/// Converts `obs` into the layout of an observation space with the given entity and action types.
+///
+/// Returns the observation with a reward of zero, and the index of each requested action in the action space
+/// together with the position of the first `target` entity for select entity actions.
+pub(crate) fn observation(
+    entity_names: &[String],
+    action_names: &[String],
+    requests: &[ActionRequest],
+    obs: &Obs,
+    normalizer: Option<&ObsNormalizer>,
+) -> (Observation, Vec<(usize, u64)>) {
+    let mut data = vec![];
+    let mut counts = vec![];
+    let mut ids = vec![];
+    for name in entity_names {
+        match obs.entities.get(name.as_str()) {
+            Some(f) => {
+                let start = data.len();
+                data.extend(f.features.iter());
+                if let Some(normalizer) = normalizer {
+                    normalizer.normalize(name, &mut data[start..]);
                 }
+                counts.push(f.num_entities);
+                ids.push(f.ids.clone());
+            }
+            None => {
+                counts.push(0);
+                ids.push(None);
             }
         }
+    }
 
+    // Position of the first entity of each type in the flattened features.
+    let mut offsets = FxHashMap::default();
+    let mut n = 0;
+    for (name, count) in entity_names.iter().zip(counts.iter()) {
+        offsets.insert(name.as_str(), n);
+        n += *count as u64;
+    }
+    let mut actions = vec![None; action_names.len()];
+    let mut requested = Vec::with_capacity(requests.len());
+    for request in requests {
+        let action = request.name();
+        let index = action_names
+            .iter()
+            .position(|n| n == action)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Action \"{}\" was not registered with the TrainEnvBuilder",
+                    action
+                )
+            });
+        // Actors are listed in the order of the observation rather than the order of the obs space.
         let mut actors = vec![];
-        let mut n = 0;
-        for entity in &self.entity_names {
-            if let Some(entity) = obs.entities.get(entity.as_str()) {
-                if entity.is_actor {
-                    for i in 0..entity.num_entities as u64 {
-                        actors.push(n + i);
-                    }
-                }
-                n += entity.num_entities as u64;
+        for (name, entity) in obs.actor_types(action) {
+            if let Some(offset) = offsets.get(name) {
+                actors.extend(*offset..*offset + entity.num_entities as u64);
             }
         }
-
-        // TODO: make noise when obs contains entity that is not in obs space
-        let last_score = self.score.replace(obs.score).unwrap_or(obs.score);
-        let observation = Observation {
-            features: CompactFeatures { counts, data },
-            ids: vec![None],
-            actions: vec![Some(ActionMask::DenseCategorical { actors, mask: None })],
-            done: obs.done,
-            reward: obs.score - last_score,
-            metrics: obs.metrics.clone(),
-        };
-        let _ = self.observation.send(observation);
+        let mut offset = 0;
+        actions[index] = Some(match *request {
+            ActionRequest::Categorical { num_actions, .. } => ActionMask::DenseCategorical {
+                actors,
+                mask: obs.mask(action, num_actions).cloned(),
+            },
+            ActionRequest::SelectEntity { target, .. } => {
+                offset = *offsets.get(target).unwrap_or_else(|| {
+                    panic!(
+                        "Entity \"{}\" was not registered with the TrainEnvBuilder",
+                        target
+                    )
+                });
+                let num_targets = obs.entities.get(target).map_or(0, |e| e.num_entities);
+                ActionMask::SelectEntity {
+                    actors,
+                    actees: (offset..offset + num_targets as u64).collect(),
+                }
+            }
+        });
+        requested.push((index, offset));
     }
+
+    // Entities that are not in the obs space are dropped, `Validator` reports them.
+    let observation = Observation {
+        features: CompactFeatures { counts, data },
+        ids,
+        actions,
+        done: obs.done,
+        reward: 0.0,
+        metrics: obs.metrics.clone(),
+    };
+    (observation, requested)
 }
 
 impl TrainEnvBuilder {
@@ -291,7 +435,109 @@ impl TrainEnvBuilder {
         self
     }
 
-    /// Spawns multiple environment instances and returns a new [`PyVecEnv`] which is connected to them.
+    /// Registers the type of a [`SelectEntity`] action.
+    pub fn select_entity<A: SelectEntity>(mut self) -> Self {
+        assert!(
+            self.actions.iter().all(|(n, _)| n != A::name()),
+            "Already have an action with name \"{}\"",
+            A::name(),
+        );
+        self.actions
+            .push((A::name().to_string(), ActionSpace::SelectEntity));
+        self
+    }
+
+    /// Registers the history entity types that are added to observations by a [`FrameStack`](super::FrameStack) with the given config.
+    pub fn frame_stack(mut self, config: &FrameStackConfig) -> Self {
+        for (name, features) in config.history_entities() {
+            assert!(
+                self.entities.iter().all(|(n, _)| n != name),
+                "Already have an entity with name \"{}\"",
+                name,
+            );
+            self.entities.push((
+                name.to_string(),
+                Entity {
+                    features: features.to_vec(),
+                },
+            ));
+        }
+        self
+    }
+
+    /// Applies a copy of `memory` to the observations of every [`TrainAgent`] and registers the entity types that it adds.
+    ///
+    /// Like in a [`RogueNetAgent`](super::RogueNetAgent), the memory observes the normalized features and is cleared when [`Agent::game_over`] is called.
+    /// At inference time, give the trained agent the same memory with [`RogueNetAgent::with_memory`](super::RogueNetAgent::with_memory).
+    /// Memories such as [`LastSeen`](super::LastSeen) identify entities by their ids, so the remembered entity types must be added with ids.
+    pub fn memory<M: Memory>(mut self, memory: M) -> Self {
+        for (name, features) in memory.entities() {
+            assert!(
+                self.entities.iter().all(|(n, _)| *n != name),
+                "Already have an entity with name \"{}\"",
+                name,
+            );
+            self.entities.push((name, Entity { features }));
+        }
+        self.memory = Some(Box::new(memory));
+        self
+    }
+
+    /// Creates an empty [`DemonstrationDataset`] with the observation and action space of the environment.
+    ///
+    /// If observations are normalized with [`TrainEnvBuilder::normalize_obs`], the features of the dataset are
+    /// normalized with the current statistics of the normalizer, which are not updated.
+    /// If a [`Memory`] was added with [`TrainEnvBuilder::memory`], it observes every recorded step and adds its entities
+    /// to the samples, like it does during training.
+    pub fn demonstrations(&self) -> DemonstrationDataset {
+        DemonstrationDataset::new(
+            ObsSpace {
+                entities: self.entities.clone(),
+            },
+            self.actions.clone(),
+            self.normalizer.clone(),
+            self.memory.clone(),
+        )
+    }
+
+    /// Normalizes the features of all observations with the given [`ObsNormalizer`].
+    ///
+    /// The normalizer is shared by all environment instances and updated with every observation.
+    /// Save it to the checkpoint directory with [`ObsNormalizer::save`] to apply the same normalization at inference time.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    /// use entity_gym_rs::agent::{ObsNormalizer, TrainEnvBuilder};
+    ///
+    /// let normalizer = Arc::new(Mutex::new(ObsNormalizer::default()));
+    /// let builder = TrainEnvBuilder::default().normalize_obs(normalizer.clone());
+    /// // After training:
+    /// // normalizer.lock().unwrap().save(checkpoint_dir.join(ObsNormalizer::FILE_NAME))?;
+    /// ```
+    pub fn normalize_obs(mut self, normalizer: Arc<Mutex<ObsNormalizer>>) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
+    /// Sets how [`TrainAgent`]s report invalid observations.
+    ///
+    /// Observations are checked for entity types that were not registered, entities with the wrong number of features,
+    /// NaN or infinite features, and requested actions without any actors.
+    /// By default, observations are not validated.
+    ///
+    /// # Example
+    /// ```rust
+    /// use entity_gym_rs::agent::{ObsValidation, TrainEnvBuilder};
+    ///
+    /// let builder = TrainEnvBuilder::default().validate_obs(ObsValidation::Panic);
+    /// ```
+    pub fn validate_obs(mut self, validation: ObsValidation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Spawns multiple environment instances and returns a new [`VecEnv`] which is connected to them.
     ///
     /// # Arguments
     /// * `config` - A configuration object which will be forwarded to every `runner`.
@@ -299,14 +545,14 @@ impl TrainEnvBuilder {
     /// * `num_envs` - The number of parallel environment instances to spawn.
     /// * `threads` - The number of threads to use for interfacing with the individual environment instances.
     /// * `first_env_index` - Offset for environment seeding.
-    pub fn build<Config, Runner>(
+    pub fn build_vec_env<Config, Runner>(
         self,
         config: Config,
         runner: Runner,
         num_envs: usize,
         threads: usize,
         first_env_index: u64,
-    ) -> PyVecEnv
+    ) -> VecEnv
     where
         Config: Clone + Send + Sync + 'static,
         Runner: Fn(Config, TrainAgent, u64) + Send + Sync + 'static,
@@ -316,15 +562,20 @@ impl TrainEnvBuilder {
             let (action_tx, action_rx) = bounded(1);
             let (observation_tx, observation_rx) = bounded(1);
             let entity_names = self.entities.iter().map(|(n, _)| n.to_string()).collect();
+            let action_names = self.actions.iter().map(|(n, _)| n.to_string()).collect();
             let agent = TrainAgent {
                 action: action_rx,
                 observation: observation_tx,
                 entity_names,
+                action_names,
                 score: None,
                 obs_remaining: [Arc::new(AtomicUsize::new(1)), Arc::new(AtomicUsize::new(1))],
                 iremaining: 0,
                 observation_sent: false,
                 agent_count: 1,
+                normalizer: self.normalizer.clone(),
+                validator: Validator::new(self.validation, self.entities.clone()),
+                memory: self.memory.clone(),
             };
             let runner = runner.clone();
             let config = config.clone();
@@ -341,20 +592,18 @@ impl TrainEnvBuilder {
             }
         });
 
-        PyVecEnv {
-            env: VecEnv::new(spawn_env, num_envs, threads, first_env_index),
-        }
+        VecEnv::new(spawn_env, num_envs, threads, first_env_index)
     }
 
-    /// Spawns multiple environment instances, each containing multiple agents, and collects them in a [`PyVecEnv`].
-    pub fn build_multiagent<Config, Runner, const N: usize>(
+    /// Spawns multiple environment instances, each containing multiple agents, and collects them in a [`VecEnv`].
+    pub fn build_multiagent_vec_env<Config, Runner, const N: usize>(
         self,
         config: Config,
         runner: Runner,
         num_envs: usize,
         threads: usize,
         first_env_index: u64,
-    ) -> PyVecEnv
+    ) -> VecEnv
     where
         Config: Clone + Send + Sync + 'static,
         Runner: Fn(Config, [TrainAgent; N], u64) + Send + Sync + 'static,
@@ -375,17 +624,22 @@ impl TrainEnvBuilder {
                     let (action_tx, action_rx) = bounded(1);
                     let (observation_tx, observation_rx) = bounded(1);
                     let entity_names = self.entities.iter().map(|(n, _)| n.to_string()).collect();
+                    let action_names = self.actions.iter().map(|(n, _)| n.to_string()).collect();
                     environment.action.push(action_tx);
                     environment.observation.push(observation_rx);
                     TrainAgent {
                         action: action_rx,
                         observation: observation_tx,
                         entity_names,
+                        action_names,
                         score: None,
                         obs_remaining: obs_remaining.clone(),
                         iremaining: 0,
                         observation_sent: false,
                         agent_count: N,
+                        normalizer: self.normalizer.clone(),
+                        validator: Validator::new(self.validation, self.entities.clone()),
+                        memory: self.memory.clone(),
                     }
                 })
                 .collect::<ArrayVec<_, N>>()
@@ -399,8 +653,356 @@ impl TrainEnvBuilder {
             environment
         });
 
+        VecEnv::new(spawn_env, num_envs, threads, first_env_index)
+    }
+
+    /// Spawns multiple environment instances and returns a new [`PyVecEnv`] which is connected to them.
+    ///
+    /// Takes the same arguments as [`TrainEnvBuilder::build_vec_env`].
+    ///
+    /// # Example
+    ///
+    /// The following code uses Pyo# to export a Python module with a `create_env` function that can be used from Python to create a
+    /// [enn-trainer](https://github.com/entity-neural-network/enn-trainer)-compatible training environment.
+    /// See [examples/bevy_snake](https://github.com/entity-neural-network/entity-gym-rs/tree/main/examples/bevy_snake) for a full example
+    /// of how to run training.
+    ///
+    /// ```rust
+    /// use entity_gym_rs::agent::{TrainEnvBuilder, TrainAgent, Featurizable, Action};
+    /// use entity_gym_rs::low_level::py_vec_env::PyVecEnv;
+    /// use pyo3::prelude::*;
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Head;
+    ///
+    /// #[derive(Featurizable)]
+    /// struct Food;
+    ///
+    /// #[derive(Action)]
+    /// enum Move { Up, Down, Left, Right }
+    ///
+    /// #[derive(Clone)]
+    /// #[pyclass]
+    /// pub struct Config;
+    ///
+    /// #[pymethods]
+    /// impl Config {
+    ///     #[new]
+    ///     fn new() -> Self {
+    ///         Config
+    ///     }
+    /// }
+    ///
+    /// fn run_headless(config: Config, agent: TrainAgent, seed: u64) {
+    ///    // Run the environment.
+    ///    todo!()
+    /// }
+    ///
+    /// #[pyfunction]
+    /// fn create_env(config: Config, num_envs: usize, threads: usize, first_env_index: u64) -> PyVecEnv {
+    ///     TrainEnvBuilder::default()
+    ///         // Declar all entity and action types that will be used
+    ///         .entity::<Head>()
+    ///         .entity::<Food>()
+    ///         .action::<Move>()
+    ///         .build(
+    ///             config,
+    ///             run_headless,
+    ///             num_envs,
+    ///             threads,
+    ///             first_env_index,
+    ///         )
+    /// }
+    ///
+    /// #[pymodule]
+    /// fn bevy_snake_ai(_py: Python, m: &PyModule) -> PyResult<()> {
+    ///     m.add_function(wrap_pyfunction!(create_env, m)?)?;
+    ///     m.add_class::<Config>()?;
+    ///     Ok(())
+    /// }
+    ///```
+    #[cfg(feature = "python")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "python")))]
+    pub fn build<Config, Runner>(
+        self,
+        config: Config,
+        runner: Runner,
+        num_envs: usize,
+        threads: usize,
+        first_env_index: u64,
+    ) -> PyVecEnv
+    where
+        Config: Clone + Send + Sync + 'static,
+        Runner: Fn(Config, TrainAgent, u64) + Send + Sync + 'static,
+    {
         PyVecEnv {
-            env: VecEnv::new(spawn_env, num_envs, threads, first_env_index),
+            env: self.build_vec_env(config, runner, num_envs, threads, first_env_index),
+        }
+    }
+
+    /// Spawns multiple environment instances, each containing multiple agents, and collects them in a [`PyVecEnv`].
+    #[cfg(feature = "python")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "python")))]
+    pub fn build_multiagent<Config, Runner, const N: usize>(
+        self,
+        config: Config,
+        runner: Runner,
+        num_envs: usize,
+        threads: usize,
+        first_env_index: u64,
+    ) -> PyVecEnv
+    where
+        Config: Clone + Send + Sync + 'static,
+        Runner: Fn(Config, [TrainAgent; N], u64) + Send + Sync + 'static,
+    {
+        PyVecEnv {
+            env: self.build_multiagent_vec_env(config, runner, num_envs, threads, first_env_index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ragged_buffer::ragged_buffer::RaggedBuffer;
+
+    use super::*;
+    use crate::agent::{Action, AgentOps, EntityRef, Select};
+
+    #[derive(Featurizable)]
+    struct Unit {
+        x: i32,
+    }
+
+    #[derive(Featurizable)]
+    struct Enemy {
+        health: u32,
+    }
+
+    struct Attack;
+
+    impl SelectEntity for Attack {
+        type Target = Enemy;
+
+        fn name() -> &'static str {
+            "Attack"
+        }
+    }
+
+    fn run_select_entity(_: (), mut agent: TrainAgent, _: u64) {
+        let mut selected: Option<EntityRef<Enemy>> = None;
+        loop {
+            let mut obs = Obs::new(0.0)
+                .actors([Unit { x: 0 }])
+                .entities([Enemy { health: 1 }, Enemy { health: 2 }]);
+            if let Some(target) = selected {
+                obs = obs.metric("selected", target.index() as f32);
+            }
+            match agent.select_entity::<Attack>(&obs) {
+                Some(targets) => selected = Some(targets[0]),
+                None => break,
+            }
+        }
+    }
+
+    #[derive(Action)]
+    enum Move {
+        Up,
+        Down,
+    }
+
+    fn run_masked(_: (), mut agent: TrainAgent, _: u64) {
+        loop {
+            let obs = Obs::new(0.0)
+                .entities([Enemy { health: 1 }])
+                .actors([Unit { x: 0 }, Unit { x: 1 }])
+                .action_mask::<Move>([[true, false], [false, true]]);
+            if agent.act::<Move>(&obs).is_none() {
+                break;
+            }
+        }
+    }
+
+    fn run_multiple_actions(_: (), mut agent: TrainAgent, _: u64) {
+        let mut metrics = vec![];
+        loop {
+            let mut obs = Obs::new(0.0)
+                .actors([Unit { x: 0 }])
+                .entities([Enemy { health: 1 }, Enemy { health: 2 }]);
+            for (name, value) in metrics.drain(..) {
+                obs = obs.metric(name, value);
+            }
+            match agent.act::<(Move, Select<Attack>)>(&obs) {
+                Some((moves, targets)) => {
+                    metrics.push(("move", moves[0].to_u64() as f32));
+                    metrics.push(("selected", targets[0].index() as f32));
+                }
+                None => break,
+            }
+        }
+    }
+
+    #[test]
+    fn test_multiple_actions() {
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Unit>()
+            .entity::<Enemy>()
+            .select_entity::<Attack>()
+            .action::<Move>()
+            .build_vec_env((), run_multiple_actions, 1, 1, 0);
+        let obs = env.reset();
+        assert!(matches!(
+            obs[0].actions[0],
+            Some(ActionMask::SelectEntity { .. })
+        ));
+        assert!(matches!(
+            obs[0].actions[1],
+            Some(ActionMask::DenseCategorical { .. })
+        ));
+        let obs = env.act(vec![
+            Some(RaggedBuffer {
+                data: vec![1],
+                subarrays: (0..1).map(|i| i..i + 1).collect(),
+                features: 1,
+                items: 1,
+            }),
+            Some(RaggedBuffer {
+                data: vec![1],
+                subarrays: (0..1).map(|i| i..i + 1).collect(),
+                features: 1,
+                items: 1,
+            }),
+        ]);
+        assert_eq!(obs[0].metrics["move"], 1.0);
+        assert_eq!(obs[0].metrics["selected"], 1.0);
+    }
+
+    fn run_action_actors(_: (), mut agent: TrainAgent, _: u64) {
+        loop {
+            let obs = Obs::new(0.0)
+                .entities([Enemy { health: 1 }, Enemy { health: 2 }])
+                .entities([Unit { x: 0 }])
+                .action_actors::<Move, Unit>()
+                .action_actors::<Select<Attack>, Enemy>();
+            if agent.act::<(Move, Select<Attack>)>(&obs).is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_action_actors() {
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Unit>()
+            .entity::<Enemy>()
+            .action::<Move>()
+            .select_entity::<Attack>()
+            .build_vec_env((), run_action_actors, 1, 1, 0);
+        let obs = env.reset();
+        match &obs[0].actions[0] {
+            Some(ActionMask::DenseCategorical { actors, .. }) => assert_eq!(actors, &[0]),
+            mask => panic!("unexpected action mask {:?}", mask),
+        }
+        match &obs[0].actions[1] {
+            Some(ActionMask::SelectEntity { actors, actees }) => {
+                assert_eq!(actors, &[1, 2]);
+                assert_eq!(actees, &[1, 2]);
+            }
+            mask => panic!("unexpected action mask {:?}", mask),
+        }
+    }
+
+    fn run_entity_ids(_: (), mut agent: TrainAgent, _: u64) {
+        loop {
+            let obs = Obs::new(0.0)
+                .actors_with_ids([(12, Unit { x: 0 }), (5, Unit { x: 1 })])
+                .entities([Enemy { health: 1 }]);
+            match agent.act_by_id::<Move>(&obs) {
+                Some(moves) => assert_eq!(moves.len(), 2),
+                None => break,
+            }
+        }
+    }
+
+    #[test]
+    fn test_entity_ids() {
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Unit>()
+            .entity::<Enemy>()
+            .action::<Move>()
+            .build_vec_env((), run_entity_ids, 1, 1, 0);
+        let obs = env.reset();
+        assert_eq!(obs[0].ids, vec![Some(vec![12, 5]), None]);
+        let obs = env.act(vec![Some(RaggedBuffer {
+            data: vec![0, 1],
+            subarrays: (0..1).map(|i| 2 * i..2 * i + 2).collect(),
+            features: 1,
+            items: 2,
+        })]);
+        assert_eq!(obs[0].ids, vec![Some(vec![12, 5]), None]);
+    }
+
+    fn run_normalized(_: (), mut agent: TrainAgent, _: u64) {
+        loop {
+            let obs = Obs::new(0.0).actors([Unit { x: 10 }, Unit { x: 30 }]);
+            if agent.act::<Move>(&obs).is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_normalize_obs() {
+        let normalizer = Arc::new(Mutex::new(ObsNormalizer::default()));
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Unit>()
+            .action::<Move>()
+            .normalize_obs(normalizer.clone())
+            .build_vec_env((), run_normalized, 1, 1, 0);
+        let obs = env.reset();
+        let normalizer = normalizer.lock().unwrap();
+        assert_eq!(normalizer.mean("Unit"), Some(vec![20.0]));
+        let std = normalizer.std("Unit").unwrap()[0];
+        assert_eq!(obs[0].features.data, vec![-10.0 / std, 10.0 / std]);
+    }
+
+    #[test]
+    fn test_action_mask() {
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Unit>()
+            .entity::<Enemy>()
+            .action::<Move>()
+            .build_vec_env((), run_masked, 1, 1, 0);
+        let obs = env.reset();
+        match &obs[0].actions[0] {
+            Some(ActionMask::DenseCategorical { actors, mask }) => {
+                assert_eq!(actors, &[0, 1]);
+                assert_eq!(mask, &Some(vec![true, false, false, true]));
+            }
+            mask => panic!("unexpected action mask {:?}", mask),
+        }
+    }
+
+    #[test]
+    fn test_select_entity() {
+        let mut env = TrainEnvBuilder::default()
+            .entity::<Unit>()
+            .entity::<Enemy>()
+            .select_entity::<Attack>()
+            .build_vec_env((), run_select_entity, 1, 1, 0);
+        env.reset();
+        let obs = env.act(vec![Some(RaggedBuffer {
+            data: vec![1],
+            subarrays: (0..1).map(|i| i..i + 1).collect(),
+            features: 1,
+            items: 1,
+        })]);
+        match &obs[0].actions[0] {
+            Some(ActionMask::SelectEntity { actors, actees }) => {
+                assert_eq!(actors, &[0]);
+                assert_eq!(actees, &[1, 2]);
+            }
+            mask => panic!("unexpected action mask {:?}", mask),
         }
+        assert_eq!(obs[0].metrics["selected"], 1.0);
     }
 }
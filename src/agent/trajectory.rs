@@ -0,0 +1,1015 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use indexmap::IndexMap;
+use rustc_hash::FxHashMap;
+
+use super::obs::{intern, EntityFeatures};
+use super::{
+    ActionInfo, ActionReceiver, ActionRequest, Agent, Featurizable, InnerActionReceiver, Obs,
+};
+
+const MAGIC: &[u8; 8] = b"EGYMTRAJ";
+const VERSION: u32 = 1;
+
+// Record tags.
+const ENTITY: u8 = 1;
+const STEP: u8 = 2;
+const GAME_OVER: u8 = 3;
+
+// Action request tags.
+const CATEGORICAL: u8 = 0;
+const SELECT_ENTITY: u8 = 1;
+
+/// Maximum number of elements that are allocated up front for a length read from a trajectory file.
+/// Longer collections grow as their elements are read, so corrupt lengths fail at the end of the data.
+const MAX_PREALLOCATED: usize = 4096;
+
+/// Writes observations and actions to a compact binary trajectory file that can be read with [`TrajectoryReader`].
+///
+/// A trajectory file starts with a short header followed by a sequence of records.
+/// All numbers are stored in little-endian byte order.
+/// There are three kinds of records:
+/// - An entity schema with the name and feature names of an entity type.
+///   It is written once, before the first observation that contains the entity type.
+/// - A step with an observation, the requested actions and the actions chosen by the agent.
+/// - The final observation of an episode.
+///
+/// Observations store the score, the change in score since the previous observation of the episode,
+/// the features and ids of all entities, metrics, action masks and action actors.
+///
+/// Feature names are taken from the observation in which an entity type first appears, or from
+/// [`Featurizable::feature_names`] for entity types that are registered with [`TrajectoryWriter::entity`].
+/// Features of entity types without names, such as those added by a [`Memory`](super::Memory), are named by their index.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{Action, ActionRequest, Featurizable, Obs, TrajectoryWriter};
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let path = std::env::temp_dir().join(format!("writer-doctest-{}.traj", std::process::id()));
+/// let mut writer = TrajectoryWriter::create(&path).unwrap().entity::<Head>();
+/// let obs = Obs::new(0.0).actors([Head { x: 1.0, y: 2.0 }]);
+/// let request = ActionRequest::Categorical { name: "Move", num_actions: 4 };
+/// writer.write_step(&obs, &[request], Some(&[vec![3]])).unwrap();
+/// writer.write_game_over(&Obs::new(1.0)).unwrap();
+/// writer.flush().unwrap();
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct TrajectoryWriter<W: Write> {
+    writer: W,
+    // Feature names of entity types registered with `entity`.
+    feature_names: FxHashMap<&'static str, Vec<String>>,
+    // Index of every entity type whose schema has already been written.
+    schemas: FxHashMap<&'static str, u32>,
+    last_score: Option<f32>,
+    // Records that are held back until the actions of the oldest step are known, oldest first.
+    pending: VecDeque<PendingRecord>,
+    // Ticket of the first pending record.
+    first_pending: u64,
+}
+
+enum PendingRecord {
+    // A step that is still waiting for its actions.
+    Step(Vec<u8>),
+    Ready(Vec<u8>),
+}
+
+impl TrajectoryWriter<BufWriter<File>> {
+    /// Creates a new trajectory file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        TrajectoryWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> TrajectoryWriter<W> {
+    /// Creates a writer that writes a trajectory to `writer`.
+    pub fn new(mut writer: W) -> Result<Self, io::Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(TrajectoryWriter {
+            writer,
+            feature_names: FxHashMap::default(),
+            schemas: FxHashMap::default(),
+            last_score: None,
+            pending: VecDeque::new(),
+            first_pending: 0,
+        })
+    }
+
+    /// Registers the feature names of entities of type `E`, which take precedence over the feature names of observations.
+    pub fn entity<E: Featurizable>(mut self) -> Self {
+        self.feature_names.insert(E::name(), E::feature_names());
+        self
+    }
+
+    /// Writes an observation, the requested actions and the actions that were chosen for them.
+    ///
+    /// `actions` is `None` if the agent did not return any actions.
+    pub fn write_step(
+        &mut self,
+        obs: &Obs,
+        requests: &[ActionRequest],
+        actions: Option<&[Vec<u64>]>,
+    ) -> Result<(), io::Error> {
+        let mut buf = self.encode_step(obs, requests)?;
+        put_actions(&mut buf, actions);
+        self.write_record(buf)
+    }
+
+    /// Writes the final observation of an episode.
+    pub fn write_game_over(&mut self, obs: &Obs) -> Result<(), io::Error> {
+        let mut buf = vec![GAME_OVER];
+        self.encode_obs(obs, &mut buf)?;
+        self.last_score = None;
+        self.write_record(buf)
+    }
+
+    /// Flushes the underlying writer.
+    ///
+    /// Records that are held back until the actions of an earlier step are known are not written.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.writer.flush()
+    }
+
+    /// Encodes a step whose actions are not known yet and returns a ticket for [`TrajectoryWriter::finish_step`].
+    ///
+    /// The step keeps its position in the trajectory: records that are written afterwards are held back until it is finished.
+    pub(crate) fn begin_step(
+        &mut self,
+        obs: &Obs,
+        requests: &[ActionRequest],
+    ) -> Result<u64, io::Error> {
+        let buf = self.encode_step(obs, requests)?;
+        self.pending.push_back(PendingRecord::Step(buf));
+        Ok(self.first_pending + self.pending.len() as u64 - 1)
+    }
+
+    /// Adds the chosen actions to a step started with [`TrajectoryWriter::begin_step`] and writes all records that are no longer held back.
+    pub(crate) fn finish_step(
+        &mut self,
+        ticket: u64,
+        actions: Option<&[Vec<u64>]>,
+    ) -> Result<(), io::Error> {
+        let record = &mut self.pending[(ticket - self.first_pending) as usize];
+        if let PendingRecord::Step(buf) = record {
+            let mut buf = std::mem::take(buf);
+            put_actions(&mut buf, actions);
+            *record = PendingRecord::Ready(buf);
+        }
+        while let Some(PendingRecord::Ready(_)) = self.pending.front() {
+            if let Some(PendingRecord::Ready(buf)) = self.pending.pop_front() {
+                self.first_pending += 1;
+                self.writer.write_all(&buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a record, or holds it back if there are steps whose actions are not known yet.
+    fn write_record(&mut self, buf: Vec<u8>) -> Result<(), io::Error> {
+        if self.pending.is_empty() {
+            self.writer.write_all(&buf)
+        } else {
+            self.pending.push_back(PendingRecord::Ready(buf));
+            Ok(())
+        }
+    }
+
+    /// Encodes the observation and requests of a step whose actions are not known yet.
+    fn encode_step(&mut self, obs: &Obs, requests: &[ActionRequest]) -> Result<Vec<u8>, io::Error> {
+        let mut buf = vec![STEP];
+        self.encode_obs(obs, &mut buf)?;
+        put_u32(&mut buf, requests.len());
+        for request in requests {
+            match *request {
+                ActionRequest::Categorical { name, num_actions } => {
+                    buf.push(CATEGORICAL);
+                    put_str(&mut buf, name);
+                    buf.extend_from_slice(&num_actions.to_le_bytes());
+                }
+                ActionRequest::SelectEntity { name, target } => {
+                    buf.push(SELECT_ENTITY);
+                    put_str(&mut buf, name);
+                    put_str(&mut buf, target);
+                }
+            }
+        }
+        Ok(buf)
+    }
+
+    fn encode_obs(&mut self, obs: &Obs, buf: &mut Vec<u8>) -> Result<(), io::Error> {
+        let last_score = self.last_score.replace(obs.score).unwrap_or(obs.score);
+        buf.extend_from_slice(&obs.score.to_le_bytes());
+        buf.extend_from_slice(&(obs.score - last_score).to_le_bytes());
+
+        put_u32(buf, obs.entities.len());
+        for (&name, entity) in &obs.entities {
+            let schema = self.schema(name, entity)?;
+            put_u32(buf, schema as usize);
+            buf.push(entity.is_actor as u8 | (entity.ids.is_some() as u8) << 1);
+            put_u32(buf, entity.num_entities);
+            for id in entity.ids.iter().flatten() {
+                buf.extend_from_slice(&id.to_le_bytes());
+            }
+            for feature in &entity.features {
+                buf.extend_from_slice(&feature.to_le_bytes());
+            }
+        }
+
+        put_u32(buf, obs.metrics.len());
+        for (name, value) in &obs.metrics {
+            put_str(buf, name);
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        put_u32(buf, obs.action_masks.len());
+        for (action, mask) in &obs.action_masks {
+            put_str(buf, action);
+            put_u32(buf, mask.len());
+            buf.extend(mask.iter().map(|&m| m as u8));
+        }
+        put_u32(buf, obs.action_actors.len());
+        for (action, actors) in &obs.action_actors {
+            put_str(buf, action);
+            put_u32(buf, actors.len());
+            for actor in actors {
+                put_str(buf, actor);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the schema of an entity type, writing the schema if it hasn't been written yet.
+    fn schema(&mut self, name: &'static str, entity: &EntityFeatures) -> Result<u32, io::Error> {
+        if let Some(&index) = self.schemas.get(name) {
+            return Ok(index);
+        }
+        let num_features = entity.num_features;
+        let feature_names = match self.feature_names.get(name) {
+            Some(feature_names) => {
+                assert_eq!(
+                    feature_names.len(),
+                    num_features,
+                    "Entity \"{}\" has {} features, but {} feature names were registered",
+                    name,
+                    num_features,
+                    feature_names.len(),
+                );
+                feature_names.clone()
+            }
+            None => match &entity.feature_names {
+                Some(feature_names) => feature_names.to_vec(),
+                None => (0..num_features).map(|i| i.to_string()).collect(),
+            },
+        };
+        let mut buf = vec![ENTITY];
+        put_str(&mut buf, name);
+        put_u32(&mut buf, feature_names.len());
+        for feature_name in &feature_names {
+            put_str(&mut buf, feature_name);
+        }
+        self.write_record(buf)?;
+        let index = self.schemas.len() as u32;
+        self.schemas.insert(name, index);
+        Ok(index)
+    }
+}
+
+// Appends the chosen actions of a step.
+fn put_actions(buf: &mut Vec<u8>, actions: Option<&[Vec<u64>]>) {
+    match actions {
+        Some(actions) => {
+            buf.push(1);
+            for actions in actions {
+                put_u32(buf, actions.len());
+                for action in actions {
+                    buf.extend_from_slice(&action.to_le_bytes());
+                }
+            }
+        }
+        None => buf.push(0),
+    }
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: usize) {
+    buf.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn put_str(buf: &mut Vec<u8>, value: &str) {
+    put_u32(buf, value.len());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// Name and feature names of an entity type in a trajectory file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntitySchema {
+    /// Name of the entity type.
+    pub name: String,
+    /// Names of the features of the entity type.
+    pub feature_names: Vec<String>,
+}
+
+/// An action request that was recorded in a trajectory file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedRequest {
+    /// Each actor chooses one of `num_actions` choices of a categorical action.
+    Categorical {
+        /// Name of the action.
+        name: String,
+        /// Number of choices.
+        num_actions: u64,
+    },
+    /// Each actor selects one of the entities of type `target`.
+    SelectEntity {
+        /// Name of the action.
+        name: String,
+        /// Name of the entity type that can be selected.
+        target: String,
+    },
+}
+
+impl RecordedRequest {
+    /// Returns the recorded request as an [`ActionRequest`].
+    pub fn as_request(&self) -> ActionRequest<'_> {
+        match self {
+            RecordedRequest::Categorical { name, num_actions } => ActionRequest::Categorical {
+                name,
+                num_actions: *num_actions,
+            },
+            RecordedRequest::SelectEntity { name, target } => {
+                ActionRequest::SelectEntity { name, target }
+            }
+        }
+    }
+}
+
+impl From<&ActionRequest<'_>> for RecordedRequest {
+    fn from(request: &ActionRequest) -> Self {
+        match *request {
+            ActionRequest::Categorical { name, num_actions } => RecordedRequest::Categorical {
+                name: name.to_string(),
+                num_actions,
+            },
+            ActionRequest::SelectEntity { name, target } => RecordedRequest::SelectEntity {
+                name: name.to_string(),
+                target: target.to_string(),
+            },
+        }
+    }
+}
+
+/// A record of a trajectory file.
+pub enum TrajectoryEvent {
+    /// An observation for which the agent was asked to act.
+    Step {
+        /// The observation.
+        obs: Obs,
+        /// Change in score since the previous observation of the episode.
+        score_delta: f32,
+        /// The requested actions.
+        requests: Vec<RecordedRequest>,
+        /// The actions chosen for each of the requests, or `None` if the agent did not return any actions.
+        actions: Option<Vec<Vec<u64>>>,
+    },
+    /// The final observation of an episode.
+    GameOver {
+        /// The observation.
+        obs: Obs,
+        /// Change in score since the previous observation of the episode.
+        score_delta: f32,
+    },
+}
+
+/// Reads trajectory files written by [`TrajectoryWriter`] or [`TrajectoryRecorder`].
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{ActionRequest, Obs, TrajectoryEvent, TrajectoryReader, TrajectoryWriter};
+///
+/// let path = std::env::temp_dir().join(format!("reader-doctest-{}.traj", std::process::id()));
+/// let mut writer = TrajectoryWriter::create(&path).unwrap();
+/// writer.write_game_over(&Obs::new(1.0)).unwrap();
+/// writer.flush().unwrap();
+///
+/// for event in TrajectoryReader::open(&path).unwrap() {
+///     match event.unwrap() {
+///         TrajectoryEvent::Step { requests, actions, .. } => println!("{:?} {:?}", requests, actions),
+///         TrajectoryEvent::GameOver { obs, .. } => assert_eq!(obs.score(), 1.0),
+///     }
+/// }
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct TrajectoryReader<R: Read> {
+    reader: R,
+    schemas: Vec<EntitySchema>,
+    // Interned name and feature names of each schema, shared by all observations.
+    names: Vec<(&'static str, Arc<[String]>)>,
+}
+
+impl TrajectoryReader<BufReader<File>> {
+    /// Opens the trajectory file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        TrajectoryReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> TrajectoryReader<R> {
+    /// Creates a reader that reads a trajectory from `reader`.
+    pub fn new(mut reader: R) -> Result<Self, io::Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a trajectory file".to_string()));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION as usize {
+            return Err(invalid_data(format!(
+                "Unsupported trajectory file version {}",
+                version
+            )));
+        }
+        Ok(TrajectoryReader {
+            reader,
+            schemas: vec![],
+            names: vec![],
+        })
+    }
+
+    /// Returns the schemas of all entity types that have been read so far.
+    pub fn schemas(&self) -> &[EntitySchema] {
+        &self.schemas
+    }
+
+    /// Reads the next step or game over record, or returns `None` at the end of the file.
+    pub fn read_event(&mut self) -> Result<Option<TrajectoryEvent>, io::Error> {
+        loop {
+            let mut tag = [0];
+            if self.reader.read(&mut tag)? == 0 {
+                return Ok(None);
+            }
+            match tag[0] {
+                ENTITY => {
+                    let name = read_str(&mut self.reader)?;
+                    let num_features = read_u32(&mut self.reader)?;
+                    let feature_names = read_vec(&mut self.reader, num_features, read_str)?;
+                    self.names
+                        .push((intern(&name), feature_names.clone().into()));
+                    self.schemas.push(EntitySchema {
+                        name,
+                        feature_names,
+                    });
+                }
+                STEP => {
+                    let (obs, score_delta) = self.read_obs()?;
+                    let num_requests = read_u32(&mut self.reader)?;
+                    let requests =
+                        read_vec(&mut self.reader, num_requests, |r| match read_u8(r)? {
+                            CATEGORICAL => Ok(RecordedRequest::Categorical {
+                                name: read_str(r)?,
+                                num_actions: read_u64(r)?,
+                            }),
+                            SELECT_ENTITY => Ok(RecordedRequest::SelectEntity {
+                                name: read_str(r)?,
+                                target: read_str(r)?,
+                            }),
+                            tag => Err(invalid_data(format!("Unknown action request tag {}", tag))),
+                        })?;
+                    let actions = match read_u8(&mut self.reader)? {
+                        0 => None,
+                        _ => Some(read_vec(&mut self.reader, num_requests, |r| {
+                            let num_actors = read_u32(r)?;
+                            read_vec(r, num_actors, read_u64)
+                        })?),
+                    };
+                    return Ok(Some(TrajectoryEvent::Step {
+                        obs,
+                        score_delta,
+                        requests,
+                        actions,
+                    }));
+                }
+                GAME_OVER => {
+                    let (obs, score_delta) = self.read_obs()?;
+                    return Ok(Some(TrajectoryEvent::GameOver { obs, score_delta }));
+                }
+                tag => return Err(invalid_data(format!("Unknown record tag {}", tag))),
+            }
+        }
+    }
+
+    fn read_obs(&mut self) -> Result<(Obs, f32), io::Error> {
+        let r = &mut self.reader;
+        let mut obs = Obs::new(read_f32(r)?);
+        let score_delta = read_f32(r)?;
+
+        let num_entity_types = read_u32(r)?;
+        let mut entities = IndexMap::with_capacity(num_entity_types.min(MAX_PREALLOCATED));
+        for _ in 0..num_entity_types {
+            let schema = read_u32(r)?;
+            let (name, feature_names, schema) =
+                match (self.names.get(schema), self.schemas.get(schema)) {
+                    (Some((name, feature_names)), Some(schema)) => (*name, feature_names, schema),
+                    _ => return Err(invalid_data(format!("Unknown entity schema {}", schema))),
+                };
+            let flags = read_u8(r)?;
+            let num_entities = read_u32(r)?;
+            let ids = if flags & 2 != 0 {
+                Some(read_vec(r, num_entities, read_u64)?)
+            } else {
+                None
+            };
+            let num_features = schema.feature_names.len();
+            let num_bytes = num_entities
+                .checked_mul(num_features * 4)
+                .ok_or_else(|| invalid_data(format!("Too many {} entities", name)))?;
+            let features = read_bytes(r, num_bytes)?
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            entities.insert(
+                name,
+                EntityFeatures {
+                    features,
+                    num_entities,
+                    num_features,
+                    is_actor: flags & 1 != 0,
+                    ids,
+                    feature_names: Some(feature_names.clone()),
+                },
+            );
+        }
+        obs.entities = entities;
+
+        for _ in 0..read_u32(r)? {
+            let name = read_str(r)?;
+            let value = read_f32(r)?;
+            obs.metrics.insert(name, value);
+        }
+        for _ in 0..read_u32(r)? {
+            let action = read_str(r)?;
+            let len = read_u32(r)?;
+            let mask = read_bytes(r, len)?;
+            obs.action_masks
+                .insert(action, mask.into_iter().map(|m| m != 0).collect());
+        }
+        for _ in 0..read_u32(r)? {
+            let action = read_str(r)?;
+            let num_actors = read_u32(r)?;
+            let actors = read_vec(r, num_actors, |r| read_str(r).map(|actor| intern(&actor)))?;
+            obs.action_actors.insert(action, actors);
+        }
+        Ok((obs, score_delta))
+    }
+}
+
+impl<R: Read> Iterator for TrajectoryReader<R> {
+    type Item = Result<TrajectoryEvent, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_event().transpose()
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> Result<u8, io::Error> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<usize, io::Error> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf) as usize)
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64, io::Error> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f32<R: Read>(r: &mut R) -> Result<f32, io::Error> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+/// Reads `len` bytes without trusting `len` for the allocation.
+fn read_bytes<R: Read>(r: &mut R, len: usize) -> Result<Vec<u8>, io::Error> {
+    let mut buf = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+    r.by_ref().take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(invalid_data(format!(
+            "Expected {} bytes, but the trajectory ends after {}",
+            len,
+            buf.len()
+        )));
+    }
+    Ok(buf)
+}
+
+/// Reads `len` elements without trusting `len` for the allocation.
+fn read_vec<R: Read, T>(
+    r: &mut R,
+    len: usize,
+    mut read: impl FnMut(&mut R) -> Result<T, io::Error>,
+) -> Result<Vec<T>, io::Error> {
+    let mut items = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+    for _ in 0..len {
+        items.push(read(r)?);
+    }
+    Ok(items)
+}
+
+fn read_str<R: Read>(r: &mut R) -> Result<String, io::Error> {
+    let len = read_u32(r)?;
+    let buf = read_bytes(r, len)?;
+    String::from_utf8(buf).map_err(|e| invalid_data(e.to_string()))
+}
+
+/// [`Agent`] that forwards to another agent and records every observation, action and game over to a trajectory file.
+///
+/// Trajectories can be read with [`TrajectoryReader`], e.g. for debugging, behavioural cloning or regression tests.
+/// Errors that occur while writing do not interrupt the agent. Recording stops at the first error,
+/// which is returned by the next call to [`TrajectoryRecorder::flush`].
+///
+/// Steps are recorded in the order in which actions were requested, even if the [`ActionReceiver`]s returned by
+/// [`Agent::act_multi_async_dyn`] are consumed in a different order. A step and everything that is recorded after it
+/// are held back until its actions are received. If the receiver is dropped instead, the step is recorded without actions.
+///
+/// # Example
+/// ```
+/// use entity_gym_rs::agent::{self, Action, Agent, AgentOps, Featurizable, Obs, TrajectoryRecorder, TrajectoryWriter};
+///
+/// #[derive(Action, Debug)]
+/// enum Move { Up, Down, Left, Right }
+///
+/// #[derive(Featurizable)]
+/// struct Head { x: f32, y: f32 }
+///
+/// let path = std::env::temp_dir().join(format!("recorder-doctest-{}.traj", std::process::id()));
+/// let writer = TrajectoryWriter::create(&path).unwrap().entity::<Head>();
+/// let mut agent = TrajectoryRecorder::new(agent::random(), writer);
+/// for step in 0..10 {
+///     let obs = Obs::new(step as f32).actors([Head { x: step as f32, y: 0.0 }]);
+///     agent.act::<Move>(&obs);
+/// }
+/// agent.game_over(&Obs::new(10.0));
+/// agent.flush().unwrap();
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct TrajectoryRecorder<A, W: Write> {
+    agent: A,
+    recording: Arc<Mutex<Recording<W>>>,
+}
+
+/// [`TrajectoryWriter`] that keeps the first error that occurs while writing instead of returning it.
+pub(crate) struct Recording<W: Write> {
+    writer: TrajectoryWriter<W>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Recording<W> {
+    pub(crate) fn new(writer: TrajectoryWriter<W>) -> Self {
+        Recording {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Runs `write` unless an earlier write has failed, and stores the error if it fails.
+    pub(crate) fn record<T>(
+        &mut self,
+        write: impl FnOnce(&mut TrajectoryWriter<W>) -> Result<T, io::Error>,
+    ) -> Option<T> {
+        if self.error.is_some() {
+            return None;
+        }
+        match write(&mut self.writer) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+
+    /// Flushes the writer and returns the first error that occurred while writing, if any.
+    pub(crate) fn flush(&mut self) -> Result<(), io::Error> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+/// Step of an [`ActionReceiver`] returned by [`TrajectoryRecorder`] whose actions have not been received yet.
+///
+/// If the receiver is dropped without receiving the actions, the step is written without actions.
+struct PendingStep<W: Write> {
+    recording: Arc<Mutex<Recording<W>>>,
+    ticket: Option<u64>,
+}
+
+impl<W: Write> PendingStep<W> {
+    fn finish(mut self, actions: Option<&[Vec<u64>]>) {
+        if let Some(ticket) = self.ticket.take() {
+            self.recording
+                .lock()
+                .unwrap()
+                .record(|w| w.finish_step(ticket, actions));
+        }
+    }
+}
+
+impl<W: Write> Drop for PendingStep<W> {
+    fn drop(&mut self) {
+        if let (Some(ticket), Ok(mut recording)) = (self.ticket.take(), self.recording.lock()) {
+            recording.record(|w| w.finish_step(ticket, None));
+        }
+    }
+}
+
+impl<A: Agent, W: Write> TrajectoryRecorder<A, W> {
+    /// Wraps `agent` so that its trajectories are written to `writer`.
+    pub fn new(agent: A, writer: TrajectoryWriter<W>) -> Self {
+        TrajectoryRecorder {
+            agent,
+            recording: Arc::new(Mutex::new(Recording::new(writer))),
+        }
+    }
+
+    /// Returns a reference to the inner agent.
+    pub fn inner(&self) -> &A {
+        &self.agent
+    }
+
+    /// Returns a mutable reference to the inner agent.
+    pub fn inner_mut(&mut self) -> &mut A {
+        &mut self.agent
+    }
+
+    /// Flushes the trajectory file and returns the first error that occurred while recording, if any.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.recording.lock().unwrap().flush()
+    }
+}
+
+impl<A: Agent, W: Write + Send + 'static> Agent for TrajectoryRecorder<A, W> {
+    fn act_multi_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> Option<Vec<Vec<u64>>> {
+        let chosen = self.agent.act_multi_dyn(actions, obs);
+        self.recording
+            .lock()
+            .unwrap()
+            .record(|w| w.write_step(obs, actions, chosen.as_deref()));
+        chosen
+    }
+
+    fn act_multi_async_dyn(&mut self, actions: &[ActionRequest], obs: &Obs) -> ActionReceiver<u64> {
+        let receiver = self.agent.act_multi_async_dyn(actions, obs);
+        let ticket = self
+            .recording
+            .lock()
+            .unwrap()
+            .record(|w| w.begin_step(obs, actions));
+        let step = PendingStep {
+            recording: self.recording.clone(),
+            ticket,
+        };
+        ActionReceiver {
+            inner: InnerActionReceiver::Recorded {
+                receiver: Box::new(receiver),
+                record: Box::new(move |chosen| step.finish(chosen.map(|c| &c[..]))),
+            },
+        }
+    }
+
+    fn act_multi_info_dyn(
+        &mut self,
+        actions: &[ActionRequest],
+        obs: &Obs,
+    ) -> Option<Vec<Vec<ActionInfo<u64>>>> {
+        let infos = self.agent.act_multi_info_dyn(actions, obs);
+        let chosen = infos.as_ref().map(|infos| {
+            infos
+                .iter()
+                .map(|infos| infos.iter().map(|info| info.action).collect())
+                .collect::<Vec<_>>()
+        });
+        self.recording
+            .lock()
+            .unwrap()
+            .record(|w| w.write_step(obs, actions, chosen.as_deref()));
+        infos
+    }
+
+    fn game_over(&mut self, obs: &Obs) {
+        self.recording
+            .lock()
+            .unwrap()
+            .record(|w| w.write_game_over(obs));
+        self.agent.game_over(obs);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Action, AgentOps, Featurizable, RandomAgent, Select, SelectEntity};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        x: f32,
+    }
+
+    #[derive(Action, Debug, Clone, Copy, PartialEq, Eq)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    struct Eat;
+
+    impl SelectEntity for Eat {
+        type Target = Food;
+
+        fn name() -> &'static str {
+            "Eat"
+        }
+    }
+
+    fn obs(step: u64) -> Obs {
+        Obs::new(step as f32 * 0.5)
+            .actors_with_ids([(
+                7,
+                Head {
+                    x: step as f32,
+                    y: 1.0,
+                },
+            )])
+            .entities([Food { x: 2.0 }, Food { x: 3.0 }])
+            .metric("step", step as f32)
+            .action_mask::<Move>([[true, step % 2 == 0]])
+    }
+
+    #[test]
+    fn test_record() {
+        let path = std::env::temp_dir().join(format!("test-record-{}.traj", std::process::id()));
+        let writer = TrajectoryWriter::create(&path).unwrap().entity::<Head>();
+        let mut agent = TrajectoryRecorder::new(RandomAgent::from_seed(0), writer);
+        let mut chosen = vec![];
+        for step in 0..3 {
+            let (moves, targets) = agent.act::<(Move, Select<Eat>)>(&obs(step)).unwrap();
+            chosen.push(vec![
+                vec![moves[0].to_u64()],
+                vec![targets[0].index() as u64],
+            ]);
+        }
+        let receiver = agent.act_async::<Move>(&obs(3));
+        chosen.push(vec![vec![receiver.rcv().unwrap()[0].to_u64()]]);
+        agent.game_over(&Obs::new(4.0));
+        agent.flush().unwrap();
+
+        let mut reader = TrajectoryReader::open(&path).unwrap();
+        for (step, chosen) in chosen.into_iter().enumerate() {
+            match reader.read_event().unwrap().unwrap() {
+                TrajectoryEvent::Step {
+                    obs,
+                    score_delta,
+                    requests,
+                    actions,
+                } => {
+                    assert_eq!(obs.score, step as f32 * 0.5);
+                    assert_eq!(score_delta, if step == 0 { 0.0 } else { 0.5 });
+                    assert_eq!(obs.entities["Head"].features, [step as f32, 1.0]);
+                    assert_eq!(obs.entities["Head"].ids, Some(vec![7]));
+                    assert!(obs.entities["Head"].is_actor);
+                    assert_eq!(obs.entities["Food"].features, [2.0, 3.0]);
+                    assert_eq!(obs.metrics["step"], step as f32);
+                    assert_eq!(obs.action_masks["Move"], [true, step % 2 == 0]);
+                    assert_eq!(
+                        requests[0],
+                        RecordedRequest::Categorical {
+                            name: "Move".to_string(),
+                            num_actions: 2
+                        }
+                    );
+                    assert_eq!(actions, Some(chosen));
+                }
+                TrajectoryEvent::GameOver { .. } => panic!("unexpected game over"),
+            }
+        }
+        match reader.read_event().unwrap().unwrap() {
+            TrajectoryEvent::GameOver { obs, score_delta } => {
+                assert_eq!(obs.score, 4.0);
+                assert_eq!(score_delta, 2.5);
+            }
+            TrajectoryEvent::Step { .. } => panic!("expected game over"),
+        }
+        assert!(reader.read_event().unwrap().is_none());
+        assert_eq!(
+            reader.schemas(),
+            [
+                EntitySchema {
+                    name: "Head".to_string(),
+                    feature_names: vec!["x".to_string(), "y".to_string()],
+                },
+                // Feature names of unregistered entity types are taken from the observation.
+                EntitySchema {
+                    name: "Food".to_string(),
+                    feature_names: vec!["x".to_string()],
+                },
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_record_async_order() {
+        let path =
+            std::env::temp_dir().join(format!("test-record-async-{}.traj", std::process::id()));
+        let writer = TrajectoryWriter::create(&path).unwrap();
+        let mut agent = TrajectoryRecorder::new(RandomAgent::from_seed(0), writer);
+        let first = agent.act_async::<Move>(&obs(0));
+        let second = agent.act_async::<Move>(&obs(1));
+        let second = second.rcv().unwrap()[0].to_u64();
+        drop(agent.act_async::<Move>(&obs(2)));
+        agent.game_over(&Obs::new(4.0));
+        agent.flush().unwrap();
+        // Everything is held back until the actions of the first step are received.
+        assert_eq!(TrajectoryReader::open(&path).unwrap().count(), 0);
+
+        let first = first.rcv().unwrap()[0].to_u64();
+        agent.flush().unwrap();
+        let events = TrajectoryReader::open(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(events.len(), 4);
+        let expected = [Some(vec![vec![first]]), Some(vec![vec![second]]), None];
+        for (step, (event, expected)) in events.iter().zip(expected).enumerate() {
+            match event {
+                TrajectoryEvent::Step { obs, actions, .. } => {
+                    assert_eq!(obs.score, step as f32 * 0.5);
+                    assert_eq!(*actions, expected);
+                }
+                TrajectoryEvent::GameOver { .. } => panic!("unexpected game over"),
+            }
+        }
+        assert!(
+            matches!(events[3], TrajectoryEvent::GameOver { score_delta, .. } if score_delta == 3.0)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_lengths() {
+        let header = || {
+            let mut data = MAGIC.to_vec();
+            data.extend_from_slice(&VERSION.to_le_bytes());
+            data
+        };
+        // Entity schema with a huge name.
+        let mut name = header();
+        name.push(ENTITY);
+        name.extend_from_slice(&u32::MAX.to_le_bytes());
+        name.extend_from_slice(b"Head");
+        // Observation with a huge number of entities.
+        let mut entities = header();
+        entities.push(ENTITY);
+        entities.extend_from_slice(&4u32.to_le_bytes());
+        entities.extend_from_slice(b"Head");
+        entities.extend_from_slice(&1u32.to_le_bytes());
+        entities.extend_from_slice(&1u32.to_le_bytes());
+        entities.extend_from_slice(b"x");
+        entities.push(STEP);
+        entities.extend_from_slice(&[0; 8]);
+        entities.extend_from_slice(&1u32.to_le_bytes());
+        entities.extend_from_slice(&0u32.to_le_bytes());
+        entities.push(1);
+        entities.extend_from_slice(&u32::MAX.to_le_bytes());
+        entities.extend_from_slice(&[0; 16]);
+
+        for data in [name, entities] {
+            match TrajectoryReader::new(&data[..]).unwrap().read_event() {
+                Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+                Ok(_) => panic!("corrupt trajectory was read"),
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::error::Error;
+use std::fmt;
+use std::mem::{self, Discriminant};
+
+use rustc_hash::FxHashSet;
+
+use crate::low_level::Entity;
+
+use super::{ActionRequest, Obs};
+
+/// A problem with an observation that is detected by [`Obs::validate`] or by a [`TrainAgent`](super::TrainAgent)
+/// with observation validation enabled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObsError {
+    /// The observation contains entities of a type that was not registered with the [`TrainEnvBuilder`](super::TrainEnvBuilder).
+    /// These entities are not visible to the agent.
+    UnregisteredEntity {
+        /// Name of the entity type.
+        entity: String,
+    },
+    /// Entities have a different number of features than the entity type registered with the [`TrainEnvBuilder`](super::TrainEnvBuilder).
+    FeatureCountMismatch {
+        /// Name of the entity type.
+        entity: String,
+        /// Number of features of the registered entity type.
+        expected: usize,
+        /// Number of features of the entities in the observation.
+        actual: usize,
+    },
+    /// A feature is NaN or infinite.
+    NonFiniteFeature {
+        /// Name of the entity type.
+        entity: String,
+        /// Position of the entity among the entities of its type.
+        index: usize,
+        /// Index of the feature.
+        feature: usize,
+        /// Value of the feature.
+        value: f32,
+    },
+    /// An action was requested, but the observation contains no actors for it.
+    NoActors {
+        /// Name of the action.
+        action: String,
+    },
+}
+
+impl fmt::Display for ObsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObsError::UnregisteredEntity { entity } => write!(
+                f,
+                "entity \"{}\" was not registered with the TrainEnvBuilder",
+                entity
+            ),
+            ObsError::FeatureCountMismatch {
+                entity,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "entity \"{}\" has {} features but was registered with {}",
+                entity, actual, expected
+            ),
+            ObsError::NonFiniteFeature {
+                entity,
+                index,
+                feature,
+                value,
+            } => write!(
+                f,
+                "feature {} of entity {} of type \"{}\" is {}",
+                feature, index, entity, value
+            ),
+            ObsError::NoActors { action } => {
+                write!(f, "action \"{}\" was requested without any actors", action)
+            }
+        }
+    }
+}
+
+impl Error for ObsError {}
+
+/// Determines how a [`TrainAgent`](super::TrainAgent) reports invalid observations, see [`TrainEnvBuilder::validate_obs`](super::TrainEnvBuilder::validate_obs).
+///
+/// Defaults to [`ObsValidation::Off`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObsValidation {
+    /// Observations are not validated.
+    #[default]
+    Off,
+    /// Problems are logged as warnings with the [`log`] crate.
+    /// Each kind of problem is only logged once for every entity type or action.
+    Log,
+    /// Panics on the first invalid observation.
+    Panic,
+}
+
+/// Validates observations against the entity types of an observation space.
+#[derive(Debug, Clone)]
+pub(crate) struct Validator {
+    mode: ObsValidation,
+    entities: Vec<(String, Entity)>,
+    // Kinds of problems that have already been logged.
+    logged: FxHashSet<(Discriminant<ObsError>, String)>,
+}
+
+impl Validator {
+    pub(crate) fn new(mode: ObsValidation, entities: Vec<(String, Entity)>) -> Self {
+        Validator {
+            mode,
+            entities,
+            logged: FxHashSet::default(),
+        }
+    }
+
+    /// Validates `obs` and reports any problems according to the validation mode.
+    pub(crate) fn check(&mut self, obs: &Obs, requests: &[ActionRequest]) {
+        if self.mode == ObsValidation::Off {
+            return;
+        }
+        let errors = validate(obs, requests, Some(&self.entities));
+        if errors.is_empty() {
+            return;
+        }
+        match self.mode {
+            ObsValidation::Off => {}
+            ObsValidation::Log => {
+                for error in errors {
+                    if self.logged.insert(kind(&error)) {
+                        log::warn!(
+                            "Invalid observation: {} (further problems of this kind are not logged)",
+                            error
+                        );
+                    }
+                }
+            }
+            ObsValidation::Panic => panic!(
+                "Invalid observation: {}",
+                errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        }
+    }
+}
+
+/// Returns the kind of `error` and the entity type or action it refers to.
+fn kind(error: &ObsError) -> (Discriminant<ObsError>, String) {
+    let name = match error {
+        ObsError::UnregisteredEntity { entity }
+        | ObsError::FeatureCountMismatch { entity, .. }
+        | ObsError::NonFiniteFeature { entity, .. } => entity,
+        ObsError::NoActors { action } => action,
+    };
+    (mem::discriminant(error), name.clone())
+}
+
+/// Returns all problems with `obs`. Registered entity types are only checked if `entities` is given.
+pub(crate) fn validate(
+    obs: &Obs,
+    requests: &[ActionRequest],
+    entities: Option<&[(String, Entity)]>,
+) -> Vec<ObsError> {
+    let mut errors = vec![];
+    for (&name, e) in &obs.entities {
+        if let Some(entities) = entities {
+            match entities.iter().find(|(n, _)| n == name) {
+                Some((_, entity)) => {
+                    if e.num_entities > 0 && e.num_features != entity.features.len() {
+                        errors.push(ObsError::FeatureCountMismatch {
+                            entity: name.to_string(),
+                            expected: entity.features.len(),
+                            actual: e.num_features,
+                        });
+                    }
+                }
+                None => {
+                    errors.push(ObsError::UnregisteredEntity {
+                        entity: name.to_string(),
+                    });
+                    continue;
+                }
+            }
+        }
+        // Only the first non-finite feature of each entity type is reported.
+        if let Some(i) = e.features.iter().position(|f| !f.is_finite()) {
+            errors.push(ObsError::NonFiniteFeature {
+                entity: name.to_string(),
+                index: i / e.num_features,
+                feature: i % e.num_features,
+                value: e.features[i],
+            });
+        }
+    }
+    for request in requests {
+        if obs.num_action_actors(request.name()) == 0 {
+            errors.push(ObsError::NoActors {
+                action: request.name().to_string(),
+            });
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Action, ActionType, Featurizable};
+
+    #[derive(Featurizable)]
+    struct Head {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Featurizable)]
+    struct Food {
+        x: f32,
+    }
+
+    #[derive(Action)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    #[test]
+    fn test_validate() {
+        let obs = Obs::new(0.0)
+            .actors([
+                Head { x: 0.0, y: 1.0 },
+                Head {
+                    x: 2.0,
+                    y: f32::NAN,
+                },
+            ])
+            .entities([Food { x: f32::INFINITY }]);
+        let errors = obs.validate(&[Move::request()]);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            &errors[0],
+            ObsError::NonFiniteFeature { entity, index: 1, feature: 1, value } if entity == "Head" && value.is_nan()
+        ));
+        assert_eq!(
+            errors[1],
+            ObsError::NonFiniteFeature {
+                entity: "Food".to_string(),
+                index: 0,
+                feature: 0,
+                value: f32::INFINITY,
+            }
+        );
+
+        let obs = Obs::new(0.0)
+            .entities([Head { x: 0.0, y: 0.0 }])
+            .entities([Food { x: 0.0 }]);
+        let entities = vec![(
+            "Head".to_string(),
+            Entity {
+                features: vec!["x".to_string()],
+            },
+        )];
+        assert_eq!(
+            validate(&obs, &[Move::request()], Some(&entities)),
+            [
+                ObsError::FeatureCountMismatch {
+                    entity: "Head".to_string(),
+                    expected: 1,
+                    actual: 2,
+                },
+                ObsError::UnregisteredEntity {
+                    entity: "Food".to_string(),
+                },
+                ObsError::NoActors {
+                    action: "Move".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            ObsError::NoActors {
+                action: "Move".to_string()
+            }
+            .to_string(),
+            "action \"Move\" was requested without any actors"
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid observation: entity \"Food\" was not registered with the TrainEnvBuilder"
+    )]
+    fn test_validator_panic() {
+        let mut validator = Validator::new(ObsValidation::Panic, vec![]);
+        validator.check(&Obs::new(0.0).entities([Food { x: 0.0 }]), &[]);
+    }
+
+    #[test]
+    fn test_validator_log() {
+        let entities = vec![(
+            "Food".to_string(),
+            Entity {
+                features: vec!["x".to_string()],
+            },
+        )];
+        let mut validator = Validator::new(ObsValidation::Log, entities);
+        for x in [f32::NAN, f32::INFINITY] {
+            let obs = Obs::new(0.0)
+                .entities([Food { x }, Food { x }])
+                .entities([Head { x: 0.0, y: 0.0 }]);
+            validator.check(&obs, &[Move::request()]);
+        }
+        // Every kind of problem is logged once.
+        assert_eq!(validator.logged.len(), 3);
+    }
+}
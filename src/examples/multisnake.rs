@@ -39,7 +39,6 @@ struct Snake {
 }
 
 impl MultiSnake {
-    #[cfg(feature = "python")]
     pub fn new(
         board_size: usize,
         num_snakes: usize,
@@ -265,3 +264,27 @@ impl Environment for MultiSnake {
         1
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::low_level::VecEnv;
+    use crate::ppo::{PpoConfig, PpoTrainer};
+
+    #[test]
+    fn test_train() {
+        let env = VecEnv::new(Arc::new(|seed| MultiSnake::new(6, 2, 5, 20, seed)), 4, 1, 0);
+        let mut trainer = PpoTrainer::new(
+            env,
+            PpoConfig {
+                d_model: 8,
+                steps: 8,
+                ..Default::default()
+            },
+        );
+        let stats = trainer.update();
+        assert!(stats.value_loss.is_finite());
+    }
+}
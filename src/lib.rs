@@ -4,11 +4,18 @@
 //! [EntityGym](https://github.com/entity-neural-network/entity-gym) is a Python library that defines a novel entity-based abstraction for reinforcement learning environments which enables highly ergonomic and efficient training of deep reinforcement learning agents.
 //! This crate provides bindings that allows Rust programs to implement the entity-gym API and run neural network agents trained with [enn-trainer](https://github.com/entity-neural-network/enn-trainer).
 
+// Allows code generated by derive macros to refer to `::entity_gym_rs` from within this crate.
+extern crate self as entity_gym_rs;
+
 /// High level API for interacting with neural network agents.
 pub mod agent;
+// Example environments are only exported by the Python module, other builds use them in tests.
+#[cfg_attr(not(feature = "python"), allow(dead_code))]
 mod examples;
 /// Low-level and highly API that mirrors the entity-gym Python API. Not intended for direct use.
 pub mod low_level;
+/// Built-in trainer that runs proximal policy optimization on a [`VecEnv`](low_level::VecEnv) without Python.
+pub mod ppo;
 
 #[cfg(feature = "python")]
 mod python {
@@ -150,10 +150,7 @@ impl PyVecEnv {
                         // TODO: could be more efficient, omit mask if None in all obs
 
                         match mask {
-                            Some(mask) => push(
-                                &mut ragged_mask,
-                                &mask.iter().copied().collect::<Vec<_>>()[..],
-                            ),
+                            Some(mask) => push(&mut ragged_mask, mask),
                             None => {
                                 let feats = ragged_mask.features;
                                 push(&mut ragged_mask, &vec![true; actors.len() * feats]);
@@ -0,0 +1,296 @@
+use ndarray::{s, Array1, Array2, ArrayView2, Axis};
+use rand::rngs::SmallRng;
+use rand::Rng;
+use statrs::function::erf::erf;
+
+/// A trainable parameter with its gradient and Adam moment estimates.
+#[derive(Debug, Clone)]
+pub(crate) struct Param {
+    pub value: Array2<f32>,
+    pub grad: Array2<f32>,
+    m: Array2<f32>,
+    v: Array2<f32>,
+}
+
+impl Param {
+    pub fn new(value: Array2<f32>) -> Self {
+        let zeros = Array2::zeros(value.dim());
+        Param {
+            grad: zeros.clone(),
+            m: zeros.clone(),
+            v: zeros,
+            value,
+        }
+    }
+
+    pub fn zero_grad(&mut self) {
+        self.grad.fill(0.0);
+    }
+
+    /// Applies one step of the Adam optimizer, where `t` is the 1-based step count.
+    pub fn adam_step(&mut self, lr: f32, t: i32) {
+        const BETA1: f32 = 0.9;
+        const BETA2: f32 = 0.999;
+        const EPS: f32 = 1e-8;
+        let bias1 = 1.0 - BETA1.powi(t);
+        let bias2 = 1.0 - BETA2.powi(t);
+        ndarray::Zip::from(&mut self.value)
+            .and(&self.grad)
+            .and(&mut self.m)
+            .and(&mut self.v)
+            .for_each(|x, &g, m, v| {
+                *m = BETA1 * *m + (1.0 - BETA1) * g;
+                *v = BETA2 * *v + (1.0 - BETA2) * g * g;
+                *x -= lr * (*m / bias1) / ((*v / bias2).sqrt() + EPS);
+            });
+    }
+}
+
+/// Fully connected layer with weights of shape `(in, out)`.
+#[derive(Debug, Clone)]
+pub(crate) struct Linear {
+    pub weight: Param,
+    pub bias: Param,
+}
+
+impl Linear {
+    /// Initializes weights and biases uniformly in `[-gain / sqrt(in), gain / sqrt(in)]`.
+    pub fn new(d_in: usize, d_out: usize, gain: f32, rng: &mut SmallRng) -> Self {
+        let bound = gain / (d_in.max(1) as f32).sqrt();
+        let mut init = |shape| Array2::from_shape_fn(shape, |_| rng.gen_range(-bound..=bound));
+        Linear {
+            weight: Param::new(init((d_in, d_out))),
+            bias: Param::new(init((1, d_out))),
+        }
+    }
+
+    pub fn forward(&self, x: ArrayView2<f32>) -> Array2<f32> {
+        x.dot(&self.weight.value) + &self.bias.value
+    }
+
+    /// Accumulates the parameter gradients and returns the gradient with respect to the input `x`.
+    pub fn backward(&mut self, x: ArrayView2<f32>, dy: ArrayView2<f32>) -> Array2<f32> {
+        self.weight.grad += &x.t().dot(&dy);
+        self.bias.grad += &dy.sum_axis(Axis(0)).insert_axis(Axis(0));
+        dy.dot(&self.weight.value.t())
+    }
+
+    pub fn params_mut(&mut self) -> [&mut Param; 2] {
+        [&mut self.weight, &mut self.bias]
+    }
+
+    /// Returns the weight and bias in PyTorch layout.
+    pub fn tensors(&self) -> [(Vec<usize>, Vec<f32>); 2] {
+        let (d_in, d_out) = self.weight.value.dim();
+        [
+            (
+                vec![d_out, d_in],
+                self.weight.value.t().iter().copied().collect(),
+            ),
+            (vec![d_out], self.bias.value.iter().copied().collect()),
+        ]
+    }
+}
+
+/// Layer normalization over the last axis, computed in the same way as rogue-net.
+#[derive(Debug, Clone)]
+pub(crate) struct LayerNorm {
+    pub weight: Param,
+    pub bias: Param,
+}
+
+pub(crate) struct LayerNormCache {
+    xhat: Array2<f32>,
+    centered: Array2<f32>,
+    std: Array1<f32>,
+}
+
+const LN_EPS: f32 = 1e-5;
+
+impl LayerNorm {
+    pub fn new(d: usize) -> Self {
+        LayerNorm {
+            weight: Param::new(Array2::ones((1, d))),
+            bias: Param::new(Array2::zeros((1, d))),
+        }
+    }
+
+    pub fn forward(&self, x: ArrayView2<f32>) -> (Array2<f32>, LayerNormCache) {
+        let mean = x.mean_axis(Axis(1)).unwrap().insert_axis(Axis(1));
+        let centered = &x - &mean;
+        let std = centered.std_axis(Axis(1), 0.0);
+        let xhat = &centered / &(&std + LN_EPS).insert_axis(Axis(1));
+        let y = &xhat * &self.weight.value + &self.bias.value;
+        (
+            y,
+            LayerNormCache {
+                xhat,
+                centered,
+                std,
+            },
+        )
+    }
+
+    pub fn backward(&mut self, cache: &LayerNormCache, dy: ArrayView2<f32>) -> Array2<f32> {
+        self.weight.grad += &(&dy * &cache.xhat).sum_axis(Axis(0)).insert_axis(Axis(0));
+        self.bias.grad += &dy.sum_axis(Axis(0)).insert_axis(Axis(0));
+        let dxhat = &dy * &self.weight.value;
+        let n = dxhat.dim().1 as f32;
+        let mut dx = Array2::zeros(dxhat.dim());
+        for (i, mut row) in dx.outer_iter_mut().enumerate() {
+            let std = cache.std[i];
+            let s = std + LN_EPS;
+            let dxhat = dxhat.row(i);
+            let centered = cache.centered.row(i);
+            let mean_dxhat = dxhat.mean().unwrap_or(0.0);
+            let dstd = if std > 0.0 {
+                -dxhat.dot(&centered) / (s * s) / (n * std)
+            } else {
+                0.0
+            };
+            for j in 0..row.len() {
+                row[j] = (dxhat[j] - mean_dxhat) / s + dstd * centered[j];
+            }
+        }
+        dx
+    }
+
+    pub fn params_mut(&mut self) -> [&mut Param; 2] {
+        [&mut self.weight, &mut self.bias]
+    }
+
+    pub fn tensors(&self) -> [(Vec<usize>, Vec<f32>); 2] {
+        let d = self.weight.value.len();
+        [
+            (vec![d], self.weight.value.iter().copied().collect()),
+            (vec![d], self.bias.value.iter().copied().collect()),
+        ]
+    }
+}
+
+/// Multi-head self-attention over all entities.
+#[derive(Debug, Clone)]
+pub(crate) struct Attention {
+    n_head: usize,
+    pub key: Linear,
+    pub query: Linear,
+    pub value: Linear,
+    pub proj: Linear,
+}
+
+pub(crate) struct AttentionCache {
+    x: Array2<f32>,
+    q: Array2<f32>,
+    k: Array2<f32>,
+    v: Array2<f32>,
+    attn: Vec<Array2<f32>>,
+    y: Array2<f32>,
+}
+
+impl Attention {
+    pub fn new(d_model: usize, n_head: usize, rng: &mut SmallRng) -> Self {
+        Attention {
+            n_head,
+            key: Linear::new(d_model, d_model, 1.0, rng),
+            query: Linear::new(d_model, d_model, 1.0, rng),
+            value: Linear::new(d_model, d_model, 1.0, rng),
+            proj: Linear::new(d_model, d_model, 1.0, rng),
+        }
+    }
+
+    pub fn forward(&self, x: ArrayView2<f32>) -> (Array2<f32>, AttentionCache) {
+        let (n, d) = x.dim();
+        let d_head = d / self.n_head;
+        let scale = 1.0 / (d_head as f32).sqrt();
+        let q = self.query.forward(x);
+        let k = self.key.forward(x);
+        let v = self.value.forward(x);
+        let mut y = Array2::zeros((n, d));
+        let mut attn = Vec::with_capacity(self.n_head);
+        for head in 0..self.n_head {
+            let slice = s![.., head * d_head..(head + 1) * d_head];
+            let logits = q.slice(slice).dot(&k.slice(slice).t()) * scale;
+            let a = softmax(&logits);
+            y.slice_mut(slice).assign(&a.dot(&v.slice(slice)));
+            attn.push(a);
+        }
+        let out = self.proj.forward(y.view());
+        (
+            out,
+            AttentionCache {
+                x: x.to_owned(),
+                q,
+                k,
+                v,
+                attn,
+                y,
+            },
+        )
+    }
+
+    pub fn backward(&mut self, cache: &AttentionCache, dout: ArrayView2<f32>) -> Array2<f32> {
+        let (n, d) = cache.x.dim();
+        let d_head = d / self.n_head;
+        let scale = 1.0 / (d_head as f32).sqrt();
+        let dy = self.proj.backward(cache.y.view(), dout);
+        let mut dq = Array2::zeros((n, d));
+        let mut dk = Array2::zeros((n, d));
+        let mut dv = Array2::zeros((n, d));
+        for (head, a) in cache.attn.iter().enumerate() {
+            let slice = s![.., head * d_head..(head + 1) * d_head];
+            let dy = dy.slice(slice);
+            let da = dy.dot(&cache.v.slice(slice).t());
+            dv.slice_mut(slice).assign(&a.t().dot(&dy));
+            let dlogits = softmax_backward(a, &da) * scale;
+            dq.slice_mut(slice)
+                .assign(&dlogits.dot(&cache.k.slice(slice)));
+            dk.slice_mut(slice)
+                .assign(&dlogits.t().dot(&cache.q.slice(slice)));
+        }
+        let x = cache.x.view();
+        self.query.backward(x, dq.view())
+            + self.key.backward(x, dk.view())
+            + self.value.backward(x, dv.view())
+    }
+
+    pub fn params_mut(&mut self) -> impl Iterator<Item = &mut Param> {
+        self.key
+            .params_mut()
+            .into_iter()
+            .chain(self.query.params_mut())
+            .chain(self.value.params_mut())
+            .chain(self.proj.params_mut())
+    }
+}
+
+/// Row-wise softmax. Entries of `-inf` receive a probability of zero.
+pub(crate) fn softmax(logits: &Array2<f32>) -> Array2<f32> {
+    let mut probs = logits.clone();
+    for mut row in probs.outer_iter_mut() {
+        let max = row.fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+        row.mapv_inplace(|x| (x - max).exp());
+        let sum = row.sum();
+        row.mapv_inplace(|x| x / sum);
+    }
+    probs
+}
+
+/// Gradient of the logits of a row-wise softmax given the gradient of its output.
+fn softmax_backward(probs: &Array2<f32>, dprobs: &Array2<f32>) -> Array2<f32> {
+    let dot = (probs * dprobs).sum_axis(Axis(1)).insert_axis(Axis(1));
+    probs * &(dprobs - &dot)
+}
+
+pub(crate) fn gelu(x: ArrayView2<f32>) -> Array2<f32> {
+    x.mapv(|x| 0.5 * x * (1.0 + erf((x / std::f32::consts::SQRT_2) as f64) as f32))
+}
+
+pub(crate) fn gelu_backward(x: ArrayView2<f32>, dy: ArrayView2<f32>) -> Array2<f32> {
+    let mut dx = x.mapv(|x| {
+        let cdf = 0.5 * (1.0 + erf((x / std::f32::consts::SQRT_2) as f64) as f32);
+        let pdf = (-0.5 * x * x).exp() / (2.0 * std::f32::consts::PI).sqrt();
+        cdf + x * pdf
+    });
+    dx *= &dy;
+    dx
+}
@@ -0,0 +1,633 @@
+mod layers;
+mod policy;
+
+use std::path::Path;
+
+use indexmap::IndexMap;
+use ndarray::Array2;
+use ragged_buffer::ragged_buffer::RaggedBuffer;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rogue_net::{Entity, ObsSpace, RogueNetConfig};
+
+use crate::low_level::{ActionMask, ActionSpace, Observation, VecEnv};
+use policy::{ActionInput, Policy};
+
+/// Hyperparameters of the [`PpoTrainer`].
+#[derive(Debug, Clone)]
+pub struct PpoConfig {
+    /// Width of the entity embeddings.
+    pub d_model: u32,
+    /// Number of transformer blocks.
+    pub n_layer: u32,
+    /// Number of attention heads.
+    pub n_head: u32,
+    /// Width of keys and queries used in entity-selection heads.
+    pub d_qk: u32,
+    /// Learning rate of the Adam optimizer.
+    pub lr: f32,
+    /// Linearly anneals the learning rate to zero over the course of training.
+    pub anneal_lr: bool,
+    /// Number of steps collected from each environment per update.
+    pub steps: usize,
+    /// Number of minibatches that the samples of each update are split into.
+    pub minibatches: usize,
+    /// Number of passes over the samples of each update.
+    pub update_epochs: usize,
+    /// Discount factor.
+    pub gamma: f32,
+    /// Lambda parameter of generalized advantage estimation.
+    pub gae_lambda: f32,
+    /// Normalizes advantages within each minibatch.
+    pub norm_adv: bool,
+    /// Clipping range of the policy probability ratio.
+    pub clip_coef: f32,
+    /// Coefficient of the entropy bonus.
+    pub ent_coef: f32,
+    /// Coefficient of the value function loss.
+    pub vf_coef: f32,
+    /// Maximum global norm of the gradients.
+    pub max_grad_norm: f32,
+    /// Total number of environment steps to train for.
+    pub total_timesteps: u64,
+    /// Seed for parameter initialization, sampling and minibatch shuffling.
+    pub seed: u64,
+}
+
+impl Default for PpoConfig {
+    fn default() -> Self {
+        PpoConfig {
+            d_model: 32,
+            n_layer: 1,
+            n_head: 2,
+            d_qk: 16,
+            lr: 1e-3,
+            anneal_lr: true,
+            steps: 32,
+            minibatches: 4,
+            update_epochs: 3,
+            gamma: 0.99,
+            gae_lambda: 0.95,
+            norm_adv: true,
+            clip_coef: 0.2,
+            ent_coef: 0.01,
+            vf_coef: 0.5,
+            max_grad_norm: 0.5,
+            total_timesteps: 100_000,
+            seed: 0,
+        }
+    }
+}
+
+/// Statistics of a single [`PpoTrainer::update`].
+#[derive(Debug, Clone, Default)]
+pub struct TrainStats {
+    /// Total number of environment steps collected so far.
+    pub step: u64,
+    /// Number of episodes completed during the rollout.
+    pub episodes: usize,
+    /// Mean sum of rewards of the episodes completed during the rollout.
+    pub episodic_return: Option<f32>,
+    /// Mean clipped policy gradient loss.
+    pub policy_loss: f32,
+    /// Mean value function loss.
+    pub value_loss: f32,
+    /// Mean entropy of the action distributions.
+    pub entropy: f32,
+    /// Estimate of the KL divergence between the rollout policy and the updated policy.
+    pub approx_kl: f32,
+}
+
+/// Trains an entity neural network with [proximal policy optimization](https://arxiv.org/abs/1707.06347) on the CPU.
+///
+/// The network has the same architecture as [RogueNet](https://github.com/entity-neural-network/rogue-net) and
+/// [`PpoTrainer::save`] writes checkpoints that can be loaded with [`RogueNetAgent::load`](crate::agent::RogueNetAgent::load).
+///
+/// # Example
+/// ```rust
+/// use entity_gym_rs::agent::{Action, AgentOps, Featurizable, Obs, TrainAgent, TrainEnvBuilder};
+/// use entity_gym_rs::ppo::{PpoConfig, PpoTrainer};
+///
+/// #[derive(Featurizable)]
+/// struct Player { x: i32 }
+///
+/// #[derive(Action)]
+/// enum Move { Left, Right }
+///
+/// fn run(_: (), mut agent: TrainAgent, _seed: u64) {
+///     let mut x = 0;
+///     while let Some(moves) = agent.act::<Move>(&Obs::new(x as f32).actors([Player { x }])) {
+///         match moves[0] {
+///             Move::Left => x -= 1,
+///             Move::Right => x += 1,
+///         }
+///     }
+/// }
+///
+/// let env = TrainEnvBuilder::default()
+///     .entity::<Player>()
+///     .action::<Move>()
+///     .build_vec_env((), run, 4, 1, 0);
+/// let mut trainer = PpoTrainer::new(env, PpoConfig { total_timesteps: 256, ..Default::default() });
+/// trainer.train(|stats| println!("{:?}", stats));
+/// // trainer.save("checkpoint-dir")?;
+/// ```
+pub struct PpoTrainer {
+    env: VecEnv,
+    config: PpoConfig,
+    policy: Policy,
+    #[allow(clippy::vec_box)]
+    obs: Vec<Box<Observation>>,
+    rng: SmallRng,
+    step: u64,
+    updates: u32,
+    optimizer_steps: i32,
+    episodic_returns: Vec<f32>,
+}
+
+/// A single observation collected during a rollout.
+struct Sample {
+    features: Vec<Array2<f32>>,
+    actions: Vec<Option<ActionInput>>,
+    chosen: Vec<Vec<usize>>,
+    logprobs: Vec<Vec<f32>>,
+    value: f32,
+    advantage: f32,
+    ret: f32,
+}
+
+impl PpoTrainer {
+    /// Creates a new trainer with a randomly initialized network for the observation and action space of `env`.
+    pub fn new(mut env: VecEnv, config: PpoConfig) -> Self {
+        let mut rng = SmallRng::seed_from_u64(config.seed);
+        let obs_space = ObsSpace {
+            global_features: vec![],
+            entities: env
+                .obs_space
+                .entities
+                .iter()
+                .map(|(name, entity)| {
+                    (
+                        name.clone(),
+                        Entity {
+                            features: entity.features.clone(),
+                        },
+                    )
+                })
+                .collect(),
+        };
+        let action_space = env
+            .action_space
+            .iter()
+            .map(|(name, space)| {
+                let space = match space {
+                    ActionSpace::Categorical { choices } => {
+                        rogue_net::ActionSpace::CategoricalActionSpace {
+                            index_to_label: choices.clone(),
+                        }
+                    }
+                    ActionSpace::SelectEntity => rogue_net::ActionSpace::SelectEntityActionSpace,
+                };
+                (name.clone(), space)
+            })
+            .collect::<IndexMap<_, _>>();
+        let net = RogueNetConfig {
+            embd_pdrop: 0.0,
+            resid_pdrop: 0.0,
+            attn_pdrop: 0.0,
+            n_layer: config.n_layer,
+            n_head: config.n_head,
+            d_model: config.d_model,
+            pooling: None,
+            relpos_encoding: None,
+            d_qk: config.d_qk,
+            translation: None,
+        };
+        let policy = Policy::new(net, obs_space, action_space, &mut rng);
+        let obs = env.reset();
+        PpoTrainer {
+            episodic_returns: vec![0.0; env.num_envs],
+            env,
+            config,
+            policy,
+            obs,
+            rng,
+            step: 0,
+            updates: 0,
+            optimizer_steps: 0,
+        }
+    }
+
+    /// Runs [`PpoTrainer::update`] until `total_timesteps` environment steps have been collected.
+    ///
+    /// `on_update` is called with the statistics of every update.
+    pub fn train<F: FnMut(&TrainStats)>(&mut self, mut on_update: F) {
+        while self.step < self.config.total_timesteps {
+            let stats = self.update();
+            on_update(&stats);
+        }
+    }
+
+    /// Collects `steps` observations from each environment and performs one PPO update.
+    pub fn update(&mut self) -> TrainStats {
+        let num_updates = (self.config.total_timesteps as f32
+            / (self.config.steps * self.env.num_envs) as f32)
+            .ceil()
+            .max(1.0);
+        let lr = if self.config.anneal_lr {
+            self.config.lr * (1.0 - self.updates as f32 / num_updates).max(0.0)
+        } else {
+            self.config.lr
+        };
+        self.updates += 1;
+
+        let (mut samples, episodes, episodic_return) = self.rollout();
+        let mut stats = self.optimize(&mut samples, lr);
+        stats.step = self.step;
+        stats.episodes = episodes;
+        stats.episodic_return = episodic_return;
+        stats
+    }
+
+    /// Saves the network to a checkpoint directory that can be loaded with [`RogueNetAgent::load`](crate::agent::RogueNetAgent::load).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        self.policy.checkpoint(self.step as u32).save(path)
+    }
+
+    fn rollout(&mut self) -> (Vec<Sample>, usize, Option<f32>) {
+        let num_envs = self.env.num_envs;
+        let num_feats = self.env.num_feats.clone();
+        let mut samples: Vec<Vec<Sample>> = (0..num_envs).map(|_| vec![]).collect();
+        let mut rewards = vec![vec![]; num_envs];
+        let mut dones = vec![vec![]; num_envs];
+        let mut completed = vec![];
+        for _ in 0..self.config.steps {
+            let mut inputs = Vec::with_capacity(num_envs);
+            for obs in &self.obs {
+                let (features, actions) = policy_input(obs, &num_feats);
+                self.policy.update_input_norm(&features);
+                inputs.push((features, actions));
+            }
+            let mut ragged = vec![None; self.policy.action_space.len()];
+            for (env, (features, actions)) in inputs.into_iter().enumerate() {
+                let out = self.policy.forward(&features, &actions);
+                let mut chosen = vec![];
+                let mut logprobs = vec![];
+                for (index, logits) in out.logits.iter().enumerate() {
+                    let (acts, lps) = match logits {
+                        Some(logits) => sample(logits, &mut self.rng),
+                        None => (vec![], vec![]),
+                    };
+                    if actions[index].is_some() {
+                        let buffer = ragged[index].get_or_insert_with(|| RaggedBuffer::<i64> {
+                            data: vec![],
+                            subarrays: Vec::with_capacity(num_envs),
+                            features: 1,
+                            items: 0,
+                        });
+                        while buffer.subarrays.len() < env {
+                            buffer.subarrays.push(buffer.items..buffer.items);
+                        }
+                        buffer.data.extend(acts.iter().map(|&a| a as i64));
+                        buffer
+                            .subarrays
+                            .push(buffer.items..buffer.items + acts.len());
+                        buffer.items += acts.len();
+                    }
+                    chosen.push(acts);
+                    logprobs.push(lps);
+                }
+                samples[env].push(Sample {
+                    features,
+                    actions,
+                    chosen,
+                    logprobs,
+                    value: out.value,
+                    advantage: 0.0,
+                    ret: 0.0,
+                });
+            }
+            for buffer in ragged.iter_mut().flatten() {
+                while buffer.subarrays.len() < num_envs {
+                    buffer.subarrays.push(buffer.items..buffer.items);
+                }
+            }
+            self.obs = self.env.act(ragged);
+            self.step += num_envs as u64;
+            for (env, obs) in self.obs.iter().enumerate() {
+                rewards[env].push(obs.reward);
+                dones[env].push(obs.done);
+                self.episodic_returns[env] += obs.reward;
+                if obs.done {
+                    completed.push(self.episodic_returns[env]);
+                    self.episodic_returns[env] = 0.0;
+                }
+            }
+        }
+
+        // Generalized advantage estimation, bootstrapped from the value of the current observations.
+        for (env, samples) in samples.iter_mut().enumerate() {
+            let (features, actions) = policy_input(&self.obs[env], &num_feats);
+            let mut next_value = self.policy.forward(&features, &actions).value;
+            let mut advantage = 0.0;
+            for t in (0..samples.len()).rev() {
+                let nonterminal = if dones[env][t] { 0.0 } else { 1.0 };
+                let delta = rewards[env][t] + self.config.gamma * next_value * nonterminal
+                    - samples[t].value;
+                advantage =
+                    delta + self.config.gamma * self.config.gae_lambda * nonterminal * advantage;
+                samples[t].advantage = advantage;
+                samples[t].ret = advantage + samples[t].value;
+                next_value = samples[t].value;
+            }
+        }
+
+        let episodes = completed.len();
+        let episodic_return = if completed.is_empty() {
+            None
+        } else {
+            Some(completed.iter().sum::<f32>() / episodes as f32)
+        };
+        (
+            samples.into_iter().flatten().collect(),
+            episodes,
+            episodic_return,
+        )
+    }
+
+    fn optimize(&mut self, samples: &mut [Sample], lr: f32) -> TrainStats {
+        let config = self.config.clone();
+        let mut stats = TrainStats::default();
+        let mut num_minibatches = 0;
+        let minibatch_size = (samples.len() / config.minibatches.max(1)).max(1);
+        for _ in 0..config.update_epochs {
+            samples.shuffle(&mut self.rng);
+            for minibatch in samples.chunks(minibatch_size) {
+                for param in self.policy.params_mut() {
+                    param.zero_grad();
+                }
+                let (mean, std) = if config.norm_adv && minibatch.len() > 1 {
+                    let n = minibatch.len() as f32;
+                    let mean = minibatch.iter().map(|s| s.advantage).sum::<f32>() / n;
+                    let var = minibatch
+                        .iter()
+                        .map(|s| (s.advantage - mean).powi(2))
+                        .sum::<f32>()
+                        / (n - 1.0);
+                    (mean, var.sqrt() + 1e-8)
+                } else {
+                    (0.0, 1.0)
+                };
+                let num_actions = minibatch
+                    .iter()
+                    .flat_map(|s| &s.chosen)
+                    .map(|c| c.len())
+                    .sum::<usize>()
+                    .max(1) as f32;
+                let num_samples = minibatch.len() as f32;
+                for sample in minibatch {
+                    let out = self.policy.forward(&sample.features, &sample.actions);
+                    let advantage = (sample.advantage - mean) / std;
+                    let mut dlogits = Vec::with_capacity(out.logits.len());
+                    for (index, logits) in out.logits.iter().enumerate() {
+                        let logits = match logits {
+                            Some(logits) => logits,
+                            None => {
+                                dlogits.push(None);
+                                continue;
+                            }
+                        };
+                        let probs = layers::softmax(logits);
+                        let mut dl = Array2::zeros(logits.dim());
+                        for (actor, (&action, &old_logprob)) in sample.chosen[index]
+                            .iter()
+                            .zip(&sample.logprobs[index])
+                            .enumerate()
+                        {
+                            let p = probs.row(actor);
+                            let logprob = p[action].ln();
+                            let ratio = (logprob - old_logprob).exp();
+                            let clipped =
+                                ratio.clamp(1.0 - config.clip_coef, 1.0 + config.clip_coef);
+                            let unclipped_loss = -advantage * ratio;
+                            let clipped_loss = -advantage * clipped;
+                            // Gradient of the loss with respect to the log probability of the chosen action.
+                            let dlogprob = if unclipped_loss >= clipped_loss {
+                                stats.policy_loss += unclipped_loss;
+                                -advantage * ratio
+                            } else {
+                                stats.policy_loss += clipped_loss;
+                                0.0
+                            };
+                            stats.approx_kl += (ratio - 1.0) - (logprob - old_logprob);
+                            let entropy = -p
+                                .iter()
+                                .filter(|&&p| p > 0.0)
+                                .map(|&p| p * p.ln())
+                                .sum::<f32>();
+                            stats.entropy += entropy;
+                            for (choice, &p) in p.iter().enumerate() {
+                                if p == 0.0 {
+                                    continue;
+                                }
+                                let onehot = if choice == action { 1.0 } else { 0.0 };
+                                let dentropy = -p * (p.ln() + entropy);
+                                dl[[actor, choice]] = (dlogprob * (onehot - p)
+                                    - config.ent_coef * dentropy)
+                                    / num_actions;
+                            }
+                        }
+                        dlogits.push(Some(dl));
+                    }
+                    let dvalue = config.vf_coef * (out.value - sample.ret) / num_samples;
+                    stats.value_loss += 0.5 * (out.value - sample.ret).powi(2) / num_samples;
+                    self.policy
+                        .backward(&out.cache, &sample.actions, &dlogits, dvalue);
+                }
+                let mut params = self.policy.params_mut();
+                let norm = params
+                    .iter()
+                    .map(|p| p.grad.iter().map(|g| g * g).sum::<f32>())
+                    .sum::<f32>()
+                    .sqrt();
+                if norm > config.max_grad_norm {
+                    for param in params.iter_mut() {
+                        param.grad *= config.max_grad_norm / norm;
+                    }
+                }
+                self.optimizer_steps += 1;
+                for param in params {
+                    param.adam_step(lr, self.optimizer_steps);
+                }
+                num_minibatches += 1;
+            }
+        }
+        let num_actions = samples
+            .iter()
+            .map(|s| s.chosen.iter().map(|c| c.len()).sum::<usize>())
+            .sum::<usize>()
+            .max(1) as f32
+            * config.update_epochs as f32;
+        stats.policy_loss /= num_actions;
+        stats.entropy /= num_actions;
+        stats.approx_kl /= num_actions;
+        stats.value_loss /= num_minibatches.max(1) as f32;
+        stats
+    }
+}
+
+/// Splits an observation into the features of each entity type and the actors of each action type.
+fn policy_input(
+    obs: &Observation,
+    num_feats: &[usize],
+) -> (Vec<Array2<f32>>, Vec<Option<ActionInput>>) {
+    let mut features = Vec::with_capacity(num_feats.len());
+    let mut offset = 0;
+    for (&count, &num_feats) in obs.features.counts.iter().zip(num_feats) {
+        let len = count * num_feats;
+        features.push(
+            Array2::from_shape_vec(
+                (count, num_feats),
+                obs.features.data[offset..offset + len].to_vec(),
+            )
+            .unwrap(),
+        );
+        offset += len;
+    }
+    let actions = obs
+        .actions
+        .iter()
+        .map(|mask| {
+            mask.as_ref().map(|mask| match mask {
+                ActionMask::DenseCategorical { actors, mask } => {
+                    let actors = actors.iter().map(|&a| a as usize).collect::<Vec<_>>();
+                    let mask = mask.as_ref().map(|mask| {
+                        let choices = mask.len() / actors.len().max(1);
+                        Array2::from_shape_vec((actors.len(), choices), mask.clone()).unwrap()
+                    });
+                    ActionInput {
+                        actors,
+                        actees: vec![],
+                        mask,
+                    }
+                }
+                ActionMask::SelectEntity { actors, actees } => ActionInput {
+                    actors: actors.iter().map(|&a| a as usize).collect(),
+                    actees: actees.iter().map(|&a| a as usize).collect(),
+                    mask: None,
+                },
+            })
+        })
+        .collect();
+    (features, actions)
+}
+
+/// Samples one choice for each row of logits and returns the choices together with their log probabilities.
+fn sample(logits: &Array2<f32>, rng: &mut SmallRng) -> (Vec<usize>, Vec<f32>) {
+    let probs = layers::softmax(logits);
+    let mut choices = Vec::with_capacity(probs.dim().0);
+    let mut logprobs = Vec::with_capacity(probs.dim().0);
+    for row in probs.outer_iter() {
+        if row.is_empty() {
+            continue;
+        }
+        let mut r = rng.gen::<f32>();
+        let mut choice = 0;
+        for (i, &p) in row.iter().enumerate() {
+            // Guards against rounding errors that would otherwise select a masked choice.
+            if p > 0.0 {
+                choice = i;
+            }
+            r -= p;
+            if r <= 0.0 {
+                break;
+            }
+        }
+        choices.push(choice);
+        logprobs.push(row[choice].ln());
+    }
+    (choices, logprobs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{
+        Action, Agent, AgentOps, Featurizable, Obs, RogueNetAgent, TrainAgent, TrainEnvBuilder,
+    };
+
+    #[derive(Featurizable)]
+    struct Player {
+        target: i32,
+    }
+
+    #[derive(Action, Debug, PartialEq)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    // Rewards moves that match the target of the player, for episodes of 8 steps.
+    fn run_guess(_: (), mut agent: TrainAgent, seed: u64) {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut score = 0.0;
+        let mut step = 0;
+        loop {
+            let target = rng.gen_range(0..2);
+            let obs = Obs::new(score).actors([Player { target }]);
+            let moves = match agent.act::<Move>(&obs) {
+                Some(moves) => moves,
+                None => break,
+            };
+            if moves[0] == Move::from_u64(target as u64) {
+                score += 1.0;
+            }
+            step += 1;
+            if step == 8 {
+                agent.game_over(&Obs::new(score));
+                score = 0.0;
+                step = 0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_train() {
+        let env = TrainEnvBuilder::default()
+            .entity::<Player>()
+            .action::<Move>()
+            .build_vec_env((), run_guess, 8, 2, 0);
+        let mut trainer = PpoTrainer::new(
+            env,
+            PpoConfig {
+                d_model: 16,
+                steps: 16,
+                lr: 3e-3,
+                ent_coef: 0.0,
+                total_timesteps: 2048,
+                ..Default::default()
+            },
+        );
+        let mut returns = vec![];
+        trainer.train(|stats| returns.extend(stats.episodic_return));
+        let last = returns[returns.len() - 3..].iter().sum::<f32>() / 3.0;
+        assert!(last > 7.0, "episodic returns: {:?}", returns);
+
+        let path = std::env::temp_dir().join(format!("ppo-trainer-{}", std::process::id()));
+        trainer.save(&path).unwrap();
+        let mut agent = RogueNetAgent::load(&path).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+        for target in 0..2 {
+            let obs = Obs::new(0.0).actors([Player { target }]);
+            assert_eq!(agent.act::<Move>(&obs).unwrap().len(), 1);
+            let (probs, _) = agent.net.forward(agent.fwd_args(&obs));
+            assert!(
+                probs[[0, target as usize]] > 0.9,
+                "target {}: {:?}",
+                target,
+                probs
+            );
+        }
+    }
+}
@@ -0,0 +1,661 @@
+use indexmap::IndexMap;
+use ndarray::{concatenate, Array2, ArrayView2, Axis};
+use rand::rngs::SmallRng;
+use rogue_net::{ActionSpace, Checkpoint, Entity, ObsSpace, RogueNetConfig};
+
+use super::layers::{
+    gelu, gelu_backward, Attention, AttentionCache, LayerNorm, LayerNormCache, Linear, Param,
+};
+use crate::agent::RunningMeanStd;
+
+/// Entity transformer with the same architecture and parameter names as [`rogue_net::RogueNet`].
+#[derive(Debug, Clone)]
+pub(crate) struct Policy {
+    pub config: RogueNetConfig,
+    pub obs_space: ObsSpace,
+    pub action_space: IndexMap<String, ActionSpace>,
+    embeddings: Vec<Embedding>,
+    blocks: Vec<Block>,
+    heads: Vec<Head>,
+    value: Linear,
+}
+
+#[derive(Debug, Clone)]
+struct Embedding {
+    norm: RunningMeanStd,
+    proj: Linear,
+    ln: LayerNorm,
+}
+
+struct EmbeddingCache {
+    x: Array2<f32>,
+    h: Array2<f32>,
+    ln: LayerNormCache,
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    ln1: LayerNorm,
+    attn: Attention,
+    ln2: LayerNorm,
+    mlp1: Linear,
+    mlp2: Linear,
+}
+
+struct BlockCache {
+    ln1: LayerNormCache,
+    attn: AttentionCache,
+    ln2: LayerNormCache,
+    mlp_in: Array2<f32>,
+    h: Array2<f32>,
+    g: Array2<f32>,
+}
+
+#[derive(Debug, Clone)]
+enum Head {
+    Categorical { proj: Linear },
+    SelectEntity { query: Linear, key: Box<Linear> },
+}
+
+/// Entities that act and can be selected for one type of action in a single observation.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ActionInput {
+    /// Positions of the actors in the concatenation of all entities.
+    pub actors: Vec<usize>,
+    /// Positions of the entities that can be selected by select entity actions.
+    pub actees: Vec<usize>,
+    /// Allowed choices of each actor for categorical actions.
+    pub mask: Option<Array2<bool>>,
+}
+
+/// Intermediate values of a forward pass that are required to compute gradients.
+pub(crate) struct Cache {
+    embeddings: Vec<EmbeddingCache>,
+    blocks: Vec<BlockCache>,
+    x: Array2<f32>,
+    heads: Vec<Option<(Array2<f32>, Array2<f32>)>>,
+    pooled: Array2<f32>,
+}
+
+/// Outputs of a forward pass on a single observation.
+pub(crate) struct Output {
+    /// Logits for each action type that was requested, of shape `(actors, choices)`. Disallowed choices are `-inf`.
+    pub logits: Vec<Option<Array2<f32>>>,
+    pub value: f32,
+    pub cache: Cache,
+}
+
+impl Policy {
+    pub fn new(
+        config: RogueNetConfig,
+        obs_space: ObsSpace,
+        action_space: IndexMap<String, ActionSpace>,
+        rng: &mut SmallRng,
+    ) -> Self {
+        let d_model = config.d_model as usize;
+        let d_qk = config.d_qk as usize;
+        assert!(
+            d_model % config.n_head as usize == 0,
+            "d_model must be divisible by n_head"
+        );
+        let embeddings = obs_space
+            .entities
+            .values()
+            .map(|Entity { features }| Embedding {
+                norm: RunningMeanStd::new(features.len()),
+                proj: Linear::new(features.len(), d_model, 1.0, rng),
+                ln: LayerNorm::new(d_model),
+            })
+            .collect();
+        let blocks = (0..config.n_layer)
+            .map(|_| Block {
+                ln1: LayerNorm::new(d_model),
+                attn: Attention::new(d_model, config.n_head as usize, rng),
+                ln2: LayerNorm::new(d_model),
+                mlp1: Linear::new(d_model, 4 * d_model, 1.0, rng),
+                mlp2: Linear::new(4 * d_model, d_model, 1.0, rng),
+            })
+            .collect();
+        // Small initial weights keep the initial policy close to uniform.
+        let heads = action_space
+            .values()
+            .map(|space| match space {
+                ActionSpace::CategoricalActionSpace { index_to_label } => Head::Categorical {
+                    proj: Linear::new(d_model, index_to_label.len(), 0.01, rng),
+                },
+                ActionSpace::SelectEntityActionSpace => Head::SelectEntity {
+                    query: Linear::new(d_model, d_qk, 0.01, rng),
+                    key: Box::new(Linear::new(d_model, d_qk, 1.0, rng)),
+                },
+            })
+            .collect();
+        Policy {
+            value: Linear::new(d_model, 1, 1.0, rng),
+            config,
+            obs_space,
+            action_space,
+            embeddings,
+            blocks,
+            heads,
+        }
+    }
+
+    /// Updates the running statistics used to normalize the features of each entity type.
+    pub fn update_input_norm(&mut self, features: &[Array2<f32>]) {
+        for (embedding, features) in self.embeddings.iter_mut().zip(features) {
+            embedding
+                .norm
+                .update(features.as_slice().expect("features must be contiguous"));
+        }
+    }
+
+    /// Runs the network on a single observation.
+    ///
+    /// `features` contains the features of each entity type in the order of the observation space, and `actions`
+    /// the actors of each action type in the order of the action space.
+    pub fn forward(&self, features: &[Array2<f32>], actions: &[Option<ActionInput>]) -> Output {
+        let d_model = self.config.d_model as usize;
+        let mut embeddings = Vec::with_capacity(self.embeddings.len());
+        let mut xs = Vec::with_capacity(self.embeddings.len());
+        for (embedding, features) in self.embeddings.iter().zip(features) {
+            let (y, cache) = embedding.forward(features.view());
+            xs.push(y);
+            embeddings.push(cache);
+        }
+        let mut x = concatenate(Axis(0), &xs.iter().map(|x| x.view()).collect::<Vec<_>>())
+            .unwrap_or_else(|_| Array2::zeros((0, d_model)));
+
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+        for block in &self.blocks {
+            let (y, cache) = block.forward(x.view());
+            x = y;
+            blocks.push(cache);
+        }
+
+        let mut logits = Vec::with_capacity(self.heads.len());
+        let mut heads = Vec::with_capacity(self.heads.len());
+        for (head, action) in self.heads.iter().zip(actions) {
+            let action = match action {
+                Some(action) => action,
+                None => {
+                    logits.push(None);
+                    heads.push(None);
+                    continue;
+                }
+            };
+            let actors = x.select(Axis(0), &action.actors);
+            match head {
+                Head::Categorical { proj } => {
+                    let mut l = proj.forward(actors.view());
+                    if let Some(mask) = &action.mask {
+                        l.zip_mut_with(mask, |l, &allowed| {
+                            if !allowed {
+                                *l = f32::NEG_INFINITY;
+                            }
+                        });
+                    }
+                    logits.push(Some(l));
+                    heads.push(Some((actors, Array2::zeros((0, 0)))));
+                }
+                Head::SelectEntity { query, key } => {
+                    let actees = x.select(Axis(0), &action.actees);
+                    let q = query.forward(actors.view());
+                    let k = key.forward(actees.view());
+                    let scale = 1.0 / (self.config.d_qk as f32).sqrt();
+                    logits.push(Some(q.dot(&k.t()) * scale));
+                    heads.push(Some((actors, actees)));
+                }
+            }
+        }
+
+        let pooled = if x.dim().0 == 0 {
+            Array2::zeros((1, d_model))
+        } else {
+            x.mean_axis(Axis(0)).unwrap().insert_axis(Axis(0))
+        };
+        let value = self.value.forward(pooled.view())[[0, 0]];
+
+        Output {
+            logits,
+            value,
+            cache: Cache {
+                embeddings,
+                blocks,
+                x,
+                heads,
+                pooled,
+            },
+        }
+    }
+
+    /// Accumulates the gradients of all parameters given the gradients of the logits and value of a forward pass.
+    pub fn backward(
+        &mut self,
+        cache: &Cache,
+        actions: &[Option<ActionInput>],
+        dlogits: &[Option<Array2<f32>>],
+        dvalue: f32,
+    ) {
+        let scale = 1.0 / (self.config.d_qk as f32).sqrt();
+        let mut dx = Array2::<f32>::zeros(cache.x.dim());
+        let n = cache.x.dim().0;
+        if n > 0 {
+            let dvalue = Array2::from_elem((1, 1), dvalue);
+            let dpooled = self.value.backward(cache.pooled.view(), dvalue.view());
+            dx += &(dpooled / n as f32);
+        }
+
+        for (((head, action), dlogits), head_cache) in self
+            .heads
+            .iter_mut()
+            .zip(actions)
+            .zip(dlogits)
+            .zip(&cache.heads)
+        {
+            let (action, dlogits, (actors, actees)) = match (action, dlogits, head_cache) {
+                (Some(a), Some(d), Some(c)) => (a, d, c),
+                _ => continue,
+            };
+            match head {
+                Head::Categorical { proj } => {
+                    let dactors = proj.backward(actors.view(), dlogits.view());
+                    scatter_add(&mut dx, &action.actors, &dactors);
+                }
+                Head::SelectEntity { query, key } => {
+                    let q = query.forward(actors.view());
+                    let k = key.forward(actees.view());
+                    let dq = dlogits.dot(&k) * scale;
+                    let dk = dlogits.t().dot(&q) * scale;
+                    let dactors = query.backward(actors.view(), dq.view());
+                    let dactees = key.backward(actees.view(), dk.view());
+                    scatter_add(&mut dx, &action.actors, &dactors);
+                    scatter_add(&mut dx, &action.actees, &dactees);
+                }
+            }
+        }
+
+        for (block, block_cache) in self.blocks.iter_mut().zip(&cache.blocks).rev() {
+            dx = block.backward(block_cache, dx.view());
+        }
+
+        let mut offset = 0;
+        for (embedding, embedding_cache) in self.embeddings.iter_mut().zip(&cache.embeddings) {
+            let count = embedding_cache.h.dim().0;
+            embedding.backward(
+                embedding_cache,
+                dx.slice(ndarray::s![offset..offset + count, ..]),
+            );
+            offset += count;
+        }
+    }
+
+    pub fn params_mut(&mut self) -> Vec<&mut Param> {
+        let mut params = vec![];
+        for embedding in &mut self.embeddings {
+            params.extend(embedding.proj.params_mut());
+            params.extend(embedding.ln.params_mut());
+        }
+        for block in &mut self.blocks {
+            params.extend(block.ln1.params_mut());
+            params.extend(block.attn.params_mut());
+            params.extend(block.ln2.params_mut());
+            params.extend(block.mlp1.params_mut());
+            params.extend(block.mlp2.params_mut());
+        }
+        for head in &mut self.heads {
+            match head {
+                Head::Categorical { proj } => params.extend(proj.params_mut()),
+                Head::SelectEntity { query, key } => {
+                    params.extend(query.params_mut());
+                    params.extend(key.params_mut());
+                }
+            }
+        }
+        params.extend(self.value.params_mut());
+        params
+    }
+
+    /// Converts the network into a checkpoint that can be loaded by [`rogue_net::RogueNet::load`].
+    pub fn checkpoint(&self, step: u32) -> Checkpoint {
+        let mut tensors = IndexMap::new();
+        let mut insert_all = |prefix: String, names: &[&str], values: &[(Vec<usize>, Vec<f32>)]| {
+            for (name, value) in names.iter().zip(values) {
+                tensors.insert(format!("{}.{}", prefix, name), value.clone());
+            }
+        };
+        for (name, embedding) in self.obs_space.entities.keys().zip(&self.embeddings) {
+            let prefix = format!("embedding.embeddings.{}", name);
+            let norm = &embedding.norm;
+            // rogue-net divides by `count - 1`, so the count is clamped to keep the standard deviation defined.
+            insert_all(
+                format!("{}.0", prefix),
+                &["count", "mean", "squares_sum"],
+                &[
+                    (vec![], vec![norm.count.max(2.0) as f32]),
+                    (
+                        vec![norm.mean.len()],
+                        norm.mean.iter().map(|&x| x as f32).collect(),
+                    ),
+                    (
+                        vec![norm.m2.len()],
+                        norm.m2.iter().map(|&x| x as f32).collect(),
+                    ),
+                ],
+            );
+            insert_all(
+                format!("{}.1", prefix),
+                &["weight", "bias"],
+                &embedding.proj.tensors(),
+            );
+            insert_all(
+                format!("{}.3", prefix),
+                &["weight", "bias"],
+                &embedding.ln.tensors(),
+            );
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            let prefix = format!("backbone.blocks.{}", i);
+            let wb = &["weight", "bias"];
+            insert_all(format!("{}.ln1", prefix), wb, &block.ln1.tensors());
+            insert_all(format!("{}.ln2", prefix), wb, &block.ln2.tensors());
+            insert_all(
+                format!("{}.attn.key", prefix),
+                wb,
+                &block.attn.key.tensors(),
+            );
+            insert_all(
+                format!("{}.attn.query", prefix),
+                wb,
+                &block.attn.query.tensors(),
+            );
+            insert_all(
+                format!("{}.attn.value", prefix),
+                wb,
+                &block.attn.value.tensors(),
+            );
+            insert_all(
+                format!("{}.attn.proj", prefix),
+                wb,
+                &block.attn.proj.tensors(),
+            );
+            insert_all(format!("{}.mlp.0", prefix), wb, &block.mlp1.tensors());
+            insert_all(format!("{}.mlp.2", prefix), wb, &block.mlp2.tensors());
+        }
+        for (name, head) in self.action_space.keys().zip(&self.heads) {
+            let prefix = format!("action_heads.{}", name);
+            let wb = &["weight", "bias"];
+            match head {
+                Head::Categorical { proj } => {
+                    insert_all(format!("{}.proj", prefix), wb, &proj.tensors())
+                }
+                Head::SelectEntity { query, key } => {
+                    insert_all(format!("{}.query_proj", prefix), wb, &query.tensors());
+                    insert_all(format!("{}.key_proj", prefix), wb, &key.tensors());
+                }
+            }
+        }
+        insert_all(
+            "auxiliary_heads.value".to_string(),
+            &["weight", "bias"],
+            &self.value.tensors(),
+        );
+        Checkpoint {
+            config: self.config.clone(),
+            obs_space: self.obs_space.clone(),
+            action_space: self.action_space.clone(),
+            step,
+            tensors,
+        }
+    }
+}
+
+impl Embedding {
+    fn forward(&self, features: ArrayView2<f32>) -> (Array2<f32>, EmbeddingCache) {
+        let std = self.norm.std();
+        let mut x = features.to_owned();
+        for mut row in x.outer_iter_mut() {
+            for (i, x) in row.iter_mut().enumerate() {
+                *x = ((*x - self.norm.mean[i] as f32) / std[i] as f32).clamp(-5.0, 5.0);
+            }
+        }
+        let h = self.proj.forward(x.view());
+        let (y, ln) = self.ln.forward(h.mapv(|h| h.max(0.0)).view());
+        (y, EmbeddingCache { x, h, ln })
+    }
+
+    fn backward(&mut self, cache: &EmbeddingCache, dy: ArrayView2<f32>) {
+        let mut dh = self.ln.backward(&cache.ln, dy);
+        dh.zip_mut_with(&cache.h, |dh, &h| {
+            if h <= 0.0 {
+                *dh = 0.0;
+            }
+        });
+        self.proj.backward(cache.x.view(), dh.view());
+    }
+}
+
+impl Block {
+    fn forward(&self, x: ArrayView2<f32>) -> (Array2<f32>, BlockCache) {
+        let (a, ln1) = self.ln1.forward(x);
+        let (a, attn) = self.attn.forward(a.view());
+        let x = &x + &a;
+        let (mlp_in, ln2) = self.ln2.forward(x.view());
+        let h = self.mlp1.forward(mlp_in.view());
+        let g = gelu(h.view());
+        let y = &x + &self.mlp2.forward(g.view());
+        (
+            y,
+            BlockCache {
+                ln1,
+                attn,
+                ln2,
+                mlp_in,
+                h,
+                g,
+            },
+        )
+    }
+
+    fn backward(&mut self, cache: &BlockCache, dy: ArrayView2<f32>) -> Array2<f32> {
+        let dg = self.mlp2.backward(cache.g.view(), dy);
+        let dh = gelu_backward(cache.h.view(), dg.view());
+        let dmlp_in = self.mlp1.backward(cache.mlp_in.view(), dh.view());
+        let dx = &dy + &self.ln2.backward(&cache.ln2, dmlp_in.view());
+        let da = self.attn.backward(&cache.attn, dx.view());
+        &dx + &self.ln1.backward(&cache.ln1, da.view())
+    }
+}
+
+fn scatter_add(dx: &mut Array2<f32>, indices: &[usize], values: &Array2<f32>) {
+    for (&i, row) in indices.iter().zip(values.outer_iter()) {
+        let mut target = dx.row_mut(i);
+        target += &row;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use ndarray::array;
+    use rand::{Rng, SeedableRng};
+    use rogue_net::{FwdArgs, RogueNet};
+
+    use super::*;
+    use crate::ppo::layers::softmax;
+
+    fn policy() -> Policy {
+        let config = RogueNetConfig {
+            embd_pdrop: 0.0,
+            resid_pdrop: 0.0,
+            attn_pdrop: 0.0,
+            n_layer: 2,
+            n_head: 2,
+            d_model: 8,
+            pooling: None,
+            relpos_encoding: None,
+            d_qk: 4,
+            translation: None,
+        };
+        let mut entities = IndexMap::new();
+        for (name, features) in [("Unit", vec!["x", "y"]), ("Enemy", vec!["hp"])] {
+            entities.insert(
+                name.to_string(),
+                Entity {
+                    features: features.into_iter().map(String::from).collect(),
+                },
+            );
+        }
+        let mut action_space = IndexMap::new();
+        action_space.insert(
+            "Move".to_string(),
+            ActionSpace::CategoricalActionSpace {
+                index_to_label: vec!["Left".to_string(), "Right".to_string(), "Stay".to_string()],
+            },
+        );
+        action_space.insert("Attack".to_string(), ActionSpace::SelectEntityActionSpace);
+        let mut policy = Policy::new(
+            config,
+            ObsSpace {
+                global_features: vec![],
+                entities,
+            },
+            action_space,
+            &mut SmallRng::seed_from_u64(0),
+        );
+        // Larger head weights make the gradients of the heads easier to check.
+        for head in &mut policy.heads {
+            match head {
+                Head::Categorical { proj } => proj.weight.value *= 100.0,
+                Head::SelectEntity { query, .. } => query.weight.value *= 100.0,
+            }
+        }
+        policy.update_input_norm(&features());
+        policy
+    }
+
+    fn features() -> Vec<Array2<f32>> {
+        vec![array![[1.0, 2.0], [3.0, -1.0]], array![[5.0], [2.0], [7.0]]]
+    }
+
+    fn actions() -> Vec<Option<ActionInput>> {
+        vec![
+            Some(ActionInput {
+                actors: vec![0, 1],
+                actees: vec![],
+                mask: Some(array![[true, true, true], [true, false, true]]),
+            }),
+            Some(ActionInput {
+                actors: vec![1],
+                actees: vec![2, 3, 4],
+                mask: None,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_gradients() {
+        let mut policy = policy();
+        let features = features();
+        let actions = actions();
+        let mut rng = SmallRng::seed_from_u64(1);
+        // Random linear function of the finite outputs.
+        let out = policy.forward(&features, &actions);
+        let coefs = out
+            .logits
+            .iter()
+            .map(|l| {
+                l.as_ref().map(|l| {
+                    l.mapv(|l| {
+                        if l.is_finite() {
+                            rng.gen_range(-1.0..1.0)
+                        } else {
+                            0.0
+                        }
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        let loss = |policy: &Policy| {
+            let out = policy.forward(&features, &actions);
+            let mut loss = 0.5 * out.value as f64;
+            for (l, c) in out.logits.iter().zip(&coefs) {
+                let (l, c) = (l.as_ref().unwrap(), c.as_ref().unwrap());
+                let l = l.mapv(|l| if l.is_finite() { l } else { 0.0 });
+                loss += (&l * c).sum() as f64;
+            }
+            loss
+        };
+        for param in policy.params_mut() {
+            param.zero_grad();
+        }
+        policy.backward(&out.cache, &actions, &coefs, 0.5);
+
+        let num_params = policy.params_mut().len();
+        for p in 0..num_params {
+            let len = policy.params_mut()[p].value.len();
+            for _ in 0..3 {
+                let i = rng.gen_range(0..len);
+                let analytic = policy.params_mut()[p].grad.as_slice().unwrap()[i] as f64;
+                let eps = 1e-2;
+                policy.params_mut()[p].value.as_slice_mut().unwrap()[i] += eps;
+                let plus = loss(&policy);
+                policy.params_mut()[p].value.as_slice_mut().unwrap()[i] -= 2.0 * eps;
+                let minus = loss(&policy);
+                policy.params_mut()[p].value.as_slice_mut().unwrap()[i] += eps;
+                let numeric = (plus - minus) / (2.0 * eps as f64);
+                assert!(
+                    (analytic - numeric).abs() <= 2e-2 * (1.0 + numeric.abs()),
+                    "param {} index {}: analytic gradient {} != numeric gradient {}",
+                    p,
+                    i,
+                    analytic,
+                    numeric,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let policy = policy();
+        let path = std::env::temp_dir().join(format!("ppo-policy-{}", std::process::id()));
+        policy.checkpoint(0).save(&path).unwrap();
+        let net = RogueNet::load(&path);
+        std::fs::remove_dir_all(&path).unwrap();
+
+        let features = features();
+        let actions = actions();
+        let out = policy.forward(&features, &actions);
+        let mut args = FwdArgs {
+            features: ["Unit", "Enemy"]
+                .iter()
+                .map(|n| n.to_string())
+                .zip(features)
+                .collect::<HashMap<_, _>>(),
+            actors: vec!["Unit".to_string()],
+            ..Default::default()
+        };
+        args.action_masks.insert(
+            "Move".to_string(),
+            actions[0].as_ref().unwrap().mask.clone().unwrap(),
+        );
+        args.action_actors
+            .insert("Attack".to_string(), vec!["Unit".to_string()]);
+        args.actees
+            .insert("Attack".to_string(), vec!["Enemy".to_string()]);
+        let output = net
+            .forward_batch(vec![(args, vec!["Move", "Attack"])])
+            .remove(0);
+        assert!((output.value.unwrap() - out.value).abs() < 1e-4);
+        let probs = output.actions;
+        let expected_move = softmax(out.logits[0].as_ref().unwrap());
+        let expected_attack = softmax(out.logits[1].as_ref().unwrap());
+        assert!(probs[0].0.abs_diff_eq(&expected_move, 1e-4));
+        // rogue-net computes the attack probabilities for both units.
+        assert!(probs[1]
+            .0
+            .slice(ndarray::s![1..2, ..])
+            .abs_diff_eq(&expected_attack, 1e-4));
+    }
+}